@@ -26,8 +26,12 @@ impl Timestamp {
 
     /// Returns the current UTC system time with millisecond precision.
     pub fn utc_now() -> Self {
+        Self::from_utc(&chrono::Utc::now())
+    }
+
+    /// Converts `utc` to a [`Timestamp`] with millisecond precision.
+    pub(crate) fn from_utc(utc: &chrono::DateTime<chrono::Utc>) -> Self {
         use chrono::{Datelike, Timelike};
-        let utc: chrono::DateTime<chrono::Utc> = chrono::Utc::now();
         let date = Date::new(utc.year() as u32, utc.month() as u32, utc.day() as u32);
         let time = Time::from_hmsm(
             utc.hour() as u32,
@@ -1,4 +1,7 @@
-use super::{Environment, MsgSeqNumCounter, SeqNumbers};
+use super::{
+    Backoff, Environment, HeartbeatRule, MsgSeqNumCounter, Schedule, SeqNumbers, SessionMode,
+    Throttle,
+};
 use std::marker::PhantomData;
 use std::num::NonZeroU64;
 use std::time::Duration;
@@ -43,6 +46,107 @@ pub trait Configure: Clone + Default {
     fn heartbeat(&self) -> Duration {
         Duration::from_secs(30)
     }
+
+    /// Returns after how many `HeartBtInt <108>` intervals without any inbound
+    /// message a `TestRequest <1>` is sent to the counterparty. Two by
+    /// default.
+    fn heartbeat_soft_tolerance(&self) -> u32 {
+        2
+    }
+
+    /// Returns after how many `HeartBtInt <108>` intervals without any inbound
+    /// message the counterparty is considered dead and the transport is
+    /// closed. Three by default.
+    fn heartbeat_hard_tolerance(&self) -> u32 {
+        3
+    }
+
+    /// Returns the rules that `HeartBtInt <108>` values proposed by initiators
+    /// must satisfy. Only relevant for acceptors. [`HeartbeatRule::Any`] by
+    /// default.
+    fn heartbeat_rule(&self) -> HeartbeatRule {
+        HeartbeatRule::Any
+    }
+
+    /// Returns how long to wait for the counterparty to confirm our
+    /// `Logout <5>` before closing the transport. Ten seconds by default.
+    fn logout_timeout(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// Returns the trading windows of the FIX session, if any. Outside of its
+    /// windows, the FIX session is logged out; at the beginning of each
+    /// window, seq. numbers are reset. [`None`] by default, i.e. the FIX
+    /// session never ends.
+    fn schedule(&self) -> Option<Schedule> {
+        None
+    }
+
+    /// Asks to reset seq. numbers at every logon via `ResetSeqNumFlag <141>`.
+    /// Initiators send `ResetSeqNumFlag <141>=Y` in their `Logon <A>`;
+    /// acceptors reset upon receiving any `Logon <A>`. Resets requested by
+    /// the counterparty are always honored. `false` by default.
+    fn reset_on_logon(&self) -> bool {
+        false
+    }
+
+    /// Asks to send `NextExpectedMsgSeqNum <789>` in `Logon <A>` messages,
+    /// and to resend whatever the counterparty's `NextExpectedMsgSeqNum
+    /// <789>` says it's missing. FIX 4.4 and later only. `false` by default.
+    fn enable_next_expected_msg_seq_num(&self) -> bool {
+        false
+    }
+
+    /// Returns what the FIX session is used for, e.g. to receive a drop-copy
+    /// feed. [`SessionMode::Normal`] by default.
+    fn session_mode(&self) -> SessionMode {
+        SessionMode::Normal
+    }
+
+    /// Asks the counterparty to replay all of its messages from `MsgSeqNum
+    /// <34>=1` as soon as the FIX session is logged on, via a `ResendRequest
+    /// <2>`. Replayed messages are delivered to the
+    /// [`Backend`](super::Backend) once again, in order, before any new ones.
+    /// `false` by default.
+    fn replay_on_logon(&self) -> bool {
+        false
+    }
+
+    /// Returns the `DefaultApplVerID <1137>` to send in `Logon <A>` messages
+    /// of FIXT.1.1 sessions, e.g. `9` for FIX 5.0 SP2. Inbound application
+    /// messages without `ApplVerID <1128>` are decoded according to the
+    /// counterparty's `DefaultApplVerID <1137>`, or this one if it has none.
+    /// [`None`] by default.
+    fn default_appl_ver_id(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Returns the outbound rate limit of application messages, if any.
+    /// [`None`] by default.
+    fn throttle(&self) -> Option<Throttle> {
+        None
+    }
+
+    /// Returns how many application messages the queue of
+    /// [`FixConnection::handle`](super::FixConnection::handle) can hold
+    /// before applying backpressure. 1024 by default.
+    fn send_queue_capacity(&self) -> usize {
+        1024
+    }
+
+    /// Returns how many out-of-order inbound messages can be queued while
+    /// waiting for a gap to be filled. Past that, they're dropped and the
+    /// counterparty is asked to resend them later on. 10000 by default.
+    fn inbound_queue_capacity(&self) -> usize {
+        10_000
+    }
+
+    /// Returns the delays between reconnection attempts of
+    /// [`FixConnection::start_with_reconnect`](super::FixConnection::start_with_reconnect).
+    /// [`Backoff::default`] by default.
+    fn backoff(&self) -> Backoff {
+        Backoff::default()
+    }
 }
 
 /// The canonical implementor of [`Configure`]. Every setting can be changed.
@@ -57,6 +161,20 @@ pub struct Config {
     pub begin_string: String,
     pub environment: Environment,
     pub heartbeat: Duration,
+    pub heartbeat_soft_tolerance: u32,
+    pub heartbeat_hard_tolerance: u32,
+    pub heartbeat_rule: HeartbeatRule,
+    pub logout_timeout: Duration,
+    pub schedule: Option<Schedule>,
+    pub backoff: Backoff,
+    pub reset_on_logon: bool,
+    pub enable_next_expected_msg_seq_num: bool,
+    pub session_mode: SessionMode,
+    pub replay_on_logon: bool,
+    pub default_appl_ver_id: Option<String>,
+    pub throttle: Option<Throttle>,
+    pub send_queue_capacity: usize,
+    pub inbound_queue_capacity: usize,
     pub seq_numbers: SeqNumbers,
     pub msg_seq_num_inbound: MsgSeqNumCounter,
     pub msg_seq_num_outbound: MsgSeqNumCounter,
@@ -92,6 +210,62 @@ impl Configure for Config {
     fn heartbeat(&self) -> Duration {
         self.heartbeat
     }
+
+    fn heartbeat_soft_tolerance(&self) -> u32 {
+        self.heartbeat_soft_tolerance
+    }
+
+    fn heartbeat_hard_tolerance(&self) -> u32 {
+        self.heartbeat_hard_tolerance
+    }
+
+    fn heartbeat_rule(&self) -> HeartbeatRule {
+        self.heartbeat_rule.clone()
+    }
+
+    fn logout_timeout(&self) -> Duration {
+        self.logout_timeout
+    }
+
+    fn schedule(&self) -> Option<Schedule> {
+        self.schedule.clone()
+    }
+
+    fn reset_on_logon(&self) -> bool {
+        self.reset_on_logon
+    }
+
+    fn enable_next_expected_msg_seq_num(&self) -> bool {
+        self.enable_next_expected_msg_seq_num
+    }
+
+    fn session_mode(&self) -> SessionMode {
+        self.session_mode
+    }
+
+    fn replay_on_logon(&self) -> bool {
+        self.replay_on_logon
+    }
+
+    fn default_appl_ver_id(&self) -> Option<&[u8]> {
+        self.default_appl_ver_id.as_deref().map(str::as_bytes)
+    }
+
+    fn throttle(&self) -> Option<Throttle> {
+        self.throttle.clone()
+    }
+
+    fn send_queue_capacity(&self) -> usize {
+        self.send_queue_capacity
+    }
+
+    fn inbound_queue_capacity(&self) -> usize {
+        self.inbound_queue_capacity
+    }
+
+    fn backoff(&self) -> Backoff {
+        self.backoff.clone()
+    }
 }
 
 impl Default for Config {
@@ -103,6 +277,20 @@ impl Default for Config {
             begin_string: "FIX.4.4".to_string(),
             environment: Environment::Production { allow_test: true },
             heartbeat: Duration::from_secs(30),
+            heartbeat_soft_tolerance: 2,
+            heartbeat_hard_tolerance: 3,
+            heartbeat_rule: HeartbeatRule::Any,
+            logout_timeout: Duration::from_secs(10),
+            schedule: None,
+            backoff: Backoff::default(),
+            reset_on_logon: false,
+            enable_next_expected_msg_seq_num: false,
+            session_mode: SessionMode::Normal,
+            replay_on_logon: false,
+            default_appl_ver_id: None,
+            throttle: None,
+            send_queue_capacity: 1024,
+            inbound_queue_capacity: 10_000,
             seq_numbers: SeqNumbers::new(NonZeroU64::new(1).unwrap(), NonZeroU64::new(1).unwrap()),
             msg_seq_num_inbound: MsgSeqNumCounter::START,
             msg_seq_num_outbound: MsgSeqNumCounter::START,
@@ -133,6 +321,35 @@ mod test {
             config.verify_test_indicator(),
             ConfigDefault.verify_test_indicator()
         );
+        assert_eq!(config.logout_timeout(), ConfigDefault.logout_timeout());
+        assert_eq!(
+            config.heartbeat_soft_tolerance(),
+            ConfigDefault.heartbeat_soft_tolerance()
+        );
+        assert_eq!(
+            config.heartbeat_hard_tolerance(),
+            ConfigDefault.heartbeat_hard_tolerance()
+        );
+        assert_eq!(config.reset_on_logon(), ConfigDefault.reset_on_logon());
+        assert_eq!(
+            config.enable_next_expected_msg_seq_num(),
+            ConfigDefault.enable_next_expected_msg_seq_num()
+        );
+        assert_eq!(
+            config.default_appl_ver_id(),
+            ConfigDefault.default_appl_ver_id()
+        );
+        assert_eq!(config.session_mode(), ConfigDefault.session_mode());
+        assert_eq!(config.replay_on_logon(), ConfigDefault.replay_on_logon());
+        assert_eq!(config.throttle(), ConfigDefault.throttle());
+        assert_eq!(
+            config.send_queue_capacity(),
+            ConfigDefault.send_queue_capacity()
+        );
+        assert_eq!(
+            config.inbound_queue_capacity(),
+            ConfigDefault.inbound_queue_capacity()
+        );
     }
 
     #[quickcheck]
@@ -1,581 +1,4192 @@
-use super::{errs, Backend, Config, Configure, LlEvent, LlEventLoop};
-use crate::session::{Environment, SeqNumbers};
-use crate::tagvalue::FieldMap;
-use crate::tagvalue::FvWrite;
-use crate::tagvalue::Message;
-use crate::tagvalue::{DecoderStreaming, Encoder, EncoderHandle};
-use crate::Buffer;
-use crate::FieldType;
-use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use std::marker::{PhantomData, Unpin};
-use std::pin::Pin;
-use std::time::Duration;
-use uuid::Uuid;
+use super::{
+    errs, message_len, Backend, BusinessRejectReason, Completer, Config, Configure,
+    ConnectionAttempt, Environment, HeartbeatTimers, MemoryStore, MessageLog, MessageStore,
+    MsgSeqNumCounter, OutboundMessage, Reject, ResendRequestRange, SendError, SendRequest,
+    SeqNumbers, SessionEvent, SessionHandle, SessionMetrics, SessionRejectReason, SessionState,
+    Throttler, TimerEvent, WhenThrottled,
+};
+use crate::field_types::Timestamp;
+use crate::tagvalue::{DecodeError, DecoderStreaming, Encoder, EncoderHandle, Message};
+use crate::{FieldMap, GetConfig, SetField, StreamingDecoder};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use futures::channel::mpsc;
+use futures::future::Fuse;
+use futures::{select, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt, StreamExt};
+use futures_timer::Delay;
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::marker::Unpin;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
 const BEGIN_SEQ_NO: u32 = 7;
 const BEGIN_STRING: u32 = 8;
 const END_SEQ_NO: u32 = 16;
+const NEW_SEQ_NO: u32 = 36;
 const MSG_SEQ_NUM: u32 = 34;
 const MSG_TYPE: u32 = 35;
+const REF_SEQ_NUM: u32 = 45;
+const POSS_DUP_FLAG: u32 = 43;
 const SENDER_COMP_ID: u32 = 49;
 const SENDING_TIME: u32 = 52;
 const TARGET_COMP_ID: u32 = 56;
 const TEXT: u32 = 58;
 const ENCRYPT_METHOD: u32 = 98;
+const HEART_BT_INT: u32 = 108;
 const TEST_REQ_ID: u32 = 112;
-const REF_TAG_ID: u32 = 371;
-const REF_MSG_TYPE: u32 = 372;
+const ORIG_SENDING_TIME: u32 = 122;
+const GAP_FILL_FLAG: u32 = 123;
+const RESET_SEQ_NUM_FLAG: u32 = 141;
 const SESSION_REJECT_REASON: u32 = 373;
+const BUSINESS_REJECT_REASON: u32 = 380;
 const TEST_MESSAGE_INDICATOR: u32 = 464;
+const NEXT_EXPECTED_MSG_SEQ_NUM: u32 = 789;
+const DEFAULT_APPL_VER_ID: u32 = 1137;
 
-const SENDING_TIME_ACCURACY_PROBLEM: u32 = 10;
+const FIXT_11: &[u8] = b"FIXT.1.1";
 
-#[derive(Debug, Clone)]
-#[cfg_attr(test, derive(enum_as_inner::EnumAsInner))]
-pub enum Response<'a> {
+/// What the transport layer should do after a [`FixConnection`] has processed
+/// an inbound message.
+///
+/// Outbound messages are never part of a [`Response`]; they are
+/// accumulated by [`FixConnection`] until the transport layer writes them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// Nothing to do, besides writing pending outbound messages.
     None,
+    /// A valid message was received, so the counterparty's `Heartbeat <0>`
+    /// timers must be reset.
     ResetHeartbeat,
+    /// The FIX session is over. The transport must be closed after writing
+    /// pending outbound messages.
     TerminateTransport,
-    Application(Message<'a, &'a [u8]>),
-    Session(&'a [u8]),
-    Inbound(Message<'a, &'a [u8]>),
-    Outbound(Message<'a, &'a [u8]>),
-    OutboundBytes(&'a [u8]),
-    Resend {
-        range: (),
-    },
-    /// The FIX session processor should log each encountered garbled message to
-    /// assist in problem detection and diagnosis.
-    LogGarbled,
 }
 
-/// A FIX connection message processor.
+/// Something that the transport layer must do on behalf of a [`FixConnection`].
+///
+/// Actions are returned by the sans-IO methods of [`FixConnection`] (e.g.
+/// [`FixConnection::on_bytes`] and [`FixConnection::on_tick`]) and must be
+/// carried out in order. Inbound application messages are not actions: they
+/// are delivered to the [`Backend`] as soon as they're decoded.
 #[derive(Debug)]
-pub struct FixConnection<B, C = Config> {
-    uuid: Uuid,
-    config: C,
-    backend: B,
+pub enum Action {
+    /// Write these bytes, i.e. one or more whole FIX messages, to the
+    /// transport.
+    Write(Vec<u8>),
+    /// Call [`FixConnection::on_tick`] at this instant, unless some other
+    /// input arrives earlier. Every new [`Action::ArmTimer`] replaces the
+    /// previous one, and actions without one disarm it.
+    ArmTimer(Instant),
+    /// Close the transport. The FIX session is over, either successfully or
+    /// because of a fatal error.
+    Disconnect(Result<(), ConnectionError>),
+}
+
+/// Which side of the FIX session a [`FixConnection`] is on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// We send the first `Logon <A>`.
+    Initiator,
+    /// We wait for the counterparty's `Logon <A>`.
+    Acceptor,
+}
+
+/// The type returned in the event of a fatal error during a [`FixConnection`].
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectionError {
+    /// I/O error at the transport layer.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The FIX counterparty sent a message that couldn't be decoded.
+    #[error("Garbled inbound message: {0}")]
+    Decode(#[from] DecodeError),
+    /// The FIX counterparty refused our `Logon <A>`, or it didn't reply with
+    /// a valid `Logon <A>`.
+    #[error("Logon failed: {0}")]
+    Logon(String),
+    /// The FIX counterparty stopped responding.
+    #[error("The FIX counterparty stopped responding.")]
+    Timeout,
+    /// The transport was established outside of the windows of
+    /// [`Configure::schedule`].
+    #[error("Outside of the FIX session's schedule.")]
+    OutsideOfSchedule,
+    /// The [`MessageStore`] failed, so outbound messages could no longer be
+    /// persisted (and resent) or seq. numbers could no longer be saved. No
+    /// messages are written after the failure.
+    #[error("Message store failure: {0}")]
+    Store(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Serializes outbound FIX messages into an internal buffer, ready to be
+/// written to the transport layer.
+///
+/// Messages are appended one after the other, so that all responses to a
+/// single event can be written at once.
+#[derive(Debug, Clone, Default)]
+pub struct MessageBuilder {
     encoder: Encoder,
     buffer: Vec<u8>,
-    msg_seq_num_inbound: MsgSeqNumCounter,
-    msg_seq_num_outbound: MsgSeqNumCounter,
+    len: usize,
+    sending_time: Option<DateTime<Utc>>,
 }
 
-#[allow(dead_code)]
-impl<C, B> FixConnection<B, C>
-where
-    C: Configure,
-    B: Backend,
-{
-    /// The entry point for a [`FixConnection`].
-    async fn start<I, O>(&mut self, mut input: I, mut output: O, mut decoder: DecoderStreaming)
-    where
-        I: AsyncRead + Unpin,
-        O: AsyncWrite + Unpin,
-    {
-        self.establish_connection(&mut input, &mut output, &mut decoder)
-            .await;
-        self.event_loop(input, output, decoder).await;
+impl MessageBuilder {
+    /// Creates a new, empty [`MessageBuilder`].
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    async fn establish_connection<I, O>(
+    /// Starts a new FIX message of type `msg_type` at the end of the internal
+    /// buffer.
+    pub fn start_message(
         &mut self,
-        mut input: &mut I,
-        output: &mut O,
-        decoder: &mut DecoderStreaming,
-    ) where
-        I: AsyncRead + Unpin,
-        O: AsyncWrite + Unpin,
-    {
-        let logon = {
-            let begin_string = self.config.begin_string();
-            let sender_comp_id = self.config.sender_comp_id();
-            let target_comp_id = self.config.target_comp_id();
-            let heartbeat = self.config.heartbeat().as_secs();
-            let msg_seq_num = self.msg_seq_num_outbound.next();
-            let mut msg = self
-                .encoder
-                .start_message(begin_string, &mut self.buffer, b"A");
-            msg.set_fv_with_key(&SENDER_COMP_ID, sender_comp_id);
-            msg.set_fv_with_key(&TARGET_COMP_ID, target_comp_id);
-            msg.set_fv_with_key(&SENDING_TIME, chrono::Utc::now());
-            msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-            msg.set_fv_with_key(&ENCRYPT_METHOD, 0);
-            msg.set_fv_with_key(&108, heartbeat);
-            msg.done()
-        };
-        output.write(logon).await.unwrap();
-        self.backend.on_outbound_message(logon).ok();
-        let logon;
-        loop {
-            let mut input = Pin::new(&mut input);
-            let buffer = decoder.supply_buffer();
-            input.read_exact(buffer).await.unwrap();
-            if let Ok(Some(())) = decoder.parse() {
-                logon = decoder.message();
-                break;
-            }
+        begin_string: &[u8],
+        msg_type: &[u8],
+    ) -> EncoderHandle<'_, Vec<u8>> {
+        self.len += 1;
+        self.encoder
+            .start_message(begin_string, &mut self.buffer, msg_type)
+    }
+
+    /// Sets the `SendingTime <52>` of the messages started with
+    /// [`MessageBuilder::start_message_with_header`]. [`None`] means the
+    /// system clock, which is the default.
+    pub fn set_sending_time(&mut self, sending_time: Option<DateTime<Utc>>) {
+        self.sending_time = sending_time;
+    }
+
+    /// Returns the `SendingTime <52>` of the next message.
+    fn sending_time(&self) -> Timestamp {
+        self.sending_time
+            .as_ref()
+            .map_or_else(Timestamp::utc_now, Timestamp::from_utc)
+    }
+
+    /// Like [`MessageBuilder::start_message`], but it also writes all
+    /// `StandardHeader` fields that are derived from `config`, i.e. the
+    /// CompIDs, `MsgSeqNum <34>`, `SendingTime <52>` and, in testing
+    /// environments, `TestMessageIndicator <464>`.
+    pub fn start_message_with_header(
+        &mut self,
+        config: &impl Configure,
+        msg_type: &[u8],
+        msg_seq_num: u64,
+    ) -> EncoderHandle<'_, Vec<u8>> {
+        self.len += 1;
+        let sending_time = self.sending_time();
+        let mut msg = self
+            .encoder
+            .start_message(config.begin_string(), &mut self.buffer, msg_type);
+        msg.set(SENDER_COMP_ID, config.sender_comp_id());
+        msg.set(TARGET_COMP_ID, config.target_comp_id());
+        msg.set(MSG_SEQ_NUM, msg_seq_num);
+        msg.set(SENDING_TIME, sending_time);
+        if config.environment() == Environment::Testing {
+            msg.set(TEST_MESSAGE_INDICATOR, true);
+        }
+        msg
+    }
+
+    /// Returns the contents of all messages written since the last call to
+    /// [`MessageBuilder::clear`].
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer[..]
+    }
+
+    /// Returns the number of pending messages.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if and only if there are no pending messages.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Discards all pending messages.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.len = 0;
+    }
+
+    /// Discards all pending messages from byte `offset` onwards.
+    fn truncate(&mut self, offset: usize) {
+        self.buffer.truncate(offset);
+        self.len = 0;
+        let mut bytes = &self.buffer[..];
+        while let Some(len) = message_len(bytes) {
+            self.len += 1;
+            bytes = &bytes[len..];
         }
-        self.on_logon(logon);
-        self.backend.on_inbound_message(logon, true).ok();
-        decoder.clear();
-        self.msg_seq_num_inbound.next();
-        self.backend.on_successful_handshake().ok();
     }
+}
+
+/// The first failure of a [`MessageStore`] since the last
+/// [`FixConnection::collect_actions`]. Pending outbound messages are written
+/// up to `offset`, and none after it.
+#[derive(Debug)]
+struct StoreFailure {
+    offset: usize,
+    error: Box<dyn std::error::Error + Send + Sync>,
+}
 
-    async fn event_loop<I, O>(&mut self, input: I, mut output: O, decoder: DecoderStreaming)
+impl StoreFailure {
+    /// Records `error` in `failure`, unless an earlier failure is already
+    /// there. Only the messages before `offset` will be written.
+    fn record<E>(failure: &mut Option<Self>, offset: usize, error: E)
     where
-        I: AsyncRead + Unpin,
-        O: AsyncWrite + Unpin,
+        E: std::error::Error + Send + Sync + 'static,
     {
-        let event_loop = &mut LlEventLoop::new(decoder, input, self.heartbeat());
-        loop {
-            let event = event_loop
-                .next_event()
-                .await
-                .expect("The connection died unexpectedly.");
-            match event {
-                LlEvent::Message(msg) => {
-                    let response = self.on_inbound_message(msg, unimplemented!());
-                    match response {
-                        Response::OutboundBytes(bytes) => {
-                            output.write_all(bytes).await.unwrap();
-                            self.on_outbound_message(bytes).ok();
-                        }
-                        Response::ResetHeartbeat => {
-                            event_loop.ping_heartbeat();
-                        }
-                        _ => {}
-                    }
-                }
-                LlEvent::BadMessage(_err) => {}
-                LlEvent::IoError(_) => {
-                    return;
-                }
-                LlEvent::Heartbeat => {
-                    let heartbeat = self.on_heartbeat_is_due();
-                    output.write_all(heartbeat).await.unwrap();
-                    self.on_outbound_message(heartbeat).ok();
-                }
-                LlEvent::Logout => {}
-                LlEvent::TestRequest => {}
+        match failure {
+            Some(failure) => failure.offset = failure.offset.min(offset),
+            None => {
+                *failure = Some(Self {
+                    offset,
+                    error: Box::new(error),
+                });
             }
         }
     }
 }
 
+/// Verification logic for inbound FIX messages.
 pub trait Verify {
+    /// The type returned in the event of a failed verification.
     type Error;
 
+    /// Verifies the `BeginString <8>` of an inbound message.
     fn verify_begin_string(&self, begin_string: &[u8]) -> Result<(), Self::Error>;
 
+    /// Verifies the `TestMessageIndicator <464>` of an inbound message, if
+    /// present.
     fn verify_test_message_indicator(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error>;
 
-    fn verify_sending_time(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error>;
+    /// Verifies the `SenderCompID <49>` and `TargetCompID <56>` of an inbound
+    /// message.
+    fn verify_comp_ids(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error>;
+
+    /// Verifies the `SendingTime <52>` of an inbound message, which was
+    /// received at `now`.
+    fn verify_sending_time(
+        &self,
+        msg: &impl FieldMap<u32>,
+        now: DateTime<Utc>,
+    ) -> Result<(), Self::Error>;
+
+    /// Verifies the `OrigSendingTime <122>` of an inbound message, if it's a
+    /// possible duplicate.
+    fn verify_orig_sending_time(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error>;
+}
+
+/// Reasons for which a [`Verifier`] refuses an inbound message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VerifyError {
+    /// `BeginString <8>` is different from the one in use.
+    BeginString,
+    /// `TestMessageIndicator <464>` is not allowed by the [`Environment`].
+    TestMessageIndicator,
+    /// `SenderCompID <49>` is not the counterparty's.
+    SenderCompId,
+    /// `TargetCompID <56>` is not ours.
+    TargetCompId,
+    /// `SendingTime <52>` is missing or invalid.
+    SendingTime,
+    /// `SendingTime <52>` is further from the current time than
+    /// [`Configure::max_allowed_latency`].
+    SendingTimeAccuracy,
+    /// `OrigSendingTime <122>` is missing or invalid in a possible duplicate.
+    OrigSendingTime,
+    /// `OrigSendingTime <122>` is later than `SendingTime <52>`.
+    OrigSendingTimeAccuracy,
+}
+
+/// The canonical implementor of [`Verify`], which takes its settings from a
+/// [`Configure`] implementor.
+#[derive(Debug, Clone, Default)]
+pub struct Verifier<C = Config> {
+    config: C,
 }
 
-impl<'a, B, C, V> FixConnector<'a, B, C, V> for FixConnection<B, C>
+impl<C> Verifier<C>
 where
-    B: Backend,
     C: Configure,
-    V: Verify,
 {
-    type Error = &'a [u8];
-    type Msg = EncoderHandle<'a, Vec<u8>>;
-
-    fn on_inbound_app_message(&mut self, message: Message<&[u8]>) -> Result<(), Self::Error> {
-        Ok(())
+    /// Creates a new [`Verifier`] with the settings in `config`.
+    pub fn new(config: C) -> Self {
+        Self { config }
     }
+}
 
-    fn on_outbound_message(&mut self, message: &[u8]) -> Result<(), Self::Error> {
-        Ok(())
-    }
+impl<C> Verify for Verifier<C>
+where
+    C: Configure,
+{
+    type Error = VerifyError;
 
-    fn verifier(&self) -> V {
-        unimplemented!()
+    fn verify_begin_string(&self, begin_string: &[u8]) -> Result<(), Self::Error> {
+        if begin_string == self.config.begin_string() {
+            Ok(())
+        } else {
+            Err(VerifyError::BeginString)
+        }
     }
 
-    fn environment(&self) -> Environment {
-        self.config.environment()
+    fn verify_test_message_indicator(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error> {
+        if !self.config.verify_test_indicator() {
+            return Ok(());
+        }
+        let is_test = msg
+            .get_opt::<bool>(TEST_MESSAGE_INDICATOR)
+            .map_err(|_| VerifyError::TestMessageIndicator)?
+            .unwrap_or(false);
+        match self.config.environment() {
+            Environment::Production { allow_test } if is_test && !allow_test => {
+                Err(VerifyError::TestMessageIndicator)
+            }
+            Environment::Testing if !is_test => Err(VerifyError::TestMessageIndicator),
+            _ => Ok(()),
+        }
     }
 
-    fn sender_comp_id(&self) -> &[u8] {
-        self.config.sender_comp_id()
+    fn verify_comp_ids(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error> {
+        if msg.get_raw(SENDER_COMP_ID) != Some(self.config.target_comp_id()) {
+            Err(VerifyError::SenderCompId)
+        } else if msg.get_raw(TARGET_COMP_ID) != Some(self.config.sender_comp_id()) {
+            Err(VerifyError::TargetCompId)
+        } else {
+            Ok(())
+        }
     }
 
-    fn target_comp_id(&self) -> &[u8] {
-        self.config.target_comp_id()
+    fn verify_sending_time(
+        &self,
+        msg: &impl FieldMap<u32>,
+        now: DateTime<Utc>,
+    ) -> Result<(), Self::Error> {
+        let sending_time = get_utc(msg, SENDING_TIME).ok_or(VerifyError::SendingTime)?;
+        let latency = (now - sending_time).abs().to_std().unwrap_or(Duration::MAX);
+        if latency > self.config.max_allowed_latency() {
+            Err(VerifyError::SendingTimeAccuracy)
+        } else {
+            Ok(())
+        }
     }
 
-    fn heartbeat(&self) -> Duration {
-        self.config.heartbeat()
+    fn verify_orig_sending_time(&self, msg: &impl FieldMap<u32>) -> Result<(), Self::Error> {
+        if msg.get_opt(POSS_DUP_FLAG) != Ok(Some(true)) {
+            return Ok(());
+        }
+        if msg.get_raw(MSG_TYPE) == Some(b"4") && msg.get_raw(ORIG_SENDING_TIME).is_none() {
+            // Many counterparties don't bother with `OrigSendingTime <122>`
+            // in gap fills, which have no original anyway.
+            return Ok(());
+        }
+        let orig_sending_time =
+            get_utc(msg, ORIG_SENDING_TIME).ok_or(VerifyError::OrigSendingTime)?;
+        let sending_time = get_utc(msg, SENDING_TIME).ok_or(VerifyError::SendingTime)?;
+        if orig_sending_time > sending_time {
+            Err(VerifyError::OrigSendingTimeAccuracy)
+        } else {
+            Ok(())
+        }
     }
 }
 
-pub struct MessageBuilder {}
-
-pub struct MessageBuiderTuple<'a> {
-    phantom: PhantomData<&'a ()>,
+/// Reads a `UTCTimestamp` field as a [`DateTime<Utc>`].
+fn get_utc(msg: &impl FieldMap<u32>, tag: u32) -> Option<DateTime<Utc>> {
+    let timestamp = msg.get::<Timestamp>(tag).ok()?;
+    let (date, time) = (timestamp.date(), timestamp.time());
+    let date = NaiveDate::from_ymd_opt(date.year() as i32, date.month(), date.day())?;
+    let time =
+        NaiveTime::from_hms_milli_opt(time.hour(), time.minute(), time.second(), time.milli())?;
+    Some(DateTime::from_naive_utc_and_offset(
+        date.and_time(time),
+        Utc,
+    ))
 }
 
-impl<'a> MessageBuiderTuple<'a> {
-    pub fn get(self) -> (EncoderHandle<'a, Vec<u8>>, &'a mut MessageBuilder) {
-        unimplemented!()
-    }
+/// A FIX connection message processor.
+///
+/// [`FixConnection`] implements the FIX session layer on top of any
+/// [`AsyncRead`] and [`AsyncWrite`] transport. Inbound application messages
+/// and all other notable events are delivered to a [`Backend`]. Outbound
+/// messages and seq. numbers are persisted to a [`MessageStore`], so that
+/// they can be resent upon request.
+///
+/// The session logic itself doesn't perform any I/O and never reads the
+/// system clock: [`FixConnection::on_connected`], [`FixConnection::on_bytes`]
+/// and [`FixConnection::on_tick`] take the current time as an argument and
+/// return the [`Action`]s that the transport layer must carry out. All
+/// timestamps, e.g. `SendingTime <52>`, are derived from the wall-clock time
+/// given to [`FixConnection::on_connected`].
+/// [`FixConnection::start`] and [`FixConnection::accept`] are thin
+/// asynchronous drivers on top of them.
+#[derive(Debug)]
+pub struct FixConnection<B, C = Config, V = Verifier<C>, S = MemoryStore> {
+    config: C,
+    backend: B,
+    verifier: V,
+    store: S,
+    builder: MessageBuilder,
+    state: SessionState,
+    msg_seq_num_inbound: MsgSeqNumCounter,
+    msg_seq_num_outbound: MsgSeqNumCounter,
+    resend_requested_up_to: Option<u64>,
+    queue: BTreeMap<u64, Vec<u8>>,
+    role: Option<Role>,
+    decoder: Option<DecoderStreaming<Vec<u8>>>,
+    timers: Option<HeartbeatTimers>,
+    logout_deadline: Option<Instant>,
+    session_end: Option<Instant>,
+    test_req_id: Option<String>,
+    test_req_sent_at: Option<DateTime<Utc>>,
+    was_logged_on: bool,
+    reset_sent: bool,
+    counterparty_appl_ver_id: Option<Vec<u8>>,
+    throttler: Option<Throttler>,
+    throttled: VecDeque<(OutboundMessage, Option<Completer>)>,
+    handle: Option<SessionHandle>,
+    send_queue: Option<mpsc::Receiver<SendRequest>>,
+    /// Messages enqueued by a [`SessionHandle`] that are waiting to be
+    /// written, with their offset in `builder`.
+    sent: Vec<(Completer, u64, usize)>,
+    metrics: SessionMetrics,
+    log: Option<Box<dyn MessageLog + Send>>,
+    clock: Option<(Instant, DateTime<Utc>)>,
+    received_at: Option<DateTime<Utc>>,
+    store_failure: Option<StoreFailure>,
 }
 
-impl MessageBuilder {
-    pub fn start_message(&mut self, begin_string: &[u8], msg_type: &[u8]) -> MessageBuiderTuple {
-        unimplemented!()
+impl<B, C> FixConnection<B, C>
+where
+    B: Backend,
+    C: Configure,
+{
+    /// Creates a new [`FixConnection`] which uses a [`Verifier`] with the
+    /// same settings as `config`.
+    pub fn new(config: C, backend: B) -> Self {
+        let verifier = Verifier::new(config.clone());
+        Self::with_verifier(config, backend, verifier)
     }
 }
 
-struct ResponseData<'a> {
-    pub begin_stringt: &'a [u8],
-    pub msg_type: &'a [u8],
-    pub msg_seq_num: u32,
+impl<B, C, V> FixConnection<B, C, V>
+where
+    B: Backend,
+    C: Configure,
+    V: Verify,
+{
+    /// Creates a new [`FixConnection`] with a custom [`Verify`] implementor.
+    pub fn with_verifier(config: C, backend: B, verifier: V) -> Self {
+        let throttler = config.throttle().map(Throttler::new);
+        Self {
+            config,
+            backend,
+            verifier,
+            store: MemoryStore::new(),
+            builder: MessageBuilder::new(),
+            state: SessionState::Disconnected,
+            msg_seq_num_inbound: MsgSeqNumCounter::START,
+            msg_seq_num_outbound: MsgSeqNumCounter::START,
+            resend_requested_up_to: None,
+            queue: BTreeMap::new(),
+            role: None,
+            decoder: None,
+            timers: None,
+            logout_deadline: None,
+            session_end: None,
+            test_req_id: None,
+            test_req_sent_at: None,
+            was_logged_on: false,
+            reset_sent: false,
+            counterparty_appl_ver_id: None,
+            throttler,
+            throttled: VecDeque::new(),
+            handle: None,
+            send_queue: None,
+            sent: vec![],
+            metrics: SessionMetrics::default(),
+            log: None,
+            clock: None,
+            received_at: None,
+            store_failure: None,
+        }
+    }
 }
 
-pub trait FixConnector<'a, B, C, Z>
+impl<B, C, V, S> FixConnection<B, C, V, S>
 where
     B: Backend,
     C: Configure,
-    Z: Verify,
+    V: Verify,
+    S: MessageStore,
 {
-    type Error: FieldType<'a>;
-    type Msg: FvWrite<'a>;
-
-    fn target_comp_id(&self) -> &[u8];
+    /// Replaces the [`MessageStore`] of `self` with `store`. The FIX session
+    /// resumes from the seq. numbers in `store`.
+    pub fn with_store<S2>(self, store: S2) -> FixConnection<B, C, V, S2>
+    where
+        S2: MessageStore,
+    {
+        let seq_numbers = store.seq_numbers();
+        let mut msg_seq_num_inbound = MsgSeqNumCounter::START;
+        msg_seq_num_inbound.set_expected(seq_numbers.next_inbound());
+        let mut msg_seq_num_outbound = MsgSeqNumCounter::START;
+        msg_seq_num_outbound.set_expected(seq_numbers.next_outbound());
+        FixConnection {
+            config: self.config,
+            backend: self.backend,
+            verifier: self.verifier,
+            store,
+            builder: self.builder,
+            state: self.state,
+            msg_seq_num_inbound,
+            msg_seq_num_outbound,
+            resend_requested_up_to: None,
+            queue: BTreeMap::new(),
+            role: self.role,
+            decoder: self.decoder,
+            timers: self.timers,
+            logout_deadline: self.logout_deadline,
+            session_end: self.session_end,
+            test_req_id: self.test_req_id,
+            test_req_sent_at: self.test_req_sent_at,
+            was_logged_on: self.was_logged_on,
+            reset_sent: self.reset_sent,
+            counterparty_appl_ver_id: self.counterparty_appl_ver_id,
+            throttler: self.throttler,
+            throttled: self.throttled,
+            handle: self.handle,
+            send_queue: self.send_queue,
+            sent: self.sent,
+            metrics: self.metrics,
+            log: self.log,
+            clock: self.clock,
+            received_at: self.received_at,
+            store_failure: None,
+        }
+    }
 
-    fn sender_comp_id(&self) -> &[u8];
+    /// Records all raw inbound and outbound FIX messages of `self` to `log`,
+    /// along with every [`SessionEvent`].
+    pub fn with_log(mut self, log: impl MessageLog + Send + 'static) -> Self {
+        self.log = Some(Box::new(log));
+        self
+    }
 
-    fn verifier(&self) -> &Z;
+    /// Returns an immutable reference to the configuration options of `self`.
+    pub fn config(&self) -> &C {
+        &self.config
+    }
 
-    fn dispatch_by_msg_type(&self, msg_type: &[u8], msg: Message<&[u8]>) -> Response {
-        match msg_type {
-            b"A" => {
-                self.on_logon(msg);
-                return Response::None;
-            }
-            b"1" => {
-                let msg = self.on_test_request(msg);
-                return Response::OutboundBytes(msg);
-            }
-            b"2" => {
-                return Response::None;
-            }
-            b"5" => {
-                return Response::OutboundBytes(self.on_logout(&msg));
-            }
-            b"0" => {
-                self.on_heartbeat(msg);
-                return Response::ResetHeartbeat;
-            }
-            _ => {
-                return self.on_application_message(msg);
-            }
-        }
+    /// Returns an immutable reference to the [`Backend`] of `self`.
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
-    /// Callback for processing incoming FIX application messages.
-    fn on_inbound_app_message(&mut self, message: Message<&[u8]>) -> Result<(), Self::Error>;
+    /// Returns a mutable reference to the [`Backend`] of `self`.
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
 
-    /// Callback for post-processing outbound FIX messages.
-    fn on_outbound_message(&mut self, message: &[u8]) -> Result<(), Self::Error>;
+    /// Returns an immutable reference to the [`MessageStore`] of `self`.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
 
-    fn environment(&self) -> Environment;
+    /// Returns a mutable reference to the [`MessageStore`] of `self`.
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
 
-    fn heartbeat(&self) -> Duration;
+    /// Returns the current [`SessionState`] of `self`.
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
 
-    fn seq_numbers(&self) -> SeqNumbers;
+    /// Returns the `ApplVerID <1128>` of inbound application messages that
+    /// don't specify one: the `DefaultApplVerID <1137>` of the
+    /// counterparty's `Logon <A>`, if any, or else
+    /// [`Configure::default_appl_ver_id`].
+    pub fn default_appl_ver_id(&self) -> Option<&[u8]> {
+        self.counterparty_appl_ver_id
+            .as_deref()
+            .or_else(|| self.config.default_appl_ver_id())
+    }
 
-    fn msg_seq_num(&mut self) -> &mut MsgSeqNumCounter;
+    /// Counts `event` and reports it to the [`Backend`].
+    fn emit(&mut self, event: SessionEvent) {
+        self.metrics.record(&event);
+        if let Some(log) = self.log.as_mut() {
+            log.on_event(&event.to_string()).ok();
+        }
+        self.backend.on_session_event(&event).ok();
+    }
 
-    fn on_inbound_message(
-        &'a mut self,
-        msg: Message<&[u8]>,
-        builder: MessageBuilder,
-    ) -> Response<'a> {
-        if self.verifier().verify_test_message_indicator(msg).is_err() {
-            return self.on_wrong_environment(msg);
+    fn set_state(&mut self, state: SessionState) {
+        if state != self.state {
+            let old = std::mem::replace(&mut self.state, state);
+            self.backend.on_state_change(old, state).ok();
         }
-        let seq_num = if let Ok(n) = msg.get::<u64>(&MSG_SEQ_NUM) {
-            let expected = self.msg_seq_num_inbound.expected();
-            if n < expected {
-                return self.on_low_seqnum(msg);
-            } else if n > expected {
-                // Refer to specs. §4.8 for more information.
-                return self.on_high_seqnum(msg);
-            }
-            n
-        } else {
-            // See §4.5.3.
-            return self.on_missing_seqnum(msg);
-        };
+    }
 
-        // Increment immediately.
-        self.msg_seq_num_inbound.next();
+    /// Switches between [`SessionState::Active`] and
+    /// [`SessionState::ResendInProgress`], depending on whether we're waiting
+    /// for the counterparty to fill a seq. number gap.
+    fn update_logged_on_state(&mut self) {
+        if self.state.is_logged_on() {
+            if self.resend_requested_up_to.is_some() {
+                self.set_state(SessionState::ResendInProgress);
+            } else {
+                self.set_state(SessionState::Active);
+            }
+        }
+    }
 
-        if self.verifier().verify_sending_time(&msg).is_err() {
-            return self.make_reject_for_inaccurate_sending_time(msg);
+    /// Returns the expected seq. numbers of the next inbound and outbound
+    /// messages.
+    pub fn seq_numbers(&self) -> SeqNumbers {
+        SeqNumbers {
+            next_inbound: self.msg_seq_num_inbound.expected(),
+            next_outbound: self.msg_seq_num_outbound.expected(),
         }
+    }
 
-        let msg_type = if let Ok(x) = msg.get::<&[u8]>(&MSG_TYPE) {
-            x
-        } else {
-            self.on_inbound_app_message(msg).ok();
-            return self.on_application_message(msg);
-        };
-        self.dispatch_by_msg_type(msg_type, msg)
+    /// Returns the counters of `self`, e.g. for exporting them to a metrics
+    /// system.
+    pub fn metrics(&self) -> &SessionMetrics {
+        &self.metrics
     }
 
-    fn on_resend_request(&self, msg: &Message<&[u8]>) {
-        let begin_seq_num = msg.get(&BEGIN_SEQ_NO).unwrap();
-        let end_seq_num = msg.get(&END_SEQ_NO).unwrap();
-        self.on_resend_request(begin_seq_num..end_seq_num).ok();
+    /// Returns a [`SessionHandle`] for sending application messages on `self`
+    /// from other tasks. Its messages are sent by the asynchronous drivers,
+    /// e.g. [`FixConnection::start`], whenever `self` is logged on; until
+    /// then, they wait in the queue.
+    pub fn handle(&mut self) -> SessionHandle {
+        let capacity = self.config.send_queue_capacity();
+        let send_queue = &mut self.send_queue;
+        let handle = self.handle.get_or_insert_with(|| {
+            let (handle, receiver) = SessionHandle::channel(capacity);
+            *send_queue = Some(receiver);
+            handle
+        });
+        handle.clone()
     }
 
-    fn on_logout(&mut self, data: ResponseData, _msg: &Message<&[u8]>) -> &[u8] {
-        let fix_message = {
-            let msg_seq_num = self.next();
-            let mut msg = self.start_message(data.begin_string, b"5");
-            self.set_sender_and_target(&mut msg);
-            msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-            msg.set_fv_with_key(&TEXT, "Logout");
-            msg.done()
-        };
-        fix_message
-    }
-
-    //    fn add_seqnum(&self, msg: &mut RawEncoderState) {
-    //        msg.add_field(tags::MSG_SEQ_NUM, self.seq_numbers().next_outbound());
-    //        self.seq_numbers_mut().incr_outbound();
-    //    }
-    //
-    //    fn add_sending_time(&self, msg: &mut RawEncoderState) {
-    //        msg.add_field(tags::SENDING_TIME, DtfTimestamp::utc_now());
-    //    }
-    //
-    //    #[must_use]
-    fn on_heartbeat_is_due(&mut self) -> &[u8] {
-        let fix_message = {
-            let begin_string = self.begin_string();
-            let msg_seq_num = self.msg_seq_num_outbound.next();
-            let mut msg = self
-                .encoder
-                .start_message(begin_string, &mut self.buffer, b"0");
-            self.set_sender_and_target(&mut msg);
-            msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-            self.set_sending_time(&mut msg);
-            msg.done()
-        };
-        fix_message
+    /// Returns all outbound messages that are waiting to be written to the
+    /// transport layer.
+    pub fn pending_outbound(&self) -> &[u8] {
+        self.builder.as_bytes()
     }
 
-    fn set_sender_and_target(&'a self, msg: &mut impl FvWrite<'a, Key = u32>) {
-        msg.set_fv_with_key(&SENDER_COMP_ID, self.sender_comp_id());
-        msg.set_fv_with_key(&TARGET_COMP_ID, self.target_comp_id());
+    /// Discards all outbound messages returned by
+    /// [`FixConnection::pending_outbound`]. Call this after writing them.
+    pub fn clear_outbound(&mut self) {
+        self.builder.clear();
     }
 
-    fn set_sending_time(&'a self, msg: &mut impl FvWrite<'a, Key = u32>) {
-        msg.set_fv_with_key(&SENDING_TIME, chrono::Utc::now());
+    /// Removes and returns the next inbound message that was queued because
+    /// of a seq. number gap, but only once the gap has been filled. Its raw
+    /// contents should be decoded and fed to
+    /// [`FixConnection::on_inbound_message`] like any other inbound message.
+    pub fn pop_queued_message(&mut self) -> Option<Vec<u8>> {
+        let expected = self.msg_seq_num_inbound.expected();
+        // Anything below `expected` has been resent in the meantime.
+        self.queue = self.queue.split_off(&expected);
+        self.queue.remove(&expected)
     }
 
-    fn set_header_details(&'a self, _msg: &mut impl FvWrite<'a, Key = u32>) {}
+    /// The entry point for a [`FixConnection`] acting as the initiator.
+    ///
+    /// Sends a `Logon <A>`, waits for the counterparty's `Logon <A>` and then
+    /// processes inbound messages until either side logs out. The transport
+    /// is closed before returning.
+    ///
+    /// The transport must be opened within a window of
+    /// [`Configure::schedule`], or else it's closed right away with
+    /// [`ConnectionError::OutsideOfSchedule`]. Most venues drop transports
+    /// that stay idle for long, so call [`FixConnection::wait_for_schedule`]
+    /// before opening it.
+    pub async fn start<I, O>(
+        &mut self,
+        input: I,
+        output: O,
+        decoder: DecoderStreaming<Vec<u8>>,
+    ) -> Result<(), ConnectionError>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        let actions = self.on_connected(Role::Initiator, decoder, Utc::now(), Instant::now());
+        let result = self.drive(input, output, actions).await;
+        self.on_disconnected();
+        result
+    }
 
-    fn on_heartbeat(&mut self, _msg: Message<&[u8]>) {
-        // TODO: verify stuff.
+    /// Waits until the next window of [`Configure::schedule`] begins, if
+    /// we're outside of all of them. Returns right away otherwise.
+    pub async fn wait_for_schedule(&self) {
+        if let Some(schedule) = self.config.schedule() {
+            let utc_now = Utc::now();
+            if !schedule.is_active(utc_now) {
+                let wait = schedule.next_window_start(utc_now) - utc_now;
+                Delay::new(wait.to_std().unwrap_or_default()).await;
+            }
+        }
     }
 
-    fn on_test_request(&mut self, msg: Message<&[u8]>) -> &[u8] {
-        let test_req_id = msg.get::<&[u8]>(&TEST_REQ_ID).unwrap();
-        let begin_string = self.begin_string();
-        let msg_seq_num = self.msg_seq_num_outbound.next();
-        let mut msg = self.start_message(begin_string, b"1");
-        self.set_sender_and_target(&mut msg);
-        msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-        self.set_sending_time(&mut msg);
-        msg.set_fv_with_key(&TEST_REQ_ID, test_req_id);
-        msg.done()
+    /// The entry point for a [`FixConnection`] acting as the acceptor.
+    ///
+    /// Waits for the counterparty's `Logon <A>` and validates its
+    /// `BeginString <8>`, CompIDs and `HeartBtInt <108>` against the
+    /// configuration options of `self`. The counterparty is then either logged
+    /// on with a `Logon <A>` reply or refused with a `Logout <5>`. After a
+    /// successful logon, inbound messages are processed until either side logs
+    /// out. The transport is closed before returning.
+    pub async fn accept<I, O>(
+        &mut self,
+        input: I,
+        output: O,
+        decoder: DecoderStreaming<Vec<u8>>,
+    ) -> Result<(), ConnectionError>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        let actions = self.on_connected(Role::Acceptor, decoder, Utc::now(), Instant::now());
+        let result = self.drive(input, output, actions).await;
+        self.on_disconnected();
+        result
     }
 
-    fn on_wrong_environment(&mut self, _message: Message<&[u8]>) -> Response {
-        self.make_logout(errs::production_env())
+    /// Like [`FixConnection::start`], but it keeps the FIX session going
+    /// across transport failures until a graceful logout.
+    ///
+    /// Transports are established by `connect`, which receives one of
+    /// `endpoints`. Failed attempts are retried after the delays of
+    /// [`Configure::backoff`], moving on to the next endpoint each time. Once
+    /// logged on, the same endpoint is tried first and the delays start over.
+    /// `decoder` builds a fresh decoder for each transport. Seq. numbers are
+    /// preserved across attempts, and each attempt is reported to
+    /// [`Backend::on_connection_attempt`]. Transports are only established
+    /// within the windows of [`Configure::schedule`]: outside of them, the
+    /// next attempt waits for the next window.
+    ///
+    /// Returns the error of the last attempt if [`Configure::backoff`] gives
+    /// up.
+    pub async fn start_with_reconnect<E, F, Fut, I, O>(
+        &mut self,
+        endpoints: &[E],
+        mut connect: F,
+        mut decoder: impl FnMut() -> DecoderStreaming<Vec<u8>>,
+    ) -> Result<(), ConnectionError>
+    where
+        F: FnMut(&E) -> Fut,
+        Fut: Future<Output = io::Result<(I, O)>>,
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        if endpoints.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput).into());
+        }
+        let backoff = self.config.backoff();
+        let mut endpoint = 0;
+        let mut failures = 0;
+        loop {
+            self.was_logged_on = false;
+            self.wait_for_schedule().await;
+            let result = match connect(&endpoints[endpoint]).await {
+                Ok((input, output)) => self.start(input, output, decoder()).await,
+                Err(err) => Err(err.into()),
+            };
+            if self.was_logged_on {
+                failures = 0;
+            }
+            let retry_in = if result.is_ok() {
+                None
+            } else {
+                failures += 1;
+                backoff.delay(failures)
+            };
+            let attempt = ConnectionAttempt {
+                failures,
+                endpoint,
+                result: &result,
+                retry_in,
+            };
+            self.backend.on_connection_attempt(&attempt).ok();
+            match retry_in {
+                Some(delay) => Delay::new(delay).await,
+                None => return result,
+            }
+            if !self.was_logged_on {
+                endpoint = (endpoint + 1) % endpoints.len();
+            }
+        }
     }
 
-    fn generate_error_seqnum_too_low(&mut self) -> &[u8] {
-        let begin_string = self.begin_string();
-        let msg_seq_num = self.msg_seq_num_outbound.next();
-        let text = errs::msg_seq_num(self.msg_seq_num_inbound.0 + 1);
-        let mut msg = self.start_message(begin_string, b"FIXME");
-        msg.set_fv_with_key(&MSG_TYPE, "5");
-        self.set_sender_and_target(&mut msg);
-        msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-        msg.set_fv_with_key(&TEXT, text.as_str());
-        msg.done()
+    /// Carries out `actions` and feeds the transport and the system clock to
+    /// the sans-IO methods of `self`, until an [`Action::Disconnect`].
+    async fn drive<I, O>(
+        &mut self,
+        mut input: I,
+        mut output: O,
+        mut actions: Vec<Action>,
+    ) -> Result<(), ConnectionError>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        let mut buffer = vec![0; 4096];
+        loop {
+            let mut deadline = None;
+            for action in actions {
+                match action {
+                    Action::Write(bytes) => {
+                        output.write_all(&bytes).await?;
+                        output.flush().await?;
+                    }
+                    Action::ArmTimer(instant) => {
+                        deadline = Some(instant);
+                    }
+                    Action::Disconnect(result) => {
+                        self.complete_sent();
+                        output.close().await?;
+                        return result;
+                    }
+                }
+            }
+            self.complete_sent();
+            let mut timer = match deadline {
+                Some(deadline) => {
+                    Delay::new(deadline.saturating_duration_since(Instant::now())).fuse()
+                }
+                None => Fuse::terminated(),
+            };
+            let logged_on = self.state.is_logged_on();
+            let wakeup = {
+                let mut read = input.read(&mut buffer).fuse();
+                let mut request = match self.send_queue.as_mut() {
+                    Some(send_queue) if logged_on => send_queue.next().fuse(),
+                    _ => Fuse::terminated(),
+                };
+                select! {
+                    read_result = read => Wakeup::Read(read_result),
+                    request = request => Wakeup::Send(request),
+                    () = timer => Wakeup::Tick,
+                }
+            };
+            actions = match wakeup {
+                Wakeup::Read(Ok(0)) => {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Wakeup::Read(Ok(num_bytes)) => self.on_bytes(&buffer[..num_bytes], Instant::now()),
+                Wakeup::Read(Err(err)) => return Err(err.into()),
+                Wakeup::Send(Some(request)) => self.on_send_request(request, Instant::now()),
+                Wakeup::Send(None) | Wakeup::Tick => self.on_tick(Instant::now()),
+            };
+        }
     }
 
-    fn on_missing_seqnum(&mut self, _message: Message<&[u8]>) -> Response {
-        self.make_logout(errs::missing_field("MsgSeqNum", MSG_SEQ_NUM))
+    /// Starts a new FIX session over a freshly established transport, at
+    /// `now`. As the initiator, this sends our `Logon <A>`; as the acceptor,
+    /// it waits for the counterparty's.
+    ///
+    /// Inbound bytes will be decoded with `decoder`, which is made to reject
+    /// duplicate tags as per
+    /// [`reject_duplicate_tags`](crate::tagvalue::Config::reject_duplicate_tags).
+    /// `utc_now` is the wall-clock time of `now`, which is compared against
+    /// [`Configure::schedule`]: the [`MessageStore`] is reset if it predates
+    /// the current window, and the transport is closed right away if there's
+    /// no current window.
+    pub fn on_connected(
+        &mut self,
+        role: Role,
+        mut decoder: DecoderStreaming<Vec<u8>>,
+        utc_now: DateTime<Utc>,
+        now: Instant,
+    ) -> Vec<Action> {
+        self.role = Some(role);
+        self.counterparty_appl_ver_id = None;
+        decoder.config_mut().reject_duplicate_tags = true;
+        self.sync_appl_ver_id(&mut decoder);
+        self.decoder = Some(decoder);
+        self.clock = Some((now, utc_now));
+        self.set_now(now);
+        self.was_logged_on = false;
+        self.timers = Some(
+            HeartbeatTimers::new(self.config.heartbeat(), now).with_tolerances(
+                self.config.heartbeat_soft_tolerance(),
+                self.config.heartbeat_hard_tolerance(),
+            ),
+        );
+        if let Some(schedule) = self.config.schedule() {
+            let window = match schedule.current_window(utc_now) {
+                Some(window) => window,
+                None => {
+                    let disconnect = Some(Err(ConnectionError::OutsideOfSchedule));
+                    return self.collect_actions(now, disconnect);
+                }
+            };
+            if self.store.creation_time() < window.start {
+                self.reset_store();
+            }
+            let remaining = (window.end - utc_now).to_std().unwrap_or_default();
+            self.session_end = Some(now + remaining);
+        }
+        if role == Role::Initiator {
+            let reset = self.config.reset_on_logon();
+            if reset {
+                self.reset_store();
+            }
+            let next_expected = self.msg_seq_num_inbound.expected();
+            self.on_logon_is_due(self.config.heartbeat(), reset, next_expected);
+            self.set_state(SessionState::LogonSent);
+        }
+        self.collect_actions(now, None)
     }
 
-    fn on_low_seqnum(&mut self, _message: Message<&[u8]>) -> Response {
-        self.make_logout(errs::msg_seq_num(self.msg_seq_num_inbound.0 + 1))
+    /// Processes `bytes`, i.e. any chunk of data that was read from the
+    /// transport at `now`. Messages don't need to be aligned to chunk
+    /// boundaries: partially received messages are retained until the next
+    /// call.
+    pub fn on_bytes(&mut self, mut bytes: &[u8], now: Instant) -> Vec<Action> {
+        let mut decoder = match self.decoder.take() {
+            Some(decoder) => decoder,
+            None => return vec![],
+        };
+        self.set_now(now);
+        self.metrics.record_inbound_bytes(bytes);
+        let disconnect = self.decode_inbound(&mut decoder, &mut bytes, now);
+        self.decoder = Some(decoder);
+        self.collect_actions(now, disconnect)
     }
 
-    fn on_reject(
-        &mut self,
-        _ref_seq_num: u64,
-        ref_tag: Option<u32>,
-        ref_msg_type: Option<&[u8]>,
-        reason: u32,
-        err_text: String,
-    ) -> Response {
-        let begin_string = self.begin_string();
-        let sender_comp_id = self.sender_comp_id();
-        let target_comp_id = self.target_comp_id();
-        let msg_seq_num = self.msg_seq_num_outbound.next();
-        let mut msg = self.start_message(begin_string, b"3");
-        self.set_sender_and_target(&mut msg);
-        msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-        if let Some(ref_tag) = ref_tag {
-            msg.set_fv_with_key(&REF_TAG_ID, ref_tag);
+    /// Sends an application message at `now`, filling in its
+    /// `StandardHeader`.
+    ///
+    /// If [`Configure::throttle`] doesn't allow it right away, the message
+    /// is either queued, to be sent by [`FixConnection::on_tick`] as soon as
+    /// the throttle allows, or refused with [`SendError::Throttled`]. Queued
+    /// messages are sent in order, even across reconnections, and before any
+    /// newer ones.
+    pub fn send(&mut self, msg: OutboundMessage, now: Instant) -> Result<Vec<Action>, SendError> {
+        self.set_now(now);
+        if self.admit(&msg, now)? {
+            self.make_app_message(&msg);
+        } else {
+            self.throttled.push_back((msg, None));
         }
-        if let Some(ref_msg_type) = ref_msg_type {
-            msg.set_fv_with_key(&REF_MSG_TYPE, ref_msg_type);
+        Ok(self.collect_actions(now, None))
+    }
+
+    /// Like [`FixConnection::send`], but the outcome is reported to the
+    /// [`Completion`](super::Completion) of `request`.
+    fn on_send_request(&mut self, request: SendRequest, now: Instant) -> Vec<Action> {
+        let SendRequest { msg, completer } = request;
+        self.set_now(now);
+        match self.admit(&msg, now) {
+            Ok(true) => {
+                let offset = self.builder.as_bytes().len();
+                let msg_seq_num = self.make_app_message(&msg);
+                self.sent.push((completer, msg_seq_num, offset));
+            }
+            Ok(false) => self.throttled.push_back((msg, Some(completer))),
+            Err(err) => {
+                completer.send(Err(err)).ok();
+            }
         }
-        msg.set_fv_with_key(&SESSION_REJECT_REASON, reason);
-        msg.set_fv_with_key(&TEXT, err_text.as_str());
-        Response::OutboundBytes(msg.done())
+        self.collect_actions(now, None)
     }
 
-    fn make_reject_for_inaccurate_sending_time(&mut self, offender: Message<&[u8]>) -> Response {
-        let ref_seq_num = offender.get(&MSG_SEQ_NUM).unwrap();
-        let ref_msg_type = offender.get::<&str>(&MSG_TYPE).unwrap();
-        self.on_reject(
-            ref_seq_num,
-            Some(SENDING_TIME),
-            Some(ref_msg_type.as_bytes()),
-            SENDING_TIME_ACCURACY_PROBLEM,
-            "Bad SendingTime".to_string(),
-        )
+    /// Returns `true` if `msg` can be sent right away at `now`, and `false`
+    /// if it must wait for the throttle.
+    fn admit(&mut self, msg: &OutboundMessage, now: Instant) -> Result<bool, SendError> {
+        if msg.is_session_message() {
+            return Err(SendError::SessionMessage);
+        }
+        if !self.config.session_mode().allows_application_messages() {
+            return Err(SendError::ReadOnly);
+        }
+        if !self.state.is_logged_on() {
+            return Err(SendError::NotLoggedOn);
+        }
+        if let Some(throttler) = self.throttler.as_mut() {
+            let wait = throttler.wait(msg.msg_type(), now);
+            if !wait.is_zero() || !self.throttled.is_empty() {
+                return match throttler.when_exceeded() {
+                    WhenThrottled::Queue => Ok(false),
+                    WhenThrottled::Reject => Err(SendError::Throttled { retry_after: wait }),
+                };
+            }
+            throttler.take(msg.msg_type());
+        }
+        Ok(true)
     }
 
-    fn make_logout(&mut self, text: String) -> Response {
-        let fix_message = {
-            let begin_string = self.begin_string();
-            let sender_comp_id = self.sender_comp_id();
-            let target_comp_id = self.target_comp_id();
-            let msg_seq_num = self.msg_seq_num_outbound.next();
-            let mut msg = self.start_message(begin_string, b"5");
-            self.set_sender_and_target(&mut msg);
-            msg.set_fv_with_key(&MSG_SEQ_NUM, msg_seq_num);
-            msg.set_fv_with_key(&TEXT, text.as_str());
-            self.set_sending_time(&mut msg);
-            msg.done()
-        };
-        Response::OutboundBytes(fix_message)
+    /// Sends as many throttled messages as allowed at `now`.
+    fn send_throttled(&mut self, now: Instant) {
+        while let (Some(throttler), Some((msg, _))) =
+            (self.throttler.as_mut(), self.throttled.front())
+        {
+            if !throttler.wait(msg.msg_type(), now).is_zero() {
+                break;
+            }
+            throttler.take(msg.msg_type());
+            if let Some((msg, completer)) = self.throttled.pop_front() {
+                let offset = self.builder.as_bytes().len();
+                let msg_seq_num = self.make_app_message(&msg);
+                if let Some(completer) = completer {
+                    self.sent.push((completer, msg_seq_num, offset));
+                }
+            }
+        }
+    }
+
+    /// Resolves the [`Completion`](super::Completion)s of all messages that
+    /// have been written.
+    fn complete_sent(&mut self) {
+        for (completer, msg_seq_num, _) in self.sent.drain(..) {
+            completer.send(Ok(msg_seq_num)).ok();
+        }
     }
 
-    fn make_resend_request(&mut self, start: u64, end: u64) -> Response {
-        let begin_string = self.begin_string();
-        let mut msg = self.start_message(begin_string, b"2");
-        //Self::add_comp_id(msg);
-        //self.add_sending_time(msg);
-        //self.add_seqnum(msg);
-        msg.set_fv_with_key(&BEGIN_SEQ_NO, start);
-        msg.set_fv_with_key(&END_SEQ_NO, end);
-        Response::OutboundBytes(msg.done())
+    /// Starts the `Logout <5>` handshake at `now`. `text` is sent as `Text
+    /// <58>`, with a generic default.
+    ///
+    /// Inbound messages are still processed (e.g. resend requests are
+    /// honored) until the counterparty confirms with its own `Logout <5>`, or
+    /// until [`Configure::logout_timeout`] expires. Either way, the transport
+    /// is then closed. Before the FIX session is established, the transport is
+    /// closed right away.
+    pub fn logout(&mut self, text: Option<&str>, now: Instant) -> Vec<Action> {
+        self.set_now(now);
+        if !self.state.is_logged_on() {
+            let disconnect = match self.state {
+                SessionState::LogoutSent => None,
+                _ => Some(Ok(())),
+            };
+            return self.collect_actions(now, disconnect);
+        }
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"5", msg_seq_num);
+        let text = text.map_or_else(errs::logout_requested, str::to_string);
+        msg.set(TEXT, text.as_str());
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.emit(SessionEvent::LogoutSent { text });
+        self.set_state(SessionState::LogoutSent);
+        self.logout_deadline = Some(now + self.config.logout_timeout());
+        self.collect_actions(now, None)
     }
 
-    fn on_high_seqnum(&mut self, msg: Message<&[u8]>) -> Response {
-        let msg_seq_num = msg.get(&MSG_SEQ_NUM).unwrap();
-        self.make_resend_request(self.seq_numbers().next_inbound(), msg_seq_num);
-        todo!()
+    /// Starts an intraday reset of seq. numbers at `now`, by sending a `Logon
+    /// <A>` with `ResetSeqNumFlag <141>=Y`. Outbound seq. numbers restart
+    /// from 1 right away, and inbound seq. numbers once the counterparty
+    /// confirms with its own `Logon <A>`. Does nothing unless logged on.
+    pub fn reset_seq_numbers(&mut self, now: Instant) -> Vec<Action> {
+        self.set_now(now);
+        if self.state.is_logged_on() {
+            self.reset_stored_messages();
+            self.msg_seq_num_outbound = MsgSeqNumCounter::START;
+            self.reset_sent = true;
+            self.on_logon_is_due(self.heartbeat(), true, 1);
+        }
+        self.collect_actions(now, None)
     }
 
-    fn on_logon(&mut self, _logon: Message<&[u8]>) {
-        let begin_string = self.begin_string();
-        let mut _msg = self.start_message(begin_string, b"A");
-        //Self::add_comp_id(msg);
-        //self.add_sending_time(msg);
-        //self.add_sending_time(msg);
+    /// Fires all timers that are due at `now`, i.e. our own `Heartbeat <0>`,
+    /// the counterparty's tolerances and the `Logout <5>` timeout.
+    pub fn on_tick(&mut self, now: Instant) -> Vec<Action> {
+        self.set_now(now);
+        if self.session_end.is_some_and(|end| now >= end) {
+            self.session_end = None;
+            if self.state.is_logged_on() {
+                return self.logout(Some(&errs::end_of_session()), now);
+            } else if self.state != SessionState::LogoutSent {
+                return self.collect_actions(now, Some(Ok(())));
+            }
+        }
+        if self.logout_deadline.is_some_and(|deadline| now >= deadline) {
+            // The counterparty never confirmed our `Logout <5>`.
+            return self.collect_actions(now, Some(Err(ConnectionError::Timeout)));
+        }
+        if self.state.is_logged_on() {
+            self.send_throttled(now);
+        }
+        let mut disconnect = None;
+        while let Some(event) = self.timers.as_mut().and_then(|timers| timers.poll(now)) {
+            match event {
+                TimerEvent::Heartbeat => {
+                    if self.state.is_logged_on() {
+                        self.on_heartbeat_is_due();
+                    }
+                }
+                TimerEvent::TestRequest => {
+                    if self.state.is_logged_on() {
+                        self.make_test_request(now);
+                    }
+                }
+                TimerEvent::Logout => {
+                    // There's no FIX session to log out of until the
+                    // handshake is over.
+                    if self.state.is_logged_on() {
+                        self.emit(SessionEvent::HeartbeatTimeout);
+                        self.make_logout(errs::heartbeat_timeout());
+                    }
+                    disconnect = Some(Err(ConnectionError::Timeout));
+                    break;
+                }
+            }
+        }
+        self.collect_actions(now, disconnect)
     }
 
-    fn on_application_message(&mut self, msg: Message<'a, &'a [u8]>) -> Response<'a> {
-        Response::Application(msg)
+    /// Notifies `self` that the transport has been closed, by either side.
+    pub fn on_disconnected(&mut self) {
+        self.role = None;
+        self.decoder = None;
+        self.timers = None;
+        self.logout_deadline = None;
+        self.session_end = None;
+        self.test_req_id = None;
+        self.test_req_sent_at = None;
+        self.reset_sent = false;
+        self.clock = None;
+        // Whatever wasn't written resolves to `SendError::Disconnected`.
+        self.sent.clear();
+        // The counterparty will have to resend everything once again.
+        self.resend_requested_up_to = None;
+        self.queue.clear();
+        self.set_state(SessionState::Disconnected);
     }
-}
 
-//fn add_time_to_msg(mut msg: EncoderHandle) {
-//    // https://www.onixs.biz/fix-dictionary/4.4/index.html#UTCTimestamp.
-//    let time = chrono::Utc::now();
-//    let timestamp = time.format("%Y%m%d-%H:%M:%S.%.3f");
-//    msg.set_fv_with_key(fix44::SENDING_TIME, timestamp.to_string().as_str());
-//}
-
-//#[cfg(test)]
-//mod test {
-//    use super::*;
-//    use std::time::Duration;
-//
-//    fn conn() -> FixConnection {
-//        let builder = FixConnectionBuilder {
-//            environment: Environment::ProductionDisallowTest,
-//            heartbeat: Duration::from_secs(30),
-//            seq_numbers: SeqNumbers::default(),
-//            sender_comp_id: "SENDER".to_string(),
-//            target_comp_id: "TARGET".to_string(),
-//        };
-//        builder.build()
-//    }
-//
-//    #[test]
-//    fn on_heartbeat_is_due() {
-//        let conn = &mut conn();
-//        let responses = &mut conn.on_heartbeat_is_due();
-//        let next = responses.next().unwrap();
-//        let msg = next.as_outbound().unwrap();
-//        assert_eq!(msg.field_str(tags::MSG_TYPE), Some("0"));
-//        assert_eq!(msg.field_str(tags::SENDER_COMP_ID), Some("SENDER"));
-//        assert_eq!(msg.field_str(tags::TARGET_COMP_ID), Some("TARGET"));
-//        assert_eq!(msg.field_bool(tags::POSS_DUP_FLAG), None);
-//        assert_eq!(msg.field_i64(tags::TEST_REQ_ID), None);
-//        assert!(responses.next().is_none());
-//    }
-//
-//    #[test]
-//    fn terminate_transport_when_error() {
-//        let conn = &mut conn();
-//        let responses = &mut conn.on_transport_error();
-//        let next = responses.next().unwrap();
-//        assert!(next.as_terminate_transport().is_some());
-//    }
-//
-//    #[test]
-//    fn inaccurate_sending_time() {
-//        let conn = &mut conn();
-//        let mut msg = FixMessage::new();
-//        msg.add_str(tags::MSG_TYPE, "BE");
-//        msg.add_str(tags::SENDER_COMP_ID, "SENDER");
-//        msg.add_str(tags::TARGET_COMP_ID, "TARGET");
-//        msg.add_i64(tags::MSG_SEQ_NUM, 1);
-//        msg.add_str(
-//            tags::USER_REQUEST_ID,
-//            "47b6f4a6-993d-4430-b68f-d9b680a1a772",
-//        );
-//        msg.add_i64(tags::USER_REQUEST_TYPE, 1);
-//        msg.add_str(tags::USERNAME, "john-doe");
-//        let mut responses = conn.on_inbound_message(msg);
-//        let next = responses.next().unwrap();
-//        let msg = next.as_outbound().unwrap();
-//        assert_eq!(msg.field_str(tags::MSG_TYPE), Some("3"));
-//        assert_eq!(msg.field_str(tags::SENDER_COMP_ID), Some("SENDER"));
-//        assert_eq!(msg.field_str(tags::TARGET_COMP_ID), Some("TARGET"));
-//        assert_eq!(msg.field_bool(tags::POSS_DUP_FLAG), None);
-//        assert_eq!(msg.field_i64(tags::TEST_REQ_ID), None);
-//        assert_eq!(msg.field_i64(tags::SESSION_REJECT_REASON), Some(10));
-//        assert_eq!(msg.field_i64(tags::REF_SEQ_NUM), Some(10));
-//        assert!(responses.next().is_none());
-//    }
-//}
+    /// Decodes and processes all whole messages in `bytes`. Returns
+    /// [`Some`] if the transport must be closed.
+    fn decode_inbound(
+        &mut self,
+        decoder: &mut DecoderStreaming<Vec<u8>>,
+        bytes: &mut &[u8],
+        now: Instant,
+    ) -> Option<Result<(), ConnectionError>> {
+        loop {
+            match next_message(decoder, bytes) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) if self.on_invalid_message(decoder, &err) => {
+                    if let DecodeError::BodyLength { .. } = err {
+                        skip_to_begin_string(bytes, decoder.config().separator);
+                    }
+                    decoder.clear();
+                    continue;
+                }
+                Err(err) => return Some(Err(err.into())),
+            }
+            self.metrics.messages_in += 1;
+            if let Some(log) = self.log.as_mut() {
+                log.on_incoming(decoder.message().as_bytes()).ok();
+            }
+            let disconnect = self.on_decoded_message(decoder.message(), now);
+            decoder.clear();
+            if disconnect.is_some() {
+                return disconnect;
+            }
+            self.sync_appl_ver_id(decoder);
+            while let Some(queued) = self.pop_queued_message() {
+                match next_message(decoder, &mut &queued[..]) {
+                    Ok(true) => {}
+                    Ok(false) => return Some(Err(DecodeError::Invalid.into())),
+                    Err(err) => return Some(Err(err.into())),
+                }
+                let disconnect = self.on_decoded_message(decoder.message(), now);
+                decoder.clear();
+                if disconnect.is_some() {
+                    return disconnect;
+                }
+            }
+        }
+    }
+
+    /// Rejects the message buffered in `decoder`, which failed to decode with
+    /// `err`, if `err` has a matching `SessionRejectReason <373>` and the
+    /// message is the next one we expect. Garbled messages are ignored
+    /// instead, as if they had never been received. Returns `false` if the
+    /// message can be neither rejected nor ignored, in which case the
+    /// connection is broken.
+    fn on_invalid_message(
+        &mut self,
+        decoder: &mut DecoderStreaming<Vec<u8>>,
+        err: &DecodeError,
+    ) -> bool {
+        if let DecodeError::CheckSum { .. } | DecodeError::BodyLength { .. } = err {
+            let text = err.to_string();
+            self.emit(SessionEvent::GarbledMessageIgnored { text });
+            return true;
+        }
+        let reason = match SessionRejectReason::for_decode_error(err) {
+            Some(reason) if self.state == SessionState::Active => reason,
+            _ => return false,
+        };
+        let separator = decoder.config().separator;
+        let raw = decoder.buffer().as_slice();
+        let msg_seq_num = raw_field(raw, separator, MSG_SEQ_NUM)
+            .and_then(|value| std::str::from_utf8(value).ok()?.parse::<u64>().ok());
+        if msg_seq_num != Some(self.msg_seq_num_inbound.expected()) {
+            return false;
+        }
+        let reject = Reject {
+            ref_seq_num: self.msg_seq_num_inbound.next(),
+            ref_tag_id: match err {
+                DecodeError::Field { tag, .. } => *tag,
+                _ => None,
+            },
+            ref_msg_type: raw_field(raw, separator, MSG_TYPE).map(<[u8]>::to_vec),
+            reason,
+            text: err.to_string(),
+        };
+        self.metrics.messages_in += 1;
+        if let Some(log) = self.log.as_mut() {
+            log.on_incoming(raw).ok();
+        }
+        self.on_reject(reject);
+        true
+    }
+
+    /// Lets `decoder` know about the current
+    /// [`FixConnection::default_appl_ver_id`], which may have been changed by
+    /// a `Logon <A>`.
+    fn sync_appl_ver_id(&self, decoder: &mut DecoderStreaming<Vec<u8>>) {
+        if decoder.default_appl_ver_id() != self.default_appl_ver_id() {
+            decoder.set_default_appl_ver_id(self.default_appl_ver_id());
+        }
+    }
+
+    fn on_decoded_message(
+        &mut self,
+        msg: Message<&[u8]>,
+        now: Instant,
+    ) -> Option<Result<(), ConnectionError>> {
+        self.received_at = self.utc_at(now);
+        let response = match (self.state, self.role) {
+            (SessionState::LogonSent, _) => self
+                .on_logon_response(msg)
+                .map(|heartbeat| self.on_logged_on(heartbeat)),
+            (SessionState::Disconnected, Some(Role::Acceptor)) => self
+                .on_logon_request(msg)
+                .map(|heartbeat| self.on_logged_on(heartbeat)),
+            _ => Ok(self.on_inbound_message(msg)),
+        };
+        match response {
+            Ok(Response::None) => None,
+            Ok(Response::ResetHeartbeat) => {
+                // While a `TestRequest <1>` is outstanding, only the
+                // `Heartbeat <0>` that echoes it is proof of life.
+                if let (Some(timers), None) = (self.timers.as_mut(), &self.test_req_id) {
+                    timers.on_inbound(now);
+                }
+                None
+            }
+            Ok(Response::TerminateTransport) => Some(Ok(())),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// Makes `now` the `SendingTime <52>` of outbound messages, until the
+    /// next call to [`FixConnection::collect_actions`].
+    fn set_now(&mut self, now: Instant) {
+        self.builder.set_sending_time(self.utc_at(now));
+    }
+
+    /// Returns the wall-clock time of `now`, according to the clock of
+    /// [`FixConnection::on_connected`].
+    fn utc_at(&self, now: Instant) -> Option<DateTime<Utc>> {
+        self.clock.map(|(then, utc_then)| {
+            let elapsed = now.saturating_duration_since(then);
+            utc_then + chrono::Duration::from_std(elapsed).unwrap_or_default()
+        })
+    }
+
+    fn on_logged_on(&mut self, heartbeat: Duration) -> Response {
+        self.was_logged_on = true;
+        if let Some(timers) = self.timers.as_mut() {
+            timers.set_heartbeat(heartbeat);
+        }
+        self.set_state(SessionState::Active);
+        if self.config.replay_on_logon() {
+            self.request_replay();
+        }
+        self.update_logged_on_state();
+        self.backend.on_successful_handshake().ok();
+        self.emit(SessionEvent::LoggedOn);
+        Response::ResetHeartbeat
+    }
+
+    /// Asks the counterparty to resend everything from `MsgSeqNum <34>=1`.
+    /// Messages received so far will be received once again.
+    fn request_replay(&mut self) {
+        let received_up_to = self.msg_seq_num_inbound.expected() - 1;
+        if received_up_to == 0 {
+            return;
+        }
+        self.msg_seq_num_inbound.set_expected(1);
+        self.resend_requested_up_to = Some(
+            self.resend_requested_up_to
+                .map_or(received_up_to, |up_to| up_to.max(received_up_to)),
+        );
+        self.make_resend_request(ResendRequestRange::new(1, None));
+    }
+
+    /// Turns pending outbound messages and the next timer deadline into
+    /// [`Action`]s. After a [`MessageStore`] failure, only the messages that
+    /// were persisted before it are written, and the transport is closed.
+    fn collect_actions(
+        &mut self,
+        now: Instant,
+        mut disconnect: Option<Result<(), ConnectionError>>,
+    ) -> Vec<Action> {
+        self.persist_seq_numbers();
+        if let Some(failure) = self.store_failure.take() {
+            self.builder.truncate(failure.offset);
+            // Messages past the failure won't be written.
+            let (unsent, sent) = std::mem::take(&mut self.sent)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(_, _, offset)| *offset >= failure.offset);
+            self.sent = sent;
+            for (completer, _, _) in unsent {
+                completer.send(Err(SendError::Disconnected)).ok();
+            }
+            disconnect = Some(Err(ConnectionError::Store(failure.error)));
+        }
+        let mut actions = vec![];
+        if !self.builder.is_empty() {
+            self.metrics
+                .record_outbound(self.builder.len(), self.builder.as_bytes());
+            if let Some(log) = self.log.as_mut() {
+                let mut bytes = self.builder.as_bytes();
+                while let Some(len) = message_len(bytes) {
+                    log.on_outgoing(&bytes[..len]).ok();
+                    bytes = &bytes[len..];
+                }
+            }
+            actions.push(Action::Write(self.builder.as_bytes().to_vec()));
+            self.builder.clear();
+            if let Some(timers) = self.timers.as_mut() {
+                timers.on_outbound(now);
+            }
+        }
+        match disconnect {
+            Some(result) => actions.push(Action::Disconnect(result)),
+            None => {
+                let throttled_until = match (self.throttler.as_mut(), self.throttled.front()) {
+                    (Some(throttler), Some((msg, _))) if self.state.is_logged_on() => {
+                        now.checked_add(throttler.wait(msg.msg_type(), now))
+                    }
+                    _ => None,
+                };
+                if let Some(timers) = &self.timers {
+                    let deadline = [
+                        timers.next_deadline(),
+                        self.logout_deadline,
+                        self.session_end,
+                        throttled_until,
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .min();
+                    actions.extend(deadline.map(Action::ArmTimer));
+                }
+            }
+        }
+        self.builder.set_sending_time(None);
+        actions
+    }
+
+    /// Processes an inbound FIX message. Any response messages are
+    /// accumulated in [`FixConnection::pending_outbound`].
+    ///
+    /// Unlike [`FixConnection::on_bytes`], this reads the system clock to
+    /// verify `SendingTime <52>` and to timestamp responses.
+    pub fn on_inbound_message(&mut self, msg: Message<&[u8]>) -> Response {
+        let response = self.process_inbound_message(msg);
+        self.persist_seq_numbers();
+        self.update_logged_on_state();
+        response
+    }
+
+    fn process_inbound_message(&mut self, msg: Message<&[u8]>) -> Response {
+        let received_at = self.received_at.take().unwrap_or_else(Utc::now);
+        if msg.get_raw(MSG_TYPE).is_none() {
+            // Garbled messages must be ignored, without incrementing the
+            // inbound seq. number. See §4.5.3.
+            return Response::None;
+        }
+        let begin_string = msg.get_raw(BEGIN_STRING).unwrap_or_default();
+        if self.verifier.verify_begin_string(begin_string).is_err() {
+            return self.make_logout(errs::begin_string(self.config.begin_string()));
+        }
+        if self.verifier.verify_test_message_indicator(&msg).is_err() {
+            return self.on_wrong_environment(msg);
+        }
+        if msg.get_raw(MSG_TYPE) == Some(b"A") && msg.get_opt(RESET_SEQ_NUM_FLAG) == Ok(Some(true))
+        {
+            // A new sequence begins with this very message.
+            return self.on_reset_logon(msg);
+        }
+        if msg.get_raw(MSG_TYPE) == Some(b"4") && msg.get_opt(GAP_FILL_FLAG) != Ok(Some(true)) {
+            // `SequenceReset <4>` in Reset mode ignores `MsgSeqNum <34>`. See
+            // §4.8.
+            return self.on_sequence_reset(msg);
+        }
+        let msg_seq_num = if let Ok(n) = msg.get::<u64>(MSG_SEQ_NUM) {
+            n
+        } else {
+            // See §4.5.3.
+            return self.on_missing_seqnum(msg);
+        };
+        let expected = self.msg_seq_num_inbound.expected();
+        if msg_seq_num < expected {
+            return self.on_low_seqnum(msg);
+        } else if msg_seq_num > expected {
+            // Refer to specs. §4.8 for more information.
+            return self.on_high_seqnum(msg);
+        }
+
+        // Increment immediately.
+        self.msg_seq_num_inbound.next();
+        if self
+            .resend_requested_up_to
+            .is_some_and(|up_to| msg_seq_num >= up_to)
+        {
+            self.resend_requested_up_to = None;
+        }
+
+        if self.verifier.verify_comp_ids(&msg).is_err() {
+            return self.on_comp_id_problem(msg);
+        }
+        if self
+            .verifier
+            .verify_sending_time(&msg, received_at)
+            .is_err()
+        {
+            return self.on_timestamp_problem(msg, "SendingTime", SENDING_TIME);
+        }
+        if self.verifier.verify_orig_sending_time(&msg).is_err() {
+            return self.on_timestamp_problem(msg, "OrigSendingTime", ORIG_SENDING_TIME);
+        }
+
+        match msg.get_raw(MSG_TYPE).unwrap_or_default() {
+            b"A" => self.on_logon(msg),
+            b"0" => self.on_heartbeat(msg, received_at),
+            b"1" => self.on_test_request(msg),
+            b"2" => self.on_resend_request(msg),
+            b"5" => self.on_logout(msg),
+            b"4" => self.on_sequence_reset(msg),
+            b"3" => self.on_inbound_reject(msg),
+            _ => self.on_application_message(msg),
+        }
+    }
+
+    /// Starts a new FIX session from scratch, i.e. with empty
+    /// [`MessageStore`] and seq. numbers.
+    fn reset_store(&mut self) {
+        self.reset_stored_messages();
+        self.msg_seq_num_inbound = MsgSeqNumCounter::START;
+        self.msg_seq_num_outbound = MsgSeqNumCounter::START;
+        self.resend_requested_up_to = None;
+        self.queue.clear();
+    }
+
+    /// Writes a `Heartbeat <0>` message.
+    pub fn on_heartbeat_is_due(&mut self) {
+        self.backend.on_heartbeat_is_due().ok();
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let msg = self
+            .builder
+            .start_message_with_header(&self.config, b"0", msg_seq_num);
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.persist_seq_numbers();
+    }
+
+    /// Resets the [`MessageStore`]. Nothing is written after a failure.
+    fn reset_stored_messages(&mut self) {
+        if let Err(err) = self.store.reset() {
+            let offset = self.builder.as_bytes().len();
+            StoreFailure::record(&mut self.store_failure, offset, err);
+        }
+    }
+
+    /// Saves the current seq. numbers to the [`MessageStore`]. If that fails,
+    /// no pending outbound message is written: their seq. numbers would be
+    /// reused after a restart.
+    fn persist_seq_numbers(&mut self) {
+        if self.store_failure.is_some() {
+            return;
+        }
+        let seq_numbers = self.seq_numbers();
+        let persisted = self.store.seq_numbers();
+        if persisted.next_inbound() != seq_numbers.next_inbound()
+            || persisted.next_outbound() != seq_numbers.next_outbound()
+        {
+            if let Err(err) = self.store.set_seq_numbers(seq_numbers) {
+                StoreFailure::record(&mut self.store_failure, 0, err);
+            }
+        }
+    }
+
+    /// Returns the current `HeartBtInt <108>`, which might have been
+    /// negotiated via `Logon <A>`.
+    fn heartbeat(&self) -> Duration {
+        self.timers
+            .as_ref()
+            .map_or_else(|| self.config.heartbeat(), HeartbeatTimers::heartbeat)
+    }
+
+    /// Writes our `Logon <A>`, which resets seq. numbers if `reset` is `true`.
+    /// `next_expected` is only sent if
+    /// [`Configure::enable_next_expected_msg_seq_num`].
+    fn on_logon_is_due(&mut self, heartbeat: Duration, reset: bool, next_expected: u64) {
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"A", msg_seq_num);
+        msg.set(ENCRYPT_METHOD, 0);
+        msg.set(HEART_BT_INT, heartbeat.as_secs());
+        if reset {
+            msg.set(RESET_SEQ_NUM_FLAG, true);
+        }
+        if self.config.enable_next_expected_msg_seq_num() {
+            msg.set(NEXT_EXPECTED_MSG_SEQ_NUM, next_expected);
+        }
+        if let Some(appl_ver_id) = self.config.default_appl_ver_id() {
+            msg.set(DEFAULT_APPL_VER_ID, appl_ver_id);
+        }
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+    }
+
+    /// Returns the `NextExpectedMsgSeqNum <789>` of a `Logon <A>`, unless
+    /// it's disabled by [`Configure::enable_next_expected_msg_seq_num`].
+    fn next_expected_msg_seq_num(&self, logon: Message<&[u8]>) -> Option<u64> {
+        if !self.config.enable_next_expected_msg_seq_num()
+            || logon.get_opt(RESET_SEQ_NUM_FLAG) == Ok(Some(true))
+        {
+            return None;
+        }
+        logon.get(NEXT_EXPECTED_MSG_SEQ_NUM).ok()
+    }
+
+    fn on_logon_response(&mut self, logon: Message<&[u8]>) -> Result<Duration, ConnectionError> {
+        match logon.get_raw(MSG_TYPE) {
+            Some(b"A") => {}
+            Some(b"5") => {
+                let text = logon.get::<&str>(TEXT).unwrap_or("Logout <5>");
+                return Err(ConnectionError::Logon(text.to_string()));
+            }
+            _ => {
+                return Err(ConnectionError::Logon(errs::logon_expected()));
+            }
+        }
+        // Nothing but our `Logon <A>` has been sent so far.
+        let own_logon = self.msg_seq_num_outbound.expected() - 1;
+        if let Response::TerminateTransport = self.on_inbound_message(logon) {
+            return Err(ConnectionError::Logon(errs::logon_invalid()));
+        }
+        if let Some(next_expected) = self.next_expected_msg_seq_num(logon) {
+            if next_expected > own_logon + 1 {
+                let text = errs::inbound_seqnum();
+                self.make_logout(text.clone());
+                return Err(ConnectionError::Logon(text));
+            } else if next_expected < own_logon {
+                self.resend(next_expected..own_logon);
+            }
+        }
+        Ok(self.config.heartbeat())
+    }
+
+    fn on_logon_request(&mut self, logon: Message<&[u8]>) -> Result<Duration, ConnectionError> {
+        if logon.get_raw(MSG_TYPE) != Some(b"A") {
+            // Anything but a `Logon <A>` is dropped without a reply.
+            return Err(ConnectionError::Logon(errs::logon_expected()));
+        }
+        self.set_state(SessionState::LogonReceived);
+        let reset_requested = logon.get_opt(RESET_SEQ_NUM_FLAG) == Ok(Some(true));
+        let reset = reset_requested || self.config.reset_on_logon();
+        match self.validate_logon_request(logon, reset) {
+            Ok(heartbeat) => {
+                if reset {
+                    self.reset_store();
+                }
+                let msg_seq_num = logon.get::<u64>(MSG_SEQ_NUM).unwrap_or_default();
+                let expected = self.msg_seq_num_inbound.expected();
+                let next_expected = if reset_requested || msg_seq_num == expected {
+                    msg_seq_num + 1
+                } else {
+                    expected
+                };
+                let own_logon = self.msg_seq_num_outbound.expected();
+                // Our `Logon <A>` must go out before any `ResendRequest <2>`
+                // triggered by the counterparty's.
+                self.on_logon_is_due(heartbeat, reset, next_expected);
+                if let Response::TerminateTransport = self.on_inbound_message(logon) {
+                    return Err(ConnectionError::Logon(errs::logon_invalid()));
+                }
+                match self.next_expected_msg_seq_num(logon) {
+                    Some(next_expected) if next_expected < own_logon => {
+                        self.resend(next_expected..own_logon);
+                    }
+                    _ => {}
+                }
+                Ok(heartbeat)
+            }
+            Err(text) => {
+                self.make_logout(text.clone());
+                Err(ConnectionError::Logon(text))
+            }
+        }
+    }
+
+    /// Validates the `Logon <A>` of an initiator. Returns the proposed
+    /// heartbeat interval, or the `Text <58>` of the `Logout <5>` to send back.
+    /// Seq. numbers are not checked if they're about to be `reset`.
+    fn validate_logon_request(
+        &self,
+        logon: Message<&[u8]>,
+        reset: bool,
+    ) -> Result<Duration, String> {
+        let begin_string = logon.get_raw(BEGIN_STRING).unwrap_or_default();
+        if self.verifier.verify_begin_string(begin_string).is_err() {
+            return Err(errs::begin_string(self.config.begin_string()));
+        }
+        if logon.get_raw(SENDER_COMP_ID) != Some(self.config.target_comp_id()) {
+            return Err(errs::sender_comp_id(self.config.target_comp_id()));
+        }
+        if logon.get_raw(TARGET_COMP_ID) != Some(self.config.sender_comp_id()) {
+            return Err(errs::target_comp_id(self.config.sender_comp_id()));
+        }
+        if self.verifier.verify_test_message_indicator(&logon).is_err() {
+            return Err(self.wrong_environment_text());
+        }
+        let received_at = self.received_at.unwrap_or_else(Utc::now);
+        if self
+            .verifier
+            .verify_sending_time(&logon, received_at)
+            .is_err()
+        {
+            return Err(match logon.get_raw(SENDING_TIME) {
+                None => errs::missing_field("SendingTime", SENDING_TIME),
+                Some(_) => errs::sending_time_accuracy("SendingTime", SENDING_TIME),
+            });
+        }
+        let expected = self.msg_seq_num_inbound.expected();
+        match logon.get::<u64>(MSG_SEQ_NUM) {
+            Ok(msg_seq_num) if msg_seq_num < expected && !reset => {
+                return Err(errs::msg_seq_num(expected));
+            }
+            Ok(msg_seq_num) if msg_seq_num.checked_add(1).is_none() => {
+                return Err(errs::msg_seq_num(if reset { 1 } else { expected }));
+            }
+            Ok(_) => {}
+            Err(_) => {
+                return Err(errs::missing_field("MsgSeqNum", MSG_SEQ_NUM));
+            }
+        }
+        let next_expected = self.next_expected_msg_seq_num(logon);
+        if next_expected.is_some_and(|n| n > self.msg_seq_num_outbound.expected() && !reset) {
+            return Err(errs::inbound_seqnum());
+        }
+        if begin_string == FIXT_11 && logon.get_raw(DEFAULT_APPL_VER_ID).is_none() {
+            return Err(errs::missing_field("DefaultApplVerID", DEFAULT_APPL_VER_ID));
+        }
+        let heartbeat = logon
+            .get::<u64>(HEART_BT_INT)
+            .map(Duration::from_secs)
+            .map_err(|_| errs::missing_field("HeartBtInt", HEART_BT_INT))?;
+        self.config.heartbeat_rule().validate(&heartbeat)?;
+        Ok(heartbeat)
+    }
+
+    fn on_logon(&mut self, logon: Message<&[u8]>) -> Response {
+        if let Some(appl_ver_id) = logon.get_raw(DEFAULT_APPL_VER_ID) {
+            self.counterparty_appl_ver_id = Some(appl_ver_id.to_vec());
+        }
+        self.backend.on_inbound_message(logon, false).ok();
+        Response::ResetHeartbeat
+    }
+
+    /// Processes a `Logon <A>` with `ResetSeqNumFlag <141>=Y`. Intraday
+    /// resets requested by the counterparty are confirmed with our own.
+    fn on_reset_logon(&mut self, logon: Message<&[u8]>) -> Response {
+        let next_expected = match logon.get::<u64>(MSG_SEQ_NUM).unwrap_or(1).checked_add(1) {
+            Some(next_expected) => next_expected,
+            None => return self.make_logout(errs::msg_seq_num(1)),
+        };
+        self.msg_seq_num_inbound.set_expected(next_expected);
+        self.resend_requested_up_to = None;
+        self.queue.clear();
+        if self.state.is_logged_on() && !std::mem::take(&mut self.reset_sent) {
+            self.reset_stored_messages();
+            self.msg_seq_num_outbound = MsgSeqNumCounter::START;
+            self.on_logon_is_due(self.heartbeat(), true, next_expected);
+        }
+        self.on_logon(logon)
+    }
+
+    fn on_heartbeat(&mut self, heartbeat: Message<&[u8]>, received_at: DateTime<Utc>) -> Response {
+        self.backend.on_inbound_message(heartbeat, false).ok();
+        if let Some(test_req_id) = &self.test_req_id {
+            if heartbeat.get_raw(TEST_REQ_ID) == Some(test_req_id.as_bytes()) {
+                self.test_req_id = None;
+                if let Some(sent_at) = self.test_req_sent_at.take() {
+                    let latency = (received_at - sent_at).to_std().unwrap_or_default();
+                    self.emit(SessionEvent::TestRequestAnswered { latency });
+                }
+            }
+        }
+        Response::ResetHeartbeat
+    }
+
+    fn on_test_request(&mut self, test_request: Message<&[u8]>) -> Response {
+        self.backend.on_inbound_message(test_request, false).ok();
+        let test_req_id = test_request.get_raw(TEST_REQ_ID);
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"0", msg_seq_num);
+        if let Some(test_req_id) = test_req_id {
+            msg.set(TEST_REQ_ID, test_req_id);
+        }
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        Response::ResetHeartbeat
+    }
+
+    fn on_resend_request(&mut self, resend_request: Message<&[u8]>) -> Response {
+        self.backend.on_inbound_message(resend_request, false).ok();
+        let seq_nos = resend_request
+            .get::<u64>(BEGIN_SEQ_NO)
+            .map_err(|err| Reject::for_field_error(&resend_request, BEGIN_SEQ_NO, &err))
+            .and_then(|begin_seq_no| {
+                resend_request
+                    .get::<u64>(END_SEQ_NO)
+                    .map(|end_seq_no| (begin_seq_no, end_seq_no))
+                    .map_err(|err| Reject::for_field_error(&resend_request, END_SEQ_NO, &err))
+            });
+        match seq_nos {
+            Ok((begin_seq_no, end_seq_no)) => {
+                let range = ResendRequestRange::from_seq_nos(begin_seq_no, end_seq_no)
+                    .to_range(self.msg_seq_num_outbound.expected());
+                if begin_seq_no == 0 || range.is_empty() {
+                    let reject =
+                        Reject::new(&resend_request, SessionRejectReason::ValueIsIncorrect)
+                            .with_ref_tag_id(BEGIN_SEQ_NO)
+                            .with_text(errs::begin_seq_no(range.end.saturating_sub(1)));
+                    return self.on_reject(reject);
+                }
+                self.emit(SessionEvent::ResendRequestReceived(range.clone()));
+                self.backend.on_resend_request(range.clone()).ok();
+                self.resend(range);
+                Response::ResetHeartbeat
+            }
+            Err(reject) => self.on_reject(reject),
+        }
+    }
+
+    /// Resends all stored messages within `range`, replacing administrative
+    /// and missing messages with `SequenceReset <4>` in GapFill mode. See
+    /// §4.8.
+    fn resend(&mut self, range: Range<u64>) {
+        let stored = match self.store.fetch_outbound(range.clone()) {
+            Ok(stored) => stored,
+            Err(err) => {
+                let offset = self.builder.as_bytes().len();
+                StoreFailure::record(&mut self.store_failure, offset, err);
+                return;
+            }
+        };
+        let mut gap_start = None;
+        let mut next = range.start;
+        for (msg_seq_num, message) in stored {
+            if msg_seq_num > next {
+                gap_start.get_or_insert(next);
+            }
+            next = msg_seq_num + 1;
+            match ResendableMessage::parse(&message) {
+                Some(resendable) => {
+                    if let Some(start) = gap_start.take() {
+                        self.make_gap_fill(start, msg_seq_num);
+                    }
+                    self.make_possible_duplicate(msg_seq_num, resendable);
+                }
+                None => {
+                    gap_start.get_or_insert(msg_seq_num);
+                }
+            }
+        }
+        if range.end > next {
+            gap_start.get_or_insert(next);
+        }
+        if let Some(start) = gap_start {
+            self.make_gap_fill(start, range.end);
+        }
+    }
+
+    fn on_sequence_reset(&mut self, sequence_reset: Message<&[u8]>) -> Response {
+        self.backend.on_inbound_message(sequence_reset, false).ok();
+        let expected = self.msg_seq_num_inbound.expected();
+        let new_seq_no = match sequence_reset.get::<u64>(NEW_SEQ_NO) {
+            Ok(n) => n,
+            Err(err) => {
+                let reject = Reject::for_field_error(&sequence_reset, NEW_SEQ_NO, &err);
+                return self.on_reject(reject);
+            }
+        };
+        if new_seq_no < expected {
+            let reject = Reject::new(&sequence_reset, SessionRejectReason::ValueIsIncorrect)
+                .with_ref_tag_id(NEW_SEQ_NO)
+                .with_text(errs::new_seq_no(expected));
+            return self.on_reject(reject);
+        }
+        self.msg_seq_num_inbound.set_expected(new_seq_no);
+        if self
+            .resend_requested_up_to
+            .is_some_and(|up_to| new_seq_no > up_to)
+        {
+            self.resend_requested_up_to = None;
+        }
+        Response::ResetHeartbeat
+    }
+
+    fn on_logout(&mut self, logout: Message<&[u8]>) -> Response {
+        self.backend.on_inbound_message(logout, false).ok();
+        let text = logout.get::<&str>(TEXT).ok().map(str::to_string);
+        self.emit(SessionEvent::LogoutReceived { text });
+        if self.state == SessionState::LogoutSent {
+            // This is the confirmation of our own `Logout <5>`.
+            return Response::TerminateTransport;
+        }
+        self.set_state(SessionState::LogoutReceived);
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"5", msg_seq_num);
+        let text = errs::logout_confirmed();
+        msg.set(TEXT, text.as_str());
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.emit(SessionEvent::LogoutSent { text });
+        Response::TerminateTransport
+    }
+
+    fn on_application_message(&mut self, msg: Message<&[u8]>) -> Response {
+        if msg.get_raw(MSG_TYPE) == Some(b"j") {
+            self.emit(SessionEvent::BusinessRejectReceived {
+                ref_seq_num: msg.get(REF_SEQ_NUM).ok(),
+                reason: msg.get::<BusinessRejectReason>(BUSINESS_REJECT_REASON).ok(),
+            });
+        }
+        let result = self.backend.on_inbound_message(msg, true);
+        if !self.config.session_mode().allows_application_messages() {
+            return Response::ResetHeartbeat;
+        }
+        if let Err(err) = result {
+            if let Some(reject) = self.backend.business_reject(msg, &err) {
+                let msg_seq_num = self.msg_seq_num_outbound.next();
+                let mut msg =
+                    self.builder
+                        .start_message_with_header(&self.config, b"j", msg_seq_num);
+                reject.set_fields(&mut msg);
+                done(
+                    msg,
+                    msg_seq_num,
+                    &mut self.backend,
+                    &mut self.store,
+                    &mut self.store_failure,
+                );
+                self.emit(SessionEvent::BusinessRejectSent(reject));
+            }
+        }
+        Response::ResetHeartbeat
+    }
+
+    fn on_inbound_reject(&mut self, reject: Message<&[u8]>) -> Response {
+        self.emit(SessionEvent::RejectReceived {
+            ref_seq_num: reject.get(REF_SEQ_NUM).ok(),
+            reason: reject
+                .get::<SessionRejectReason>(SESSION_REJECT_REASON)
+                .ok(),
+        });
+        self.backend.on_inbound_message(reject, false).ok();
+        Response::ResetHeartbeat
+    }
+
+    fn on_wrong_environment(&mut self, _message: Message<&[u8]>) -> Response {
+        self.make_logout(self.wrong_environment_text())
+    }
+
+    fn wrong_environment_text(&self) -> String {
+        match self.config.environment() {
+            Environment::Testing => errs::testing_env(),
+            _ => errs::production_env(),
+        }
+    }
+
+    fn on_missing_seqnum(&mut self, _message: Message<&[u8]>) -> Response {
+        self.make_logout(errs::missing_field("MsgSeqNum", MSG_SEQ_NUM))
+    }
+
+    fn on_low_seqnum(&mut self, message: Message<&[u8]>) -> Response {
+        if message.get_opt(POSS_DUP_FLAG) == Ok(Some(true)) {
+            // Possible duplicates that we've already received are simply
+            // ignored.
+            Response::None
+        } else {
+            self.make_logout(errs::msg_seq_num(self.msg_seq_num_inbound.expected()))
+        }
+    }
+
+    fn on_high_seqnum(&mut self, msg: Message<&[u8]>) -> Response {
+        let msg_seq_num = msg.get::<u64>(MSG_SEQ_NUM).unwrap_or_default();
+        let response = match msg.get_raw(MSG_TYPE).unwrap_or_default() {
+            // `Logon <A>` and `ResendRequest <2>` are processed right away,
+            // but without incrementing the inbound seq. number. They will be
+            // gap-filled by the counterparty.
+            b"A" => self.on_logon(msg),
+            b"2" => self.on_resend_request(msg),
+            _ if self.queue.len() >= self.config.inbound_queue_capacity() => {
+                // The queue is full, so the message is dropped. The
+                // outstanding `ResendRequest <2>` might not cover it: don't
+                // extend it, so that a new one is sent once it's satisfied.
+                if self.resend_requested_up_to.is_none() {
+                    self.make_resend_request_from_expected(msg_seq_num);
+                }
+                return Response::ResetHeartbeat;
+            }
+            _ => {
+                // Everything else waits until the gap has been filled.
+                self.queue.insert(msg_seq_num, msg.as_bytes().to_vec());
+                Response::ResetHeartbeat
+            }
+        };
+        // Only one `ResendRequest <2>` is sent for each gap; the counterparty
+        // will resend everything we're missing anyway.
+        match self.resend_requested_up_to {
+            Some(up_to) => {
+                self.resend_requested_up_to = Some(up_to.max(msg_seq_num));
+            }
+            None => self.make_resend_request_from_expected(msg_seq_num),
+        }
+        response
+    }
+
+    /// Asks the counterparty to resend everything from the next expected
+    /// seq. number, after receiving `msg_seq_num`.
+    fn make_resend_request_from_expected(&mut self, msg_seq_num: u64) {
+        self.resend_requested_up_to = Some(msg_seq_num);
+        self.emit(SessionEvent::GapDetected {
+            expected: self.msg_seq_num_inbound.expected(),
+            received: msg_seq_num,
+        });
+        let range = ResendRequestRange::new(self.msg_seq_num_inbound.expected(), None);
+        self.make_resend_request(range);
+    }
+
+    /// Writes a `Reject <3>`.
+    fn on_reject(&mut self, reject: Reject) -> Response {
+        if !self.config.session_mode().allows_rejects() {
+            return Response::ResetHeartbeat;
+        }
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"3", msg_seq_num);
+        reject.set_fields(&mut msg);
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.emit(SessionEvent::RejectSent(reject));
+        Response::ResetHeartbeat
+    }
+
+    /// Rejects a message with the wrong CompIDs and logs out.
+    fn on_comp_id_problem(&mut self, offender: Message<&[u8]>) -> Response {
+        let text = errs::comp_ids(self.config.target_comp_id(), self.config.sender_comp_id());
+        let reject =
+            Reject::new(&offender, SessionRejectReason::CompIdProblem).with_text(text.as_str());
+        self.on_reject(reject);
+        self.make_logout(text)
+    }
+
+    /// Rejects a message with an unacceptable `tag` timestamp. Inaccurate
+    /// timestamps also cause a logout.
+    fn on_timestamp_problem(&mut self, offender: Message<&[u8]>, name: &str, tag: u32) -> Response {
+        let (reason, text) = match offender.get_raw(tag) {
+            None => (
+                SessionRejectReason::RequiredTagMissing,
+                errs::missing_field(name, tag),
+            ),
+            Some(value) if Timestamp::parse(value).is_none() => (
+                SessionRejectReason::IncorrectDataFormatForValue,
+                errs::incorrect_data_format(name, tag),
+            ),
+            Some(_) => {
+                let text = errs::sending_time_accuracy(name, tag);
+                let reason = SessionRejectReason::SendingTimeAccuracyProblem;
+                let reject = Reject::new(&offender, reason).with_ref_tag_id(tag);
+                self.on_reject(reject.with_text(text.as_str()));
+                return self.make_logout(text);
+            }
+        };
+        let reject = Reject::new(&offender, reason).with_ref_tag_id(tag);
+        self.on_reject(reject.with_text(text))
+    }
+
+    fn make_app_message(&mut self, app_msg: &OutboundMessage) -> u64 {
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg =
+            self.builder
+                .start_message_with_header(&self.config, app_msg.msg_type(), msg_seq_num);
+        app_msg.set_fields(&mut msg);
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        msg_seq_num
+    }
+
+    fn make_logout(&mut self, text: String) -> Response {
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"5", msg_seq_num);
+        msg.set(TEXT, text.as_str());
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.emit(SessionEvent::LogoutSent { text });
+        self.set_state(SessionState::LogoutSent);
+        Response::TerminateTransport
+    }
+
+    /// Writes a `TestRequest <1>` with a new, unique `TestReqID <112>`, which
+    /// the counterparty must echo in a `Heartbeat <0>`.
+    fn make_test_request(&mut self, now: Instant) {
+        let test_req_id = uuid::Uuid::new_v4().to_string();
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"1", msg_seq_num);
+        msg.set(TEST_REQ_ID, test_req_id.as_str());
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.test_req_id = Some(test_req_id);
+        self.test_req_sent_at = self.utc_at(now);
+    }
+
+    fn make_resend_request(&mut self, range: ResendRequestRange) {
+        let msg_seq_num = self.msg_seq_num_outbound.next();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"2", msg_seq_num);
+        msg.set(BEGIN_SEQ_NO, range.begin_seq_no());
+        msg.set(END_SEQ_NO, range.end_seq_no());
+        done(
+            msg,
+            msg_seq_num,
+            &mut self.backend,
+            &mut self.store,
+            &mut self.store_failure,
+        );
+        self.emit(SessionEvent::ResendRequestSent(range));
+    }
+
+    /// Writes a `SequenceReset <4>` in GapFill mode that takes the place of
+    /// all messages from `start` (inclusive) to `new_seq_no` (exclusive).
+    fn make_gap_fill(&mut self, start: u64, new_seq_no: u64) {
+        let sending_time = self.builder.sending_time();
+        let mut msg = self
+            .builder
+            .start_message_with_header(&self.config, b"4", start);
+        msg.set(POSS_DUP_FLAG, true);
+        msg.set(ORIG_SENDING_TIME, sending_time);
+        msg.set(GAP_FILL_FLAG, true);
+        msg.set(NEW_SEQ_NO, new_seq_no);
+        let (buffer, offset) = msg.done();
+        self.backend.on_outbound_message(&buffer[offset..]).ok();
+    }
+
+    /// Writes a stored message again, with `PossDupFlag <43>` and
+    /// `OrigSendingTime <122>`.
+    fn make_possible_duplicate(&mut self, msg_seq_num: u64, original: ResendableMessage) {
+        let sending_time = self.builder.sending_time();
+        let mut msg = self
+            .builder
+            .start_message(self.config.begin_string(), original.msg_type);
+        msg.set(SENDER_COMP_ID, self.config.sender_comp_id());
+        msg.set(TARGET_COMP_ID, self.config.target_comp_id());
+        msg.set(MSG_SEQ_NUM, msg_seq_num);
+        msg.set(POSS_DUP_FLAG, true);
+        msg.set(SENDING_TIME, sending_time);
+        msg.set(ORIG_SENDING_TIME, original.sending_time);
+        msg.set_raw(original.remaining_fields);
+        let (buffer, offset) = msg.done();
+        self.backend.on_outbound_message(&buffer[offset..]).ok();
+    }
+}
+
+/// What woke up [`FixConnection::drive`].
+enum Wakeup {
+    Read(io::Result<usize>),
+    Send(Option<SendRequest>),
+    Tick,
+}
+
+/// A stored outbound message which must be resent as-is (i.e. not
+/// gap-filled), split into the parts that are needed to rewrite its
+/// `StandardHeader`.
+struct ResendableMessage<'a> {
+    msg_type: &'a [u8],
+    sending_time: &'a [u8],
+    /// All fields after `SendingTime <52>` and before `CheckSum <10>`.
+    remaining_fields: &'a [u8],
+}
+
+impl<'a> ResendableMessage<'a> {
+    /// Returns [`None`] for administrative messages, which are never resent,
+    /// and for messages that were not written by [`MessageBuilder`].
+    fn parse(message: &'a [u8]) -> Option<Self> {
+        let checksum_start = message.windows(4).rposition(|w| w == b"\x0110=")? + 1;
+        let mut msg_type = None;
+        let mut i = 0;
+        // `MessageBuilder` writes `SendingTime <52>` as the last field of
+        // the `StandardHeader`, before any data fields that might contain
+        // the separator.
+        while i < checksum_start {
+            let len = message[i..].iter().position(|b| *b == b'\x01')?;
+            let field = &message[i..i + len];
+            i += len + 1;
+            let equal_sign = field.iter().position(|b| *b == b'=')?;
+            let (tag, value) = (&field[..equal_sign], &field[equal_sign + 1..]);
+            match tag {
+                b"35" => msg_type = Some(value),
+                b"52" => {
+                    let msg_type = msg_type?;
+                    if is_admin_msg_type(msg_type) {
+                        return None;
+                    }
+                    return Some(Self {
+                        msg_type,
+                        sending_time: value,
+                        remaining_fields: &message[i..checksum_start],
+                    });
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Administrative messages that are gap-filled rather than resent. Session
+/// level `Reject <3>` messages are resent.
+pub(super) fn is_admin_msg_type(msg_type: &[u8]) -> bool {
+    matches!(msg_type, b"0" | b"1" | b"2" | b"4" | b"5" | b"A")
+}
+
+/// Moves as many `bytes` as needed into `decoder`, until a whole message is
+/// ready. Returns `false` if `bytes` run out first.
+pub(super) fn next_message(
+    decoder: &mut DecoderStreaming<Vec<u8>>,
+    bytes: &mut &[u8],
+) -> Result<bool, DecodeError> {
+    loop {
+        let num_bytes_required = decoder.num_bytes_required();
+        let num_bytes_buffered = decoder.buffer().len();
+        if num_bytes_buffered < num_bytes_required {
+            if bytes.is_empty() {
+                return Ok(false);
+            }
+            let len = (num_bytes_required - num_bytes_buffered).min(bytes.len());
+            decoder.buffer().extend_from_slice(&bytes[..len]);
+            *bytes = &bytes[len..];
+        } else if decoder.try_parse()?.is_some() {
+            return Ok(true);
+        }
+    }
+}
+
+/// Skips `bytes` up to the next `BeginString <8>`, after a message with a
+/// wrong `BodyLength <9>`. Messages swallowed in the process are lost, and
+/// the counterparty will have to resend them.
+fn skip_to_begin_string(bytes: &mut &[u8], separator: u8) {
+    if !bytes.starts_with(b"8=") {
+        let i = bytes
+            .windows(3)
+            .position(|window| window == [separator, b'8', b'='])
+            .map_or(bytes.len(), |i| i + 1);
+        *bytes = &bytes[i..];
+    }
+}
+
+/// Returns the value of the first `tag` field in the raw message `msg`.
+fn raw_field(msg: &[u8], separator: u8, tag: u32) -> Option<&[u8]> {
+    let needle = format!("{}{}=", separator as char, tag);
+    let start = msg
+        .windows(needle.len())
+        .position(|window| window == needle.as_bytes())?
+        + needle.len();
+    let len = msg[start..].iter().position(|byte| *byte == separator)?;
+    Some(&msg[start..][..len])
+}
+
+/// Finalizes an outbound message, hands it over to the [`Backend`] and stores
+/// it for later resending. Nothing is stored after a failure of `store`,
+/// which is recorded in `failure`.
+fn done<B, S>(
+    msg: EncoderHandle<Vec<u8>>,
+    msg_seq_num: u64,
+    backend: &mut B,
+    store: &mut S,
+    failure: &mut Option<StoreFailure>,
+) where
+    B: Backend,
+    S: MessageStore,
+{
+    let (buffer, offset) = msg.done();
+    if failure.is_some() {
+        return;
+    }
+    backend.on_outbound_message(&buffer[offset..]).ok();
+    if let Err(err) = store.store_outbound(msg_seq_num, &buffer[offset..]) {
+        StoreFailure::record(failure, offset, err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session::{
+        testing, Direction, FileLog, FileLogReader, HeartbeatRule, Rate, Schedule, SessionMode,
+        Throttle,
+    };
+    use crate::tagvalue::Decoder;
+    use crate::Dictionary;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, DuplexStream};
+    use tokio_util::compat::*;
+
+    const REF_TAG_ID: u32 = 371;
+
+    #[derive(Debug, Clone, Default)]
+    struct TestBackend {
+        inbound_app_messages: Vec<Vec<u8>>,
+        outbound_messages: Vec<Vec<u8>>,
+        resend_requests: Vec<std::ops::Range<u64>>,
+        states: Vec<SessionState>,
+        attempts: Vec<(u32, usize, bool)>,
+        retry_delays: Vec<Option<Duration>>,
+        is_logged_on: bool,
+        app_error: Option<u32>,
+        events: Vec<SessionEvent>,
+    }
+
+    impl Backend for TestBackend {
+        type Error = u32;
+
+        fn sender_comp_id(&self) -> &[u8] {
+            b"SENDER_COMP"
+        }
+
+        fn target_comp_id(&self) -> &[u8] {
+            b"TARGET_COMP"
+        }
+
+        fn on_inbound_app_message(&mut self, message: Message<&[u8]>) -> Result<(), Self::Error> {
+            self.inbound_app_messages.push(message.as_bytes().to_vec());
+            self.app_error.map_or(Ok(()), Err)
+        }
+
+        fn on_outbound_message(&mut self, message: &[u8]) -> Result<(), Self::Error> {
+            self.outbound_messages.push(message.to_vec());
+            Ok(())
+        }
+
+        fn on_resend_request(&mut self, range: std::ops::Range<u64>) -> Result<(), Self::Error> {
+            self.resend_requests.push(range);
+            Ok(())
+        }
+
+        fn on_successful_handshake(&mut self) -> Result<(), Self::Error> {
+            self.is_logged_on = true;
+            Ok(())
+        }
+
+        fn on_connection_attempt(
+            &mut self,
+            attempt: &ConnectionAttempt,
+        ) -> Result<(), Self::Error> {
+            self.attempts
+                .push((attempt.failures, attempt.endpoint, attempt.result.is_ok()));
+            self.retry_delays.push(attempt.retry_in);
+            Ok(())
+        }
+
+        fn on_state_change(
+            &mut self,
+            old: SessionState,
+            new: SessionState,
+        ) -> Result<(), Self::Error> {
+            assert_eq!(self.states.last().copied().unwrap_or_default(), old);
+            self.states.push(new);
+            Ok(())
+        }
+
+        fn on_session_event(&mut self, event: &SessionEvent) -> Result<(), Self::Error> {
+            self.events.push(event.clone());
+            Ok(())
+        }
+
+        fn fetch_messages(&mut self) -> Result<&[&[u8]], Self::Error> {
+            Ok(&[])
+        }
+
+        fn pending_message(&mut self) -> Option<&[u8]> {
+            None
+        }
+    }
+
+    fn conn() -> FixConnection<TestBackend> {
+        FixConnection::new(Config::default(), TestBackend::default())
+    }
+
+    fn decoder() -> DecoderStreaming<Vec<u8>> {
+        Decoder::new(Dictionary::fix44()).streaming(vec![])
+    }
+
+    /// Encodes a message as if it was sent by the counterparty.
+    fn counterparty_msg(msg_type: &[u8], msg_seq_num: u64, fields: &[(u32, &str)]) -> Vec<u8> {
+        counterparty_msg_sent_at(Utc::now(), msg_type, msg_seq_num, fields)
+    }
+
+    /// Like [`counterparty_msg`], but with `sending_time` as its
+    /// `SendingTime <52>`.
+    fn counterparty_msg_sent_at(
+        sending_time: DateTime<Utc>,
+        msg_type: &[u8],
+        msg_seq_num: u64,
+        fields: &[(u32, &str)],
+    ) -> Vec<u8> {
+        versioned_counterparty_msg(b"FIX.4.4", sending_time, msg_type, msg_seq_num, fields)
+    }
+
+    /// Like [`counterparty_msg_sent_at`], but with `begin_string` as its
+    /// `BeginString <8>`.
+    fn versioned_counterparty_msg(
+        begin_string: &[u8],
+        sending_time: DateTime<Utc>,
+        msg_type: &[u8],
+        msg_seq_num: u64,
+        fields: &[(u32, &str)],
+    ) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut encoder = Encoder::new();
+        let mut msg = encoder.start_message(begin_string, &mut buffer, msg_type);
+        msg.set(SENDER_COMP_ID, "TARGET_COMP");
+        msg.set(TARGET_COMP_ID, "SENDER_COMP");
+        msg.set(MSG_SEQ_NUM, msg_seq_num);
+        msg.set(SENDING_TIME, sending_time.naive_utc());
+        for (tag, value) in fields {
+            msg.set(*tag, *value);
+        }
+        msg.done();
+        buffer
+    }
+
+    /// Splits the concatenated outbound messages of a [`FixConnection`].
+    fn split_messages(mut bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut messages = vec![];
+        while let Some(i) = bytes.windows(4).position(|w| w == b"\x0110=") {
+            let len = i + 8;
+            messages.push(bytes[..len].to_vec());
+            bytes = &bytes[len..];
+        }
+        messages
+    }
+
+    fn field(msg: &[u8], tag: u32) -> Option<String> {
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        decoder.config_mut().verify_checksum = true;
+        let msg = decoder.decode(msg).expect("Invalid outbound message");
+        msg.get::<&str>(tag).ok().map(str::to_string)
+    }
+
+    async fn read_message(stream: &mut DuplexStream) -> Vec<u8> {
+        let mut msg = vec![];
+        loop {
+            msg.push(stream.read_u8().await.unwrap());
+            if msg.len() > 8 && msg[msg.len() - 8..].starts_with(b"\x0110=") {
+                return msg;
+            }
+        }
+    }
+
+    fn start(
+        conn: &mut FixConnection<TestBackend>,
+        stream: DuplexStream,
+    ) -> impl std::future::Future<Output = Result<(), ConnectionError>> + '_ {
+        let (input, output) = tokio::io::split(stream);
+        conn.start(input.compat(), output.compat_write(), decoder())
+    }
+
+    fn accept(
+        conn: &mut FixConnection<TestBackend>,
+        stream: DuplexStream,
+    ) -> impl std::future::Future<Output = Result<(), ConnectionError>> + '_ {
+        let (input, output) = tokio::io::split(stream);
+        conn.accept(input.compat(), output.compat_write(), decoder())
+    }
+
+    /// Sends a `Logon <A>` to an acceptor and returns its reply.
+    async fn logon_with_acceptor(
+        mut config: Config,
+        logon: Vec<u8>,
+    ) -> (Result<(), ConnectionError>, Vec<u8>) {
+        config.heartbeat = Duration::from_secs(60);
+        let (stream, mut counterparty) = tokio::io::duplex(4096);
+        let counterparty = tokio::spawn(async move {
+            counterparty.write_all(&logon).await.unwrap();
+            read_message(&mut counterparty).await
+        });
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        let result = accept(conn, stream).await;
+        (result, counterparty.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn logon_then_counterparty_logout() {
+        let (stream, mut counterparty) = tokio::io::duplex(4096);
+        let counterparty = tokio::spawn(async move {
+            let logon = read_message(&mut counterparty).await;
+            counterparty
+                .write_all(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]))
+                .await
+                .unwrap();
+            counterparty
+                .write_all(&counterparty_msg(b"D", 2, &[(11, "order-1")]))
+                .await
+                .unwrap();
+            counterparty
+                .write_all(&counterparty_msg(b"5", 3, &[]))
+                .await
+                .unwrap();
+            let logout = read_message(&mut counterparty).await;
+            (logon, logout)
+        });
+        let conn = &mut conn();
+        start(conn, stream).await.unwrap();
+        let (logon, logout) = counterparty.await.unwrap();
+
+        assert_eq!(field(&logon, MSG_TYPE).as_deref(), Some("A"));
+        assert_eq!(field(&logon, MSG_SEQ_NUM).as_deref(), Some("1"));
+        assert_eq!(field(&logon, HEART_BT_INT).as_deref(), Some("30"));
+        assert_eq!(
+            field(&logon, SENDER_COMP_ID).as_deref(),
+            Some("SENDER_COMP")
+        );
+        assert_eq!(
+            field(&logon, TARGET_COMP_ID).as_deref(),
+            Some("TARGET_COMP")
+        );
+        assert_eq!(field(&logout, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&logout, MSG_SEQ_NUM).as_deref(), Some("2"));
+
+        let backend = conn.backend();
+        assert!(backend.is_logged_on);
+        assert_eq!(backend.inbound_app_messages.len(), 1);
+        assert_eq!(backend.outbound_messages, vec![logon, logout]);
+        assert_eq!(
+            backend.states,
+            vec![
+                SessionState::LogonSent,
+                SessionState::Active,
+                SessionState::LogoutReceived,
+                SessionState::Disconnected
+            ]
+        );
+        assert_eq!(conn.seq_numbers().next_inbound(), 4);
+        assert_eq!(conn.seq_numbers().next_outbound(), 3);
+    }
+
+    #[tokio::test]
+    async fn logon_refused_by_counterparty() {
+        let (stream, mut counterparty) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            read_message(&mut counterparty).await;
+            counterparty
+                .write_all(&counterparty_msg(b"5", 1, &[(58, "Unknown CompID")]))
+                .await
+                .unwrap();
+        });
+        let conn = &mut conn();
+        let result = start(conn, stream).await;
+        assert!(matches!(result, Err(ConnectionError::Logon(text)) if text == "Unknown CompID"));
+        assert!(!conn.backend().is_logged_on);
+        assert_eq!(
+            conn.backend().states,
+            vec![SessionState::LogonSent, SessionState::Disconnected]
+        );
+    }
+
+    #[test]
+    fn on_heartbeat_is_due() {
+        let conn = &mut conn();
+        conn.on_heartbeat_is_due();
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("0"));
+        assert_eq!(
+            field(&messages[0], SENDER_COMP_ID).as_deref(),
+            Some("SENDER_COMP")
+        );
+        assert_eq!(
+            field(&messages[0], TARGET_COMP_ID).as_deref(),
+            Some("TARGET_COMP")
+        );
+        assert_eq!(field(&messages[0], POSS_DUP_FLAG), None);
+        assert_eq!(field(&messages[0], TEST_REQ_ID), None);
+    }
+
+    #[test]
+    fn test_request_is_answered_with_heartbeat() {
+        let conn = &mut conn();
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        let test_request = counterparty_msg(b"1", 1, &[(112, "ping")]);
+        let response = conn.on_inbound_message(decoder.decode(&test_request[..]).unwrap());
+        assert_eq!(response, Response::ResetHeartbeat);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("0"));
+        assert_eq!(field(&messages[0], TEST_REQ_ID).as_deref(), Some("ping"));
+    }
+
+    #[test]
+    fn high_seqnum_triggers_single_resend_request() {
+        let conn = &mut conn();
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        for msg_seq_num in [5, 6] {
+            let msg = counterparty_msg(b"D", msg_seq_num, &[]);
+            conn.on_inbound_message(decoder.decode(&msg[..]).unwrap());
+        }
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("2"));
+        assert_eq!(field(&messages[0], BEGIN_SEQ_NO).as_deref(), Some("1"));
+        assert_eq!(field(&messages[0], END_SEQ_NO).as_deref(), Some("0"));
+        assert!(conn.backend().inbound_app_messages.is_empty());
+        assert_eq!(conn.seq_numbers().next_inbound(), 1);
+    }
+
+    #[test]
+    fn low_seqnum_triggers_logout() {
+        let conn = &mut conn();
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        let msg = counterparty_msg(b"0", 1, &[]);
+        conn.on_inbound_message(decoder.decode(&msg[..]).unwrap());
+        let response = conn.on_inbound_message(decoder.decode(&msg[..]).unwrap());
+        assert_eq!(response, Response::TerminateTransport);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&messages[0], TEXT), Some(errs::msg_seq_num(2)),);
+    }
+
+    #[test]
+    fn possible_duplicate_with_low_seqnum_is_ignored() {
+        let conn = &mut conn();
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        let msg = counterparty_msg(b"0", 1, &[]);
+        conn.on_inbound_message(decoder.decode(&msg[..]).unwrap());
+        let dup = counterparty_msg(b"0", 1, &[(43, "Y")]);
+        let response = conn.on_inbound_message(decoder.decode(&dup[..]).unwrap());
+        assert_eq!(response, Response::None);
+        assert!(conn.pending_outbound().is_empty());
+    }
+
+    #[test]
+    fn resend_request_is_forwarded_to_backend() {
+        let conn = &mut conn();
+        for _ in 0..5 {
+            conn.on_heartbeat_is_due();
+        }
+        conn.clear_outbound();
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        let msg = counterparty_msg(b"2", 1, &[(7, "2"), (16, "0")]);
+        conn.on_inbound_message(decoder.decode(&msg[..]).unwrap());
+        assert_eq!(conn.backend().resend_requests, vec![2..6]);
+    }
+
+    #[tokio::test]
+    async fn accept_logon_then_counterparty_logout() {
+        let (stream, mut counterparty) = tokio::io::duplex(4096);
+        let counterparty = tokio::spawn(async move {
+            counterparty
+                .write_all(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "20")]))
+                .await
+                .unwrap();
+            let logon = read_message(&mut counterparty).await;
+            counterparty
+                .write_all(&counterparty_msg(b"5", 2, &[]))
+                .await
+                .unwrap();
+            let logout = read_message(&mut counterparty).await;
+            (logon, logout)
+        });
+        let conn = &mut conn();
+        accept(conn, stream).await.unwrap();
+        let (logon, logout) = counterparty.await.unwrap();
+
+        assert_eq!(field(&logon, MSG_TYPE).as_deref(), Some("A"));
+        assert_eq!(field(&logon, MSG_SEQ_NUM).as_deref(), Some("1"));
+        // The initiator's proposal wins over our own configuration.
+        assert_eq!(field(&logon, HEART_BT_INT).as_deref(), Some("20"));
+        assert_eq!(field(&logout, MSG_TYPE).as_deref(), Some("5"));
+        assert!(conn.backend().is_logged_on);
+        assert_eq!(conn.state(), SessionState::Disconnected);
+        assert_eq!(
+            conn.backend().states,
+            vec![
+                SessionState::LogonReceived,
+                SessionState::Active,
+                SessionState::LogoutReceived,
+                SessionState::Disconnected
+            ]
+        );
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+        assert_eq!(conn.seq_numbers().next_outbound(), 3);
+    }
+
+    #[tokio::test]
+    async fn accept_refuses_unknown_sender_comp_id() {
+        let mut config = Config::default();
+        config.target_comp_id = "SOMEONE_ELSE".to_string();
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]);
+        let (result, reply) = logon_with_acceptor(config, logon).await;
+
+        let text = errs::sender_comp_id(b"SOMEONE_ELSE");
+        assert!(matches!(result, Err(ConnectionError::Logon(t)) if t == text));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&reply, TEXT), Some(text));
+    }
+
+    #[tokio::test]
+    async fn accept_refuses_heartbeat_outside_of_rule() {
+        let mut config = Config::default();
+        config.heartbeat_rule = HeartbeatRule::Exact(Duration::from_secs(30));
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "10")]);
+        let (result, reply) = logon_with_acceptor(config, logon).await;
+
+        let text = errs::heartbeat_exact(30);
+        assert!(matches!(result, Err(ConnectionError::Logon(t)) if t == text));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&reply, TEXT), Some(text));
+    }
+
+    #[tokio::test]
+    async fn accept_refuses_stale_sending_time() {
+        let sent_at = Utc::now() - chrono::Duration::seconds(600);
+        let logon = counterparty_msg_sent_at(sent_at, b"A", 1, &[(98, "0"), (108, "30")]);
+        let (result, reply) = logon_with_acceptor(Config::default(), logon).await;
+
+        let text = errs::sending_time_accuracy("SendingTime", SENDING_TIME);
+        assert!(matches!(result, Err(ConnectionError::Logon(t)) if t == text));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&reply, TEXT), Some(text));
+    }
+
+    #[tokio::test]
+    async fn accept_refuses_missing_heart_bt_int() {
+        let logon = counterparty_msg(b"A", 1, &[(98, "0")]);
+        let (result, reply) = logon_with_acceptor(Config::default(), logon).await;
+
+        assert!(matches!(result, Err(ConnectionError::Logon(_))));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(
+            field(&reply, TEXT),
+            Some(errs::missing_field("HeartBtInt", HEART_BT_INT))
+        );
+    }
+
+    fn feed<S>(conn: &mut FixConnection<TestBackend, Config, Verifier, S>, msg: &[u8]) -> Response
+    where
+        S: MessageStore,
+    {
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        conn.on_inbound_message(decoder.decode(msg).unwrap())
+    }
+
+    /// Encodes a message as if it was written by us in the past.
+    fn stored_msg(msg_type: &[u8], msg_seq_num: u64, fields: &[(u32, &str)]) -> Vec<u8> {
+        let mut builder = MessageBuilder::new();
+        let mut msg = builder.start_message_with_header(&Config::default(), msg_type, msg_seq_num);
+        for (tag, value) in fields {
+            msg.set(*tag, *value);
+        }
+        msg.done();
+        builder.as_bytes().to_vec()
+    }
+
+    #[test]
+    fn resend_request_replays_app_messages_and_gap_fills_admin_messages() {
+        let mut store = MemoryStore::new();
+        store
+            .store_outbound(1, &stored_msg(b"A", 1, &[(98, "0"), (108, "30")]))
+            .unwrap();
+        store
+            .store_outbound(2, &stored_msg(b"D", 2, &[(11, "order-1")]))
+            .unwrap();
+        store.store_outbound(3, &stored_msg(b"0", 3, &[])).unwrap();
+        store
+            .store_outbound(5, &stored_msg(b"D", 5, &[(11, "order-2")]))
+            .unwrap();
+        store
+            .set_seq_numbers(SeqNumbers {
+                next_inbound: 1,
+                next_outbound: 7,
+            })
+            .unwrap();
+        let conn = &mut conn().with_store(store);
+
+        feed(conn, &counterparty_msg(b"2", 1, &[(7, "1"), (16, "0")]));
+        let messages = split_messages(conn.pending_outbound());
+        let summary: Vec<_> = messages
+            .iter()
+            .map(|msg| {
+                (
+                    field(msg, MSG_TYPE).unwrap(),
+                    field(msg, MSG_SEQ_NUM).unwrap(),
+                    field(msg, NEW_SEQ_NO),
+                    field(msg, POSS_DUP_FLAG),
+                )
+            })
+            .collect();
+        let y = Some("Y".to_string());
+        assert_eq!(
+            summary,
+            vec![
+                ("4".into(), "1".into(), Some("2".into()), y.clone()),
+                ("D".into(), "2".into(), None, y.clone()),
+                ("4".into(), "3".into(), Some("5".into()), y.clone()),
+                ("D".into(), "5".into(), None, y.clone()),
+                ("4".into(), "6".into(), Some("7".into()), y),
+            ]
+        );
+        assert_eq!(field(&messages[1], 11).as_deref(), Some("order-1"));
+        assert!(field(&messages[1], ORIG_SENDING_TIME).is_some());
+        assert_eq!(field(&messages[0], GAP_FILL_FLAG).as_deref(), Some("Y"));
+        assert_eq!(conn.backend().resend_requests, vec![1..7]);
+        // Resent messages don't consume new seq. numbers.
+        assert_eq!(conn.seq_numbers().next_outbound(), 7);
+    }
+
+    #[test]
+    fn out_of_order_messages_are_queued_until_the_gap_is_filled() {
+        let conn = &mut conn();
+        let order = |n: u64| counterparty_msg(b"D", n, &[(11, &n.to_string())]);
+        feed(conn, &order(1));
+        feed(conn, &order(3));
+        feed(conn, &order(4));
+        assert_eq!(conn.pop_queued_message(), None);
+        assert_eq!(conn.backend().inbound_app_messages.len(), 1);
+
+        let resent = counterparty_msg(b"D", 2, &[(43, "Y"), (122, "20000101-00:00:00"), (11, "2")]);
+        feed(conn, &resent);
+        while let Some(queued) = conn.pop_queued_message() {
+            feed(conn, &queued);
+        }
+        let orders: Vec<_> = conn
+            .backend()
+            .inbound_app_messages
+            .iter()
+            .map(|msg| field(msg, 11).unwrap())
+            .collect();
+        assert_eq!(orders, vec!["1", "2", "3", "4"]);
+        assert_eq!(conn.seq_numbers().next_inbound(), 5);
+
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("2"));
+        assert_eq!(field(&messages[0], BEGIN_SEQ_NO).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn out_of_order_messages_past_the_capacity_are_dropped() {
+        let mut config = Config::default();
+        config.inbound_queue_capacity = 2;
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        let order = |n: u64| counterparty_msg(b"D", n, &[(11, &n.to_string())]);
+        feed(conn, &order(1));
+        for n in 3..=6 {
+            feed(conn, &order(n));
+        }
+        assert_eq!(conn.queue.len(), 2);
+
+        let resent = counterparty_msg(b"D", 2, &[(43, "Y"), (122, "20000101-00:00:00"), (11, "2")]);
+        feed(conn, &resent);
+        while let Some(queued) = conn.pop_queued_message() {
+            feed(conn, &queued);
+        }
+        assert_eq!(conn.backend().inbound_app_messages.len(), 4);
+        assert_eq!(conn.seq_numbers().next_inbound(), 5);
+
+        // The dropped messages are requested once again.
+        feed(conn, &order(7));
+        let messages = split_messages(conn.pending_outbound());
+        let begin_seq_nos: Vec<_> = messages
+            .iter()
+            .filter(|msg| field(msg, MSG_TYPE).as_deref() == Some("2"))
+            .map(|msg| field(msg, BEGIN_SEQ_NO).unwrap())
+            .collect();
+        assert_eq!(begin_seq_nos, vec!["2", "5"]);
+    }
+
+    #[test]
+    fn sequence_reset_gap_fill_advances_inbound_seq_number() {
+        let conn = &mut conn();
+        feed(conn, &counterparty_msg(b"4", 1, &[(123, "Y"), (36, "5")]));
+        assert_eq!(conn.seq_numbers().next_inbound(), 5);
+        assert!(conn.pending_outbound().is_empty());
+    }
+
+    #[test]
+    fn sequence_reset_reset_ignores_msg_seq_num() {
+        let conn = &mut conn();
+        feed(conn, &counterparty_msg(b"4", 99, &[(36, "10")]));
+        assert_eq!(conn.seq_numbers().next_inbound(), 10);
+
+        feed(conn, &counterparty_msg(b"4", 99, &[(36, "3")]));
+        assert_eq!(conn.seq_numbers().next_inbound(), 10);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("3"));
+        assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("36"));
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("5")
+        );
+    }
+
+    #[test]
+    fn malformed_resend_request_is_rejected() {
+        let conn = &mut conn();
+        feed(conn, &counterparty_msg(b"2", 1, &[(7, "1"), (16, "X")]));
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("3"));
+        assert_eq!(field(&messages[0], 45).as_deref(), Some("1"));
+        assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("16"));
+        assert_eq!(field(&messages[0], 372).as_deref(), Some("2"));
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("6")
+        );
+    }
+
+    #[test]
+    fn inverted_resend_request_is_rejected() {
+        for (begin_seq_no, end_seq_no) in [("5", "3"), ("100", "0"), ("6", "8")] {
+            let conn = &mut conn();
+            for _ in 0..5 {
+                conn.on_heartbeat_is_due();
+            }
+            conn.clear_outbound();
+            feed(
+                conn,
+                &counterparty_msg(b"2", 1, &[(7, begin_seq_no), (16, end_seq_no)]),
+            );
+            assert!(conn.backend().resend_requests.is_empty());
+            let messages = split_messages(conn.pending_outbound());
+            assert_eq!(messages.len(), 1);
+            assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("3"));
+            assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("7"));
+            assert_eq!(
+                field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+                Some("5")
+            );
+        }
+    }
+
+    #[test]
+    fn failed_app_messages_get_a_business_reject() {
+        let conn = &mut conn();
+        conn.backend.app_error = Some(42);
+        feed(conn, &counterparty_msg(b"D", 1, &[(11, "order")]));
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("j"));
+        assert_eq!(field(&messages[0], 45).as_deref(), Some("1"));
+        assert_eq!(field(&messages[0], 372).as_deref(), Some("D"));
+        assert_eq!(field(&messages[0], 380).as_deref(), Some("0"));
+        assert_eq!(field(&messages[0], TEXT).as_deref(), Some("42"));
+    }
+
+    #[tokio::test]
+    async fn queued_messages_are_replayed_by_the_event_loop() {
+        let (stream, mut counterparty) = tokio::io::duplex(4096);
+        let counterparty = tokio::spawn(async move {
+            read_message(&mut counterparty).await;
+            for msg in [
+                counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]),
+                counterparty_msg(b"D", 3, &[(11, "3")]),
+            ] {
+                counterparty.write_all(&msg).await.unwrap();
+            }
+            let resend_request = read_message(&mut counterparty).await;
+            for msg in [
+                counterparty_msg(b"D", 2, &[(43, "Y"), (122, "20000101-00:00:00"), (11, "2")]),
+                counterparty_msg(b"5", 4, &[]),
+            ] {
+                counterparty.write_all(&msg).await.unwrap();
+            }
+            read_message(&mut counterparty).await;
+            resend_request
+        });
+        let conn = &mut conn();
+        start(conn, stream).await.unwrap();
+        let resend_request = counterparty.await.unwrap();
+
+        assert_eq!(field(&resend_request, MSG_TYPE).as_deref(), Some("2"));
+        assert_eq!(
+            conn.backend().states,
+            vec![
+                SessionState::LogonSent,
+                SessionState::Active,
+                SessionState::ResendInProgress,
+                SessionState::Active,
+                SessionState::LogoutReceived,
+                SessionState::Disconnected
+            ]
+        );
+        let orders: Vec<_> = conn
+            .backend()
+            .inbound_app_messages
+            .iter()
+            .map(|msg| field(msg, 11).unwrap())
+            .collect();
+        assert_eq!(orders, vec!["2", "3"]);
+        assert_eq!(conn.seq_numbers().next_inbound(), 5);
+    }
+
+    #[test]
+    fn seq_numbers_are_persisted_to_the_store() {
+        let conn = &mut conn();
+        feed(conn, &counterparty_msg(b"1", 1, &[(112, "ping")]));
+        let seq_numbers = conn.store().seq_numbers();
+        assert_eq!(seq_numbers.next_inbound(), 2);
+        assert_eq!(seq_numbers.next_outbound(), 2);
+        let stored = conn.store_mut().fetch_outbound(1..2).unwrap();
+        assert_eq!(stored.len(), 1);
+    }
+
+    /// Returns the concatenated contents of all [`Action::Write`]s.
+    fn written(actions: &[Action]) -> Vec<u8> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Write(bytes) => Some(&bytes[..]),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Logs on as the initiator at `t0`, without any I/O.
+    fn logged_on(t0: Instant) -> FixConnection<TestBackend> {
+        logged_on_with(Config::default(), t0)
+    }
+
+    fn logged_on_with(config: Config, t0: Instant) -> FixConnection<TestBackend> {
+        let mut conn = FixConnection::new(config, TestBackend::default());
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        conn.on_bytes(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]), t0);
+        assert_eq!(conn.state(), SessionState::Active);
+        conn
+    }
+
+    fn order(cl_ord_id: &str) -> OutboundMessage {
+        let mut msg = OutboundMessage::new(b"D");
+        msg.set(11, cl_ord_id);
+        msg
+    }
+
+    #[test]
+    fn throttled_messages_are_queued_in_order() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.throttle = Some(Throttle::new(Rate::per_second(2)));
+        let conn = &mut logged_on_with(config, t0);
+        assert_eq!(
+            conn.send(OutboundMessage::new(b"0"), t0).err(),
+            Some(SendError::SessionMessage)
+        );
+
+        let mut sent = vec![];
+        for cl_ord_id in ["1", "2", "3", "4"] {
+            sent.extend(split_messages(&written(
+                &conn.send(order(cl_ord_id), t0).unwrap(),
+            )));
+        }
+        let cl_ord_ids: Vec<_> = sent.iter().map(|msg| field(msg, 11).unwrap()).collect();
+        assert_eq!(cl_ord_ids, vec!["1", "2"]);
+        let actions = conn
+            .send(order("5"), t0 + Duration::from_millis(400))
+            .unwrap();
+        assert!(written(&actions).is_empty());
+        let half_a_second = t0 + Duration::from_millis(500);
+        assert!(matches!(actions[..], [Action::ArmTimer(t)] if t == half_a_second));
+
+        // Session-level messages are never delayed.
+        let heartbeat = written(&conn.on_tick(t0 + Duration::from_secs(30)));
+        let messages = split_messages(&heartbeat);
+        let msg_types: Vec<_> = messages.iter().map(|m| field(m, MSG_TYPE)).collect();
+        assert_eq!(msg_types.last(), Some(&Some("0".to_string())));
+        let cl_ord_ids: Vec<_> = messages.iter().filter_map(|msg| field(msg, 11)).collect();
+        assert_eq!(cl_ord_ids, vec!["3", "4"]);
+        let order = written(&conn.on_tick(t0 + Duration::from_millis(30_500)));
+        assert_eq!(field(&order, 11).as_deref(), Some("5"));
+        assert_eq!(conn.seq_numbers().next_outbound(), 8);
+    }
+
+    #[test]
+    fn throttled_messages_can_be_rejected() {
+        let t0 = Instant::now();
+        let throttle = Throttle::new(Rate::per_second(10))
+            .with_msg_type_limit(b"D", Rate::per_second(1))
+            .when_exceeded(WhenThrottled::Reject);
+        let mut config = Config::default();
+        config.throttle = Some(throttle);
+        let conn = &mut logged_on_with(config, t0);
+        conn.send(order("1"), t0).unwrap();
+        assert_eq!(
+            conn.send(order("2"), t0).err(),
+            Some(SendError::Throttled {
+                retry_after: Duration::from_secs(1)
+            })
+        );
+        assert!(conn.send(OutboundMessage::new(b"F"), t0).is_ok());
+    }
+
+    #[test]
+    fn drop_copy_sessions_replay_everything_and_never_send_app_messages() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.session_mode = SessionMode::DropCopy;
+        config.replay_on_logon = true;
+        let mut store = MemoryStore::new();
+        store
+            .set_seq_numbers(SeqNumbers {
+                next_inbound: 5,
+                next_outbound: 1,
+            })
+            .unwrap();
+        let mut backend = TestBackend::default();
+        backend.app_error = Some(1);
+        let conn = &mut FixConnection::new(config, backend).with_store(store);
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        let actions = conn.on_bytes(&counterparty_msg(b"A", 5, &[(98, "0"), (108, "30")]), t0);
+        let resend_request = &split_messages(&written(&actions))[0];
+        assert_eq!(field(resend_request, MSG_TYPE).as_deref(), Some("2"));
+        assert_eq!(field(resend_request, BEGIN_SEQ_NO).as_deref(), Some("1"));
+        assert_eq!(field(resend_request, END_SEQ_NO).as_deref(), Some("0"));
+        assert_eq!(conn.state(), SessionState::ResendInProgress);
+
+        let orig_sending_time = "20200101-00:00:00";
+        let replayed = |msg_type: &[u8], msg_seq_num, fields: &[(u32, &str)]| {
+            let mut fields = fields.to_vec();
+            fields.extend([(43, "Y"), (122, orig_sending_time)]);
+            counterparty_msg(msg_type, msg_seq_num, &fields)
+        };
+        let mut actions = vec![];
+        for msg in [
+            replayed(b"4", 1, &[(123, "Y"), (36, "3")]),
+            replayed(b"8", 3, &[(17, "exec-1")]),
+            replayed(b"8", 4, &[(17, "exec-2")]),
+            counterparty_msg(b"8", 6, &[(17, "exec-3")]),
+            replayed(b"4", 5, &[(123, "Y"), (36, "6")]),
+        ] {
+            actions.extend(conn.on_bytes(&msg, t0));
+        }
+        assert!(written(&actions).is_empty());
+        assert_eq!(conn.state(), SessionState::Active);
+        let exec_ids: Vec<_> = conn
+            .backend()
+            .inbound_app_messages
+            .iter()
+            .map(|msg| field(msg, 17).unwrap())
+            .collect();
+        assert_eq!(exec_ids, ["exec-1", "exec-2", "exec-3"]);
+        assert_eq!(conn.send(order("1"), t0).err(), Some(SendError::ReadOnly));
+    }
+
+    #[tokio::test]
+    async fn handles_send_once_logged_on() {
+        let (stream, mut counterparty) = tokio::io::duplex(4096);
+        let conn = &mut conn();
+        let mut handle = conn.handle();
+        let first = handle.send(order("order-1")).await.unwrap();
+        let second = handle.send(order("order-2")).await.unwrap();
+        let counterparty = tokio::spawn(async move {
+            read_message(&mut counterparty).await;
+            counterparty
+                .write_all(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]))
+                .await
+                .unwrap();
+            let orders = [
+                read_message(&mut counterparty).await,
+                read_message(&mut counterparty).await,
+            ];
+            counterparty
+                .write_all(&counterparty_msg(b"5", 2, &[]))
+                .await
+                .unwrap();
+            read_message(&mut counterparty).await;
+            orders
+        });
+        start(conn, stream).await.unwrap();
+        let orders = counterparty.await.unwrap();
+
+        assert_eq!(field(&orders[0], 11).as_deref(), Some("order-1"));
+        assert_eq!(field(&orders[0], MSG_SEQ_NUM).as_deref(), Some("2"));
+        assert_eq!(field(&orders[1], 11).as_deref(), Some("order-2"));
+        assert_eq!(first.await, Ok(2));
+        assert_eq!(second.await, Ok(3));
+        let stored = conn.store_mut().fetch_outbound(2..4).unwrap();
+        assert_eq!(stored, vec![(2, orders[0].clone()), (3, orders[1].clone())]);
+    }
+
+    #[test]
+    fn undecodable_fields_are_rejected_without_a_gap() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let actions = conn.on_bytes(&counterparty_msg(b"D", 2, &[(11, "")]), t0);
+        assert!(!actions.iter().any(|a| matches!(a, Action::Disconnect(_))));
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("3"));
+        assert_eq!(field(&messages[0], 45).as_deref(), Some("2"));
+        assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("11"));
+        assert_eq!(field(&messages[0], 372).as_deref(), Some("D"));
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("4")
+        );
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+
+        let actions = conn.on_bytes(&counterparty_msg(b"0", 3, &[]), t0);
+        assert!(written(&actions).is_empty());
+        assert_eq!(conn.seq_numbers().next_inbound(), 4);
+    }
+
+    #[test]
+    fn duplicate_tags_are_rejected() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let msg = counterparty_msg(b"D", 2, &[(11, "A"), (11, "B")]);
+        let messages = split_messages(&written(&conn.on_bytes(&msg, t0)));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("3"));
+        assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("11"));
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("13")
+        );
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+    }
+
+    #[test]
+    fn messages_with_a_wrong_checksum_are_ignored() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let mut garbled = counterparty_msg(b"0", 2, &[]);
+        let len = garbled.len();
+        garbled[len - 4..len - 1].copy_from_slice(b"000");
+        let actions = conn.on_bytes(&garbled, t0);
+        assert!(!actions.iter().any(|a| matches!(a, Action::Disconnect(_))));
+        assert!(written(&actions).is_empty());
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+
+        let actions = conn.on_bytes(&counterparty_msg(b"0", 2, &[]), t0);
+        assert!(written(&actions).is_empty());
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+    }
+
+    #[test]
+    fn messages_with_a_wrong_body_length_are_ignored() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let garbled = counterparty_msg(b"0", 2, &[]);
+        let body_length = raw_field(&garbled, b'\x01', 9).unwrap();
+        let body_length = std::str::from_utf8(body_length).unwrap();
+        let garbled = String::from_utf8(garbled.clone()).unwrap().replacen(
+            &format!("\x019={}\x01", body_length),
+            &format!("\x019={}\x01", body_length.parse::<usize>().unwrap() + 20),
+            1,
+        );
+        let mut bytes = garbled.into_bytes();
+        bytes.extend_from_slice(&counterparty_msg(b"0", 3, &[]));
+        bytes.extend_from_slice(&counterparty_msg(b"0", 4, &[]));
+        let actions = conn.on_bytes(&bytes, t0);
+        assert!(!actions.iter().any(|a| matches!(a, Action::Disconnect(_))));
+        assert_eq!(conn.metrics().garbled_messages_ignored, 1);
+        assert!(conn
+            .backend()
+            .events
+            .iter()
+            .any(|event| matches!(event, SessionEvent::GarbledMessageIgnored { .. })));
+
+        // `Heartbeat <0>` number 3 is swallowed by the garbled message.
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("2"));
+        assert_eq!(field(&messages[0], 7).as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn overflowing_body_length_breaks_the_connection() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let actions = conn.on_bytes(
+            b"8=FIX.4.4\x019=18446744073709551615\x0135=0\x0110=000\x01",
+            t0,
+        );
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Decode(
+                DecodeError::Field {
+                    offset: 10,
+                    tag: Some(9),
+                    ..
+                }
+            ))))
+        ));
+    }
+
+    #[test]
+    fn zero_heart_bt_int_disables_heartbeats_and_timeouts() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.heartbeat_rule = HeartbeatRule::Exact(Duration::ZERO);
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        conn.on_connected(Role::Acceptor, decoder(), Utc::now(), t0);
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "0")]);
+        let actions = conn.on_bytes(&logon, t0);
+        assert_eq!(conn.state(), SessionState::Active);
+        assert!(!actions.iter().any(|a| matches!(a, Action::ArmTimer(_))));
+
+        let actions = conn.on_tick(t0 + Duration::from_secs(3600));
+        assert!(actions.is_empty());
+        assert_eq!(conn.state(), SessionState::Active);
+    }
+
+    #[test]
+    fn timestamps_follow_the_fake_clock() {
+        let t0 = Instant::now();
+        let utc0 = NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+        let utc = |n| utc0 + chrono::Duration::seconds(n);
+        let conn = &mut conn();
+        let logon = written(&conn.on_connected(Role::Initiator, decoder(), utc0, t0));
+        assert_eq!(
+            field(&logon, SENDING_TIME).as_deref(),
+            Some("20200101-00:00:00.000")
+        );
+        let reply = counterparty_msg_sent_at(utc0, b"A", 1, &[(98, "0"), (108, "30")]);
+        conn.on_bytes(&reply, t0);
+
+        let heartbeat = written(&conn.on_tick(t0 + Duration::from_secs(30)));
+        assert_eq!(
+            field(&heartbeat, SENDING_TIME).as_deref(),
+            Some("20200101-00:00:30.000")
+        );
+        let resend_request = counterparty_msg_sent_at(utc(40), b"2", 2, &[(7, "1"), (16, "0")]);
+        let resent = written(&conn.on_bytes(&resend_request, t0 + Duration::from_secs(40)));
+        assert!(!resent.is_empty());
+        for msg in split_messages(&resent) {
+            assert_eq!(
+                field(&msg, SENDING_TIME).as_deref(),
+                Some("20200101-00:00:40.000")
+            );
+            assert_eq!(
+                field(&msg, ORIG_SENDING_TIME).as_deref(),
+                Some("20200101-00:00:40.000")
+            );
+        }
+    }
+
+    #[test]
+    fn heartbeat_is_sent_after_the_interval() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let conn = &mut logged_on(t0);
+        let actions = conn.on_tick(secs(29));
+        assert!(written(&actions).is_empty());
+        assert!(matches!(actions[..], [Action::ArmTimer(t)] if t == secs(30)));
+
+        let actions = conn.on_tick(secs(30));
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("0"));
+        assert!(matches!(actions.last(), Some(Action::ArmTimer(t)) if *t == secs(60)));
+    }
+
+    #[test]
+    fn silent_counterparty_is_logged_out_after_the_hard_tolerance() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let utc = |n| Utc::now() + chrono::Duration::seconds(n);
+        let conn = &mut logged_on(t0);
+        conn.on_bytes(&counterparty_msg_sent_at(utc(50), b"0", 2, &[]), secs(50));
+        let actions = conn.on_tick(secs(110));
+        assert!(matches!(actions.last(), Some(Action::ArmTimer(t)) if *t == secs(140)));
+
+        let actions = conn.on_tick(secs(140));
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&messages[0], TEXT), Some(errs::heartbeat_timeout()));
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Timeout)))
+        ));
+        assert_eq!(conn.state(), SessionState::LogoutSent);
+    }
+
+    #[test]
+    fn heartbeat_tolerances_are_configurable() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let mut config = Config::default();
+        config.heartbeat_soft_tolerance = 3;
+        config.heartbeat_hard_tolerance = 5;
+        let conn = &mut logged_on_with(config, t0);
+        conn.on_tick(secs(30));
+        let actions = conn.on_tick(secs(60));
+        let messages = split_messages(&written(&actions));
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("0"));
+        assert_eq!(messages.len(), 1);
+
+        let actions = conn.on_tick(secs(90));
+        let messages = split_messages(&written(&actions));
+        assert!(messages
+            .iter()
+            .any(|msg| field(msg, MSG_TYPE).as_deref() == Some("1")));
+        let actions = conn.on_tick(secs(149));
+        assert!(!actions.iter().any(|a| matches!(a, Action::Disconnect(_))));
+        let actions = conn.on_tick(secs(150));
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Timeout)))
+        ));
+    }
+
+    #[test]
+    fn unanswered_logon_times_out_without_logout() {
+        let t0 = Instant::now();
+        let conn = &mut conn();
+        let actions = conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        assert_eq!(split_messages(&written(&actions)).len(), 1);
+
+        let actions = conn.on_tick(t0 + Duration::from_secs(90));
+        assert!(matches!(
+            actions[..],
+            [Action::Disconnect(Err(ConnectionError::Timeout))]
+        ));
+        assert_eq!(conn.state(), SessionState::LogonSent);
+    }
+
+    #[test]
+    fn messages_can_be_split_across_chunks() {
+        let t0 = Instant::now();
+        let conn = &mut conn();
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        let mut bytes = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]);
+        bytes.extend(counterparty_msg(b"D", 2, &[(11, "order-1")]));
+        for chunk in bytes.chunks(7) {
+            let actions = conn.on_bytes(chunk, t0);
+            assert!(!matches!(actions.last(), Some(Action::Disconnect(_))));
+        }
+        assert_eq!(conn.state(), SessionState::Active);
+        assert_eq!(conn.backend().inbound_app_messages.len(), 1);
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+    }
+
+    #[test]
+    fn logout_waits_for_confirmation_and_honors_resend_requests() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let conn = &mut logged_on(t0);
+        let actions = conn.logout(Some("End of day"), secs(1));
+        let messages = split_messages(&written(&actions));
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&messages[0], TEXT).as_deref(), Some("End of day"));
+        assert!(matches!(actions.last(), Some(Action::ArmTimer(t)) if *t == secs(11)));
+        assert_eq!(conn.state(), SessionState::LogoutSent);
+
+        let resend_request = counterparty_msg(b"2", 2, &[(7, "1"), (16, "0")]);
+        let actions = conn.on_bytes(&resend_request, secs(2));
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("4"));
+        assert_eq!(field(&messages[0], NEW_SEQ_NO).as_deref(), Some("3"));
+        assert!(matches!(actions.last(), Some(Action::ArmTimer(_))));
+
+        let actions = conn.on_bytes(&counterparty_msg(b"5", 3, &[]), secs(3));
+        assert!(matches!(actions[..], [Action::Disconnect(Ok(()))]));
+    }
+
+    #[test]
+    fn unconfirmed_logout_times_out() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let actions = conn.logout(None, t0);
+        let messages = split_messages(&written(&actions));
+        assert_eq!(field(&messages[0], TEXT), Some(errs::logout_requested()));
+
+        let actions = conn.on_tick(t0 + Duration::from_secs(10));
+        assert!(matches!(
+            actions[..],
+            [Action::Disconnect(Err(ConnectionError::Timeout))]
+        ));
+    }
+
+    #[test]
+    fn inbound_logout_is_confirmed() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let actions = conn.on_bytes(&counterparty_msg(b"5", 2, &[(58, "Bye")]), t0);
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&messages[0], TEXT), Some(errs::logout_confirmed()));
+        assert!(matches!(actions.last(), Some(Action::Disconnect(Ok(())))));
+        assert_eq!(conn.state(), SessionState::LogoutReceived);
+    }
+
+    /// Lets the soft tolerance expire and returns the `TestReqID <112>` of the
+    /// resulting `TestRequest <1>`.
+    fn expect_test_request(conn: &mut FixConnection<TestBackend>, now: Instant) -> String {
+        let actions = conn.on_tick(now);
+        let messages = split_messages(&written(&actions));
+        let test_request = messages
+            .iter()
+            .find(|msg| field(msg, MSG_TYPE).as_deref() == Some("1"))
+            .expect("No TestRequest <1>");
+        field(test_request, TEST_REQ_ID).unwrap()
+    }
+
+    #[test]
+    fn only_matching_heartbeat_is_proof_of_life() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let conn = &mut logged_on(t0);
+        let test_req_id = expect_test_request(conn, secs(60));
+        assert!(!test_req_id.is_empty());
+
+        let utc = |n| Utc::now() + chrono::Duration::seconds(n);
+        let order = counterparty_msg_sent_at(utc(61), b"D", 2, &[(11, "order-1")]);
+        conn.on_bytes(&order, secs(61));
+        let heartbeat = counterparty_msg_sent_at(utc(62), b"0", 3, &[(112, "other")]);
+        conn.on_bytes(&heartbeat, secs(62));
+        conn.on_bytes(&counterparty_msg_sent_at(utc(63), b"0", 4, &[]), secs(63));
+        assert_eq!(conn.state(), SessionState::Active);
+        let actions = conn.on_tick(secs(90));
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Timeout)))
+        ));
+    }
+
+    #[test]
+    fn matching_heartbeat_resets_the_tolerances() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let conn = &mut logged_on(t0);
+        let test_req_id = expect_test_request(conn, secs(60));
+
+        let sending_time = Utc::now() + chrono::Duration::seconds(61);
+        let heartbeat = counterparty_msg_sent_at(sending_time, b"0", 2, &[(112, &test_req_id)]);
+        conn.on_bytes(&heartbeat, secs(61));
+        let actions = conn.on_tick(secs(90));
+        assert!(matches!(actions.last(), Some(Action::ArmTimer(t)) if *t == secs(120)));
+
+        // A new, different TestReqID after the next silence.
+        assert_ne!(expect_test_request(conn, secs(121)), test_req_id);
+    }
+
+    #[test]
+    fn session_events_are_reported_and_counted() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let utc = |n| Utc::now() + chrono::Duration::seconds(n);
+        let conn = &mut logged_on(t0);
+        let test_req_id = expect_test_request(conn, secs(60));
+        let heartbeat = counterparty_msg_sent_at(utc(61), b"0", 2, &[(112, &test_req_id)]);
+        conn.on_bytes(&heartbeat, secs(61) + Duration::from_millis(250));
+        let reject = counterparty_msg_sent_at(utc(62), b"3", 3, &[(45, "2"), (373, "5")]);
+        conn.on_bytes(&reject, secs(62));
+        let order = counterparty_msg_sent_at(utc(63), b"D", 5, &[(11, "order-1")]);
+        conn.on_bytes(&order, secs(63));
+
+        let latency = Duration::from_millis(1250);
+        assert_eq!(
+            conn.backend().events,
+            vec![
+                SessionEvent::LoggedOn,
+                SessionEvent::TestRequestAnswered { latency },
+                SessionEvent::RejectReceived {
+                    ref_seq_num: Some(2),
+                    reason: Some(SessionRejectReason::ValueIsIncorrect),
+                },
+                SessionEvent::GapDetected {
+                    expected: 4,
+                    received: 5
+                },
+                SessionEvent::ResendRequestSent(ResendRequestRange::new(4, None)),
+            ]
+        );
+        let metrics = conn.metrics();
+        assert_eq!(metrics.logons, 1);
+        assert_eq!(metrics.rejects_received, 1);
+        assert_eq!(metrics.gaps_detected, 1);
+        assert_eq!(metrics.resend_requests_sent, 1);
+        assert_eq!(metrics.last_test_request_latency, Some(latency));
+        assert_eq!(metrics.messages_in, 4);
+        let inbound = [heartbeat, reject, order]
+            .iter()
+            .map(Vec::len)
+            .sum::<usize>();
+        assert!(metrics.bytes_in > inbound as u64);
+    }
+
+    #[test]
+    fn raw_messages_and_events_are_logged() {
+        let t0 = Instant::now();
+        let dir = std::env::temp_dir().join(format!("fefix-log-{}", uuid::Uuid::new_v4()));
+        let log = FileLog::open(&dir, "FIX.4.4-A-B").unwrap();
+        let events_path = log.events_path().to_path_buf();
+        let mut conn = FixConnection::new(Config::default(), TestBackend::default()).with_log(log);
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]);
+        conn.on_bytes(&logon, t0);
+        conn.send(order("order-1"), t0).unwrap();
+
+        let entries = FileLogReader::open(&dir, "FIX.4.4-A-B")
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        let logged = entries
+            .iter()
+            .map(|entry| (entry.direction, field(&entry.message, 35)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logged,
+            vec![
+                (Some(Direction::Outgoing), Some("A".to_string())),
+                (Some(Direction::Incoming), Some("A".to_string())),
+                (Some(Direction::Outgoing), Some("D".to_string())),
+            ]
+        );
+        assert_eq!(entries[1].message, logon);
+        let events = std::fs::read_to_string(events_path).unwrap();
+        assert!(events.ends_with(" : Logged on\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn scheduled_conn(window: Range<DateTime<Utc>>) -> FixConnection<TestBackend> {
+        let mut config = Config::default();
+        config.schedule = Some(Schedule::daily(
+            window.start.time(),
+            window.end.time(),
+            crate::field_types::Tz::UTC,
+        ));
+        let mut store = MemoryStore::new();
+        store
+            .store_outbound(1, &stored_msg(b"D", 1, &[(11, "order-1")]))
+            .unwrap();
+        store
+            .set_seq_numbers(SeqNumbers {
+                next_inbound: 5,
+                next_outbound: 7,
+            })
+            .unwrap();
+        FixConnection::new(config, TestBackend::default()).with_store(store)
+    }
+
+    #[test]
+    fn schedule_resets_seq_numbers_and_logs_out_at_the_end_of_the_window() {
+        let t0 = Instant::now();
+        let hour = chrono::Duration::hours(1);
+        let utc_now = Utc::now() + chrono::Duration::days(2);
+        let conn = &mut scheduled_conn(utc_now - hour..utc_now + hour);
+
+        let actions = conn.on_connected(Role::Initiator, decoder(), utc_now, t0);
+        let logon = &split_messages(&written(&actions))[0];
+        assert_eq!(field(logon, MSG_SEQ_NUM).as_deref(), Some("1"));
+        assert!(conn.store_mut().fetch_outbound(1..2).unwrap()[0].1 == logon[..]);
+        let logon = counterparty_msg_sent_at(utc_now, b"A", 1, &[(98, "0"), (108, "30")]);
+        conn.on_bytes(&logon, t0);
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+
+        let end = t0 + Duration::from_secs(3600);
+        let actions = conn.on_tick(end);
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&messages[0], TEXT), Some(errs::end_of_session()));
+        assert_eq!(conn.state(), SessionState::LogoutSent);
+    }
+
+    #[test]
+    fn connections_outside_of_the_schedule_are_closed() {
+        let t0 = Instant::now();
+        let hour = chrono::Duration::hours(1);
+        let utc_now = Utc::now();
+        let conn = &mut scheduled_conn(utc_now + hour..utc_now + hour * 2);
+
+        let actions = conn.on_connected(Role::Acceptor, decoder(), utc_now, t0);
+        assert!(matches!(
+            actions[..],
+            [Action::Disconnect(Err(ConnectionError::OutsideOfSchedule))]
+        ));
+        assert_eq!(conn.seq_numbers().next_outbound(), 7);
+    }
+
+    #[tokio::test]
+    async fn reconnect_fails_over_and_resumes_seq_numbers() {
+        let mut config = Config::default();
+        config.backoff = crate::session::Backoff {
+            initial: Duration::from_millis(1),
+            jitter: 0.0,
+            ..Default::default()
+        };
+        let (first, mut counterparty) = tokio::io::duplex(4096);
+        let (second, mut counterparty_again) = tokio::io::duplex(4096);
+        let counterparty = tokio::spawn(async move {
+            read_message(&mut counterparty).await;
+            counterparty
+                .write_all(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]))
+                .await
+                .unwrap();
+            // The line drops.
+            drop(counterparty);
+            let logon = read_message(&mut counterparty_again).await;
+            for msg in [
+                counterparty_msg(b"A", 2, &[(98, "0"), (108, "30")]),
+                counterparty_msg(b"5", 3, &[]),
+            ] {
+                counterparty_again.write_all(&msg).await.unwrap();
+            }
+            read_message(&mut counterparty_again).await;
+            logon
+        });
+        let mut transports = vec![Ok(second), Ok(first), Err(io::ErrorKind::ConnectionRefused)];
+        let connect = |_: &&str| {
+            let transport = transports.pop().unwrap().map(|stream| {
+                let (input, output) = tokio::io::split(stream);
+                (input.compat(), output.compat_write())
+            });
+            std::future::ready(transport.map_err(io::Error::from))
+        };
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        conn.start_with_reconnect(&["primary", "backup"], connect, decoder)
+            .await
+            .unwrap();
+        let logon = counterparty.await.unwrap();
+
+        assert_eq!(field(&logon, MSG_SEQ_NUM).as_deref(), Some("2"));
+        assert_eq!(
+            conn.backend().attempts,
+            vec![(1, 0, false), (1, 1, false), (0, 1, true)]
+        );
+        assert_eq!(conn.seq_numbers().next_inbound(), 4);
+    }
+
+    #[tokio::test]
+    async fn reconnect_backs_off_and_fails_over_after_a_dropped_session() {
+        let mut config = Config::default();
+        config.backoff = crate::session::Backoff {
+            initial: Duration::from_millis(1),
+            multiplier: 2,
+            jitter: 0.0,
+            max_attempts: Some(3),
+            ..Default::default()
+        };
+        let (first, mut counterparty) = tokio::io::duplex(4096);
+        let counterparty = tokio::spawn(async move {
+            read_message(&mut counterparty).await;
+            counterparty
+                .write_all(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]))
+                .await
+                .unwrap();
+            // The line drops, and nobody answers anymore.
+            drop(counterparty);
+        });
+        let mut transports = vec![
+            Err(io::ErrorKind::ConnectionRefused),
+            Err(io::ErrorKind::ConnectionRefused),
+            Ok(first),
+        ];
+        let connect = |_: &&str| {
+            let transport = transports.pop().unwrap().map(|stream| {
+                let (input, output) = tokio::io::split(stream);
+                (input.compat(), output.compat_write())
+            });
+            std::future::ready(transport.map_err(io::Error::from))
+        };
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        let result = conn
+            .start_with_reconnect(&["primary", "backup"], connect, decoder)
+            .await;
+        counterparty.await.unwrap();
+
+        assert!(matches!(result, Err(ConnectionError::Io(_))));
+        assert_eq!(
+            conn.backend().attempts,
+            vec![(1, 0, false), (2, 0, false), (3, 1, false)]
+        );
+        assert_eq!(
+            conn.backend().retry_delays,
+            vec![
+                Some(Duration::from_millis(1)),
+                Some(Duration::from_millis(2)),
+                None
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reconnect_waits_for_the_schedule_before_connecting() {
+        let window_start = Utc::now() + chrono::Duration::seconds(2);
+        let mut config = Config::default();
+        config.schedule = Some(Schedule::daily(
+            window_start.time(),
+            (window_start + chrono::Duration::hours(1)).time(),
+            crate::field_types::Tz::UTC,
+        ));
+        config.backoff = crate::session::Backoff {
+            max_attempts: Some(1),
+            ..Default::default()
+        };
+        let mut connected_at = None;
+        let connect = |_: &&str| {
+            connected_at = Some(Utc::now());
+            std::future::ready(Err::<(testing::DuplexStream, testing::DuplexStream), _>(
+                io::Error::from(io::ErrorKind::ConnectionRefused),
+            ))
+        };
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        let result = conn
+            .start_with_reconnect(&["primary"], connect, decoder)
+            .await;
+        assert!(matches!(result, Err(ConnectionError::Io(_))));
+        assert!(connected_at.unwrap() >= window_start);
+    }
+
+    /// A [`MemoryStore`] that fails on demand.
+    #[derive(Debug, Default)]
+    struct FailingStore {
+        inner: MemoryStore,
+        fail: bool,
+    }
+
+    impl FailingStore {
+        fn check(&self) -> io::Result<()> {
+            if self.fail {
+                Err(io::Error::new(io::ErrorKind::Other, "disk full"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl MessageStore for FailingStore {
+        type Error = io::Error;
+
+        fn store_outbound(&mut self, msg_seq_num: u64, message: &[u8]) -> io::Result<()> {
+            self.check()?;
+            self.inner.store_outbound(msg_seq_num, message).ok();
+            Ok(())
+        }
+
+        fn fetch_outbound(&mut self, range: Range<u64>) -> io::Result<Vec<(u64, Vec<u8>)>> {
+            self.check()?;
+            Ok(self.inner.fetch_outbound(range).unwrap_or_default())
+        }
+
+        fn seq_numbers(&self) -> SeqNumbers {
+            self.inner.seq_numbers()
+        }
+
+        fn set_seq_numbers(&mut self, seq_numbers: SeqNumbers) -> io::Result<()> {
+            self.check()?;
+            self.inner.set_seq_numbers(seq_numbers).ok();
+            Ok(())
+        }
+
+        fn creation_time(&self) -> DateTime<Utc> {
+            self.inner.creation_time()
+        }
+
+        fn reset(&mut self) -> io::Result<()> {
+            self.check()?;
+            self.inner.reset().ok();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn store_failures_stop_writes_and_disconnect() {
+        let t0 = Instant::now();
+        let conn = &mut FixConnection::new(Config::default(), TestBackend::default())
+            .with_store(FailingStore::default());
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        conn.on_bytes(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]), t0);
+        assert_eq!(conn.state(), SessionState::Active);
+
+        conn.store_mut().fail = true;
+        let actions = conn.send(order("1"), t0).unwrap();
+        assert!(written(&actions).is_empty());
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Store(_))))
+        ));
+
+        // Resend requests can't be served either.
+        let resend_request = counterparty_msg(b"2", 2, &[(7, "1"), (16, "0")]);
+        let actions = conn.on_bytes(&resend_request, t0);
+        assert!(written(&actions).is_empty());
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Store(_))))
+        ));
+    }
+
+    #[tokio::test]
+    async fn store_failures_fail_unwritten_completions() {
+        let t0 = Instant::now();
+        let conn = &mut FixConnection::new(Config::default(), TestBackend::default())
+            .with_store(FailingStore::default());
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        conn.on_bytes(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]), t0);
+        let mut handle = conn.handle();
+        let completions = [
+            handle.try_send(order("1")).unwrap(),
+            handle.try_send(order("2")).unwrap(),
+        ];
+        let send_queue = conn.send_queue.as_mut().unwrap();
+        let requests = [
+            send_queue.try_next().unwrap().unwrap(),
+            send_queue.try_next().unwrap().unwrap(),
+        ];
+
+        let [first, second] = requests;
+        let actions = conn.on_send_request(first, t0);
+        assert!(!written(&actions).is_empty());
+        conn.complete_sent();
+        conn.store_mut().fail = true;
+        let actions = conn.on_send_request(second, t0);
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Store(_))))
+        ));
+        conn.complete_sent();
+
+        let [first, second] = completions;
+        assert_eq!(first.await, Ok(2));
+        assert_eq!(second.await, Err(SendError::Disconnected));
+    }
+
+    #[test]
+    fn initiator_resets_seq_numbers_on_logon() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.reset_on_logon = true;
+        let mut store = MemoryStore::new();
+        let mut seq_numbers = SeqNumbers::default();
+        seq_numbers.next_inbound = 5;
+        seq_numbers.next_outbound = 7;
+        store.set_seq_numbers(seq_numbers).unwrap();
+        let conn = &mut FixConnection::new(config, TestBackend::default()).with_store(store);
+        assert_eq!(conn.seq_numbers().next_outbound(), 7);
+
+        let logon = written(&conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0));
+        assert_eq!(field(&logon, MSG_SEQ_NUM).as_deref(), Some("1"));
+        assert_eq!(field(&logon, RESET_SEQ_NUM_FLAG).as_deref(), Some("Y"));
+        let reply = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30"), (141, "Y")]);
+        conn.on_bytes(&reply, t0);
+        assert_eq!(conn.state(), SessionState::Active);
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+        assert_eq!(conn.seq_numbers().next_outbound(), 2);
+    }
+
+    #[test]
+    fn acceptor_honors_reset_seq_num_flag() {
+        let t0 = Instant::now();
+        let conn = &mut conn();
+        conn.on_connected(Role::Acceptor, decoder(), Utc::now(), t0);
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]);
+        conn.on_bytes(&logon, t0);
+        conn.on_bytes(&counterparty_msg(b"D", 2, &[(11, "order-1")]), t0);
+        assert_eq!(conn.seq_numbers().next_outbound(), 2);
+
+        // Intraday reset, requested by the counterparty.
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30"), (141, "Y")]);
+        let reply = written(&conn.on_bytes(&logon, t0));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("A"));
+        assert_eq!(field(&reply, MSG_SEQ_NUM).as_deref(), Some("1"));
+        assert_eq!(field(&reply, RESET_SEQ_NUM_FLAG).as_deref(), Some("Y"));
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+        assert_eq!(conn.seq_numbers().next_outbound(), 2);
+        assert_eq!(conn.state(), SessionState::Active);
+
+        // Intraday reset, requested by us.
+        let logon = written(&conn.reset_seq_numbers(t0));
+        assert_eq!(field(&logon, MSG_SEQ_NUM).as_deref(), Some("1"));
+        assert_eq!(field(&logon, RESET_SEQ_NUM_FLAG).as_deref(), Some("Y"));
+        conn.on_bytes(&counterparty_msg(b"0", 2, &[]), t0);
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+        let confirmation = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30"), (141, "Y")]);
+        assert!(written(&conn.on_bytes(&confirmation, t0)).is_empty());
+        assert_eq!(conn.seq_numbers().next_inbound(), 2);
+        assert_eq!(conn.seq_numbers().next_outbound(), 2);
+    }
+
+    #[tokio::test]
+    async fn accept_refuses_max_msg_seq_num() {
+        let logon = counterparty_msg(b"A", u64::MAX, &[(98, "0"), (108, "30"), (141, "Y")]);
+        let (result, reply) = logon_with_acceptor(Config::default(), logon).await;
+
+        let text = errs::msg_seq_num(1);
+        assert!(matches!(result, Err(ConnectionError::Logon(t)) if t == text));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&reply, TEXT), Some(text));
+    }
+
+    #[test]
+    fn reset_logon_with_max_msg_seq_num_is_refused() {
+        let t0 = Instant::now();
+        let conn = &mut conn();
+        conn.on_connected(Role::Acceptor, decoder(), Utc::now(), t0);
+        conn.on_bytes(&counterparty_msg(b"A", 1, &[(98, "0"), (108, "30")]), t0);
+        assert_eq!(conn.state(), SessionState::Active);
+
+        let logon = counterparty_msg(b"A", u64::MAX, &[(98, "0"), (108, "30"), (141, "Y")]);
+        let reply = written(&conn.on_bytes(&logon, t0));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(field(&reply, TEXT), Some(errs::msg_seq_num(1)));
+        assert_eq!(conn.state(), SessionState::LogoutSent);
+    }
+
+    #[test]
+    fn fixt_logon_negotiates_default_appl_ver_id() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.begin_string = "FIXT.1.1".to_string();
+        config.default_appl_ver_id = Some("9".to_string());
+        let fixt_logon = |fields: &[(u32, &str)]| {
+            versioned_counterparty_msg(b"FIXT.1.1", Utc::now(), b"A", 1, fields)
+        };
+
+        let conn = &mut FixConnection::new(config.clone(), TestBackend::default());
+        conn.on_connected(Role::Acceptor, decoder(), Utc::now(), t0);
+        let reply = written(&conn.on_bytes(&fixt_logon(&[(98, "0"), (108, "30")]), t0));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(
+            field(&reply, TEXT),
+            Some(errs::missing_field("DefaultApplVerID", DEFAULT_APPL_VER_ID))
+        );
+
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        conn.on_connected(Role::Acceptor, decoder(), Utc::now(), t0);
+        assert_eq!(conn.default_appl_ver_id(), Some(&b"9"[..]));
+        let logon = fixt_logon(&[(98, "0"), (108, "30"), (1137, "8")]);
+        let reply = written(&conn.on_bytes(&logon, t0));
+        assert_eq!(field(&reply, MSG_TYPE).as_deref(), Some("A"));
+        assert_eq!(field(&reply, DEFAULT_APPL_VER_ID).as_deref(), Some("9"));
+        assert_eq!(conn.state(), SessionState::Active);
+        assert_eq!(conn.default_appl_ver_id(), Some(&b"8"[..]));
+        let decoder = conn.decoder.as_ref().unwrap();
+        assert_eq!(decoder.default_appl_ver_id(), Some(&b"8"[..]));
+    }
+
+    #[test]
+    fn next_expected_msg_seq_num_triggers_resend() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.enable_next_expected_msg_seq_num = true;
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        for (msg_seq_num, cl_ord_id) in [(1, "order-1"), (2, "order-2")] {
+            let msg = stored_msg(b"D", msg_seq_num, &[(11, cl_ord_id)]);
+            conn.store_mut().store_outbound(msg_seq_num, &msg).unwrap();
+        }
+        conn.msg_seq_num_outbound.set_expected(3);
+        conn.on_connected(Role::Acceptor, decoder(), Utc::now(), t0);
+
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30"), (789, "2")]);
+        let replies = split_messages(&written(&conn.on_bytes(&logon, t0)));
+        assert_eq!(replies.len(), 2);
+        assert_eq!(field(&replies[0], MSG_TYPE).as_deref(), Some("A"));
+        assert_eq!(field(&replies[0], MSG_SEQ_NUM).as_deref(), Some("3"));
+        assert_eq!(
+            field(&replies[0], NEXT_EXPECTED_MSG_SEQ_NUM).as_deref(),
+            Some("2")
+        );
+        assert_eq!(field(&replies[1], MSG_SEQ_NUM).as_deref(), Some("2"));
+        assert_eq!(field(&replies[1], 11).as_deref(), Some("order-2"));
+        assert_eq!(field(&replies[1], POSS_DUP_FLAG).as_deref(), Some("Y"));
+    }
+
+    #[test]
+    fn next_expected_msg_seq_num_beyond_last_sent_is_refused() {
+        let t0 = Instant::now();
+        let mut config = Config::default();
+        config.enable_next_expected_msg_seq_num = true;
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        conn.on_connected(Role::Initiator, decoder(), Utc::now(), t0);
+        let logon = counterparty_msg(b"A", 1, &[(98, "0"), (108, "30"), (789, "5")]);
+        let actions = conn.on_bytes(&logon, t0);
+
+        let text = errs::inbound_seqnum();
+        assert_eq!(field(&written(&actions), TEXT), Some(text.clone()));
+        assert!(matches!(
+            actions.last(),
+            Some(Action::Disconnect(Err(ConnectionError::Logon(t)))) if *t == text
+        ));
+    }
+
+    #[test]
+    fn inaccurate_sending_time_is_rejected_then_logged_out() {
+        let t0 = Instant::now();
+        let conn = &mut logged_on(t0);
+        let sending_time = Utc::now() - chrono::Duration::seconds(60);
+        let msg = counterparty_msg_sent_at(sending_time, b"D", 2, &[(11, "order-1")]);
+        let actions = conn.on_bytes(&msg, t0);
+
+        let messages = split_messages(&written(&actions));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(field(&messages[0], MSG_TYPE).as_deref(), Some("3"));
+        assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("52"));
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("10")
+        );
+        assert_eq!(field(&messages[1], MSG_TYPE).as_deref(), Some("5"));
+        assert!(matches!(actions.last(), Some(Action::Disconnect(Ok(())))));
+        assert!(conn.backend().inbound_app_messages.is_empty());
+        assert_eq!(conn.seq_numbers().next_inbound(), 3);
+    }
+
+    #[test]
+    fn possible_duplicates_need_an_earlier_orig_sending_time() {
+        let conn = &mut conn();
+        feed(
+            conn,
+            &counterparty_msg(b"D", 1, &[(43, "Y"), (11, "order-1")]),
+        );
+        let reject = split_messages(conn.pending_outbound()).pop().unwrap();
+        assert_eq!(field(&reject, MSG_TYPE).as_deref(), Some("3"));
+        assert_eq!(field(&reject, REF_TAG_ID).as_deref(), Some("122"));
+        assert_eq!(field(&reject, SESSION_REJECT_REASON).as_deref(), Some("1"));
+        conn.clear_outbound();
+
+        // Gap fills are let through anyway.
+        feed(
+            conn,
+            &counterparty_msg(b"4", 2, &[(43, "Y"), (123, "Y"), (36, "3")]),
+        );
+        assert!(conn.pending_outbound().is_empty());
+
+        let orig_sending_time = (Utc::now() + chrono::Duration::hours(1)).naive_utc();
+        let orig_sending_time = orig_sending_time.format("%Y%m%d-%H:%M:%S").to_string();
+        let msg = counterparty_msg(b"D", 3, &[(43, "Y"), (122, &orig_sending_time)]);
+        assert_eq!(feed(conn, &msg), Response::TerminateTransport);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(field(&messages[0], REF_TAG_ID).as_deref(), Some("122"));
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("10")
+        );
+        assert_eq!(field(&messages[1], MSG_TYPE).as_deref(), Some("5"));
+        assert!(conn.backend().inbound_app_messages.is_empty());
+    }
+
+    #[test]
+    fn wrong_comp_ids_are_rejected_then_logged_out() {
+        let mut config = Config::default();
+        config.target_comp_id = "SOMEONE_ELSE".to_string();
+        let conn = &mut FixConnection::new(config, TestBackend::default());
+        let response = feed(conn, &counterparty_msg(b"D", 1, &[(11, "order-1")]));
+
+        assert_eq!(response, Response::TerminateTransport);
+        let messages = split_messages(conn.pending_outbound());
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            field(&messages[0], SESSION_REJECT_REASON).as_deref(),
+            Some("9")
+        );
+        assert_eq!(field(&messages[1], MSG_TYPE).as_deref(), Some("5"));
+        assert_eq!(
+            field(&messages[1], TEXT),
+            Some(errs::comp_ids(b"SOMEONE_ELSE", b"SENDER_COMP"))
+        );
+        assert!(conn.backend().inbound_app_messages.is_empty());
+    }
+}
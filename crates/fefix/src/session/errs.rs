@@ -25,6 +25,94 @@ pub fn msg_seq_num(seq_number: u64) -> String {
     format!("Invalid MsgSeqNum <34>, expected value {}", seq_number)
 }
 
+pub fn new_seq_no(expected: u64) -> String {
+    format!(
+        "Invalid NewSeqNo <36>, expected value greater than or equal to {}",
+        expected
+    )
+}
+
+pub fn begin_seq_no(last: u64) -> String {
+    format!(
+        "Invalid BeginSeqNo <7>, expected value between 1 and {}",
+        last
+    )
+}
+
+pub fn begin_string(expected: &[u8]) -> String {
+    format!(
+        "Invalid BeginString(8), expected value {}",
+        String::from_utf8_lossy(expected)
+    )
+}
+
+pub fn sender_comp_id(expected: &[u8]) -> String {
+    format!(
+        "Invalid SenderCompID(49), expected value {}",
+        String::from_utf8_lossy(expected)
+    )
+}
+
+pub fn target_comp_id(expected: &[u8]) -> String {
+    format!(
+        "Invalid TargetCompID(56), expected value {}",
+        String::from_utf8_lossy(expected)
+    )
+}
+
+pub fn comp_ids(sender_comp_id: &[u8], target_comp_id: &[u8]) -> String {
+    format!(
+        "CompID problem, expected SenderCompID(49) {} and TargetCompID(56) {}",
+        String::from_utf8_lossy(sender_comp_id),
+        String::from_utf8_lossy(target_comp_id),
+    )
+}
+
+pub fn incorrect_data_format(name: &str, tag: u32) -> String {
+    format!("Incorrect data format for {}({})", name, tag)
+}
+
+pub fn sending_time_accuracy(name: &str, tag: u32) -> String {
+    format!("{}({}) accuracy problem", name, tag)
+}
+
+pub fn heartbeat_timeout() -> String {
+    "Heartbeat timeout, the counterparty stopped responding".to_string()
+}
+
+pub fn logout_confirmed() -> String {
+    "Logout(5) confirmed".to_string()
+}
+
+pub fn logout_requested() -> String {
+    "Logout(5) requested".to_string()
+}
+
+pub fn end_of_session() -> String {
+    "End of the FIX session's schedule".to_string()
+}
+
+pub fn unknown_session() -> String {
+    "Unknown FIX session, check BeginString(8), SenderCompID(49) and TargetCompID(56)".to_string()
+}
+
+pub fn already_logged_on() -> String {
+    "The FIX session is already logged on".to_string()
+}
+
+pub fn logon_expected() -> String {
+    "Expected Logon(A) as the first message".to_string()
+}
+
+pub fn logon_invalid() -> String {
+    "Invalid Logon(A)".to_string()
+}
+
+pub fn testing_env() -> String {
+    "TestMessageIndicator(464) was not set to 'Y' but the environment is a testing environment"
+        .to_string()
+}
+
 pub fn production_env() -> String {
     "TestMessageIndicator(464) was set to 'Y' but the environment is a production environment"
         .to_string()
@@ -1,5 +1,11 @@
+// `FixConnection` no longer uses this module, which is kept for backwards
+// compatibility only.
+#![allow(deprecated)]
+
+use super::{HeartbeatTimers, TimerEvent};
 use crate::tagvalue::{DecodeError, DecoderStreaming, Message};
 use crate::StreamingDecoder;
+use futures::future::Fuse;
 use futures::{select, AsyncRead, AsyncReadExt, FutureExt};
 use futures_timer::Delay;
 use std::io;
@@ -10,15 +16,19 @@ use std::time::{Duration, Instant};
 /// This event loop allows FIX connectors to delegate event-tracking logic to a
 /// single entity. This event loop keeps track of such events within a FIX
 /// session. See [`LlEvent`] for more information.
+///
+/// New code should use [`FixConnection`](super::FixConnection), which tracks
+/// heartbeats, seq. number gaps and resends by itself.
 #[derive(Debug)]
+#[deprecated(
+    note = "`FixConnection` doesn't use it anymore; drive the sans-IO `FixConnection::on_bytes` and `FixConnection::on_tick` instead, and see `Configure::heartbeat_soft_tolerance` and `Configure::heartbeat_hard_tolerance`"
+)]
 pub struct LlEventLoop<I> {
     decoder: DecoderStreaming<Vec<u8>>,
     input: I,
-    heartbeat: Duration,
-    heartbeat_soft_tolerance: Duration,
-    heartbeat_hard_tolerance: Duration,
-    last_reset: Instant,
-    last_heartbeat: Instant,
+    timers: HeartbeatTimers,
+    bytes_filled: usize,
+    is_message_ready: bool,
     is_alive: bool,
 }
 
@@ -29,16 +39,12 @@ where
     /// Creates a new [`LlEventLoop`] with the provided `decoder` and
     /// `heartbeat`. Events will be read from `input`.
     pub fn new(decoder: DecoderStreaming<Vec<u8>>, input: I, heartbeat: Duration) -> Self {
-        let heartbeat_soft_tolerance = heartbeat * 2;
-        let heartbeat_hard_tolerance = heartbeat * 3;
         Self {
             decoder,
             input,
-            heartbeat,
-            heartbeat_soft_tolerance,
-            heartbeat_hard_tolerance,
-            last_reset: Instant::now(),
-            last_heartbeat: Instant::now(),
+            timers: HeartbeatTimers::new(heartbeat, Instant::now()),
+            bytes_filled: 0,
+            is_message_ready: false,
             is_alive: true,
         }
     }
@@ -46,49 +52,66 @@ where
     /// How long after a missed `Heartbeat <0>` should we send a `TestRequest
     /// <1>`?
     pub fn set_soft_tolerance(&mut self, soft_tolerance: Duration) {
-        self.heartbeat_soft_tolerance = soft_tolerance;
+        self.timers.set_soft_tolerance(soft_tolerance);
     }
 
     /// How long after a missed `Heartbeat <0>` should we send a `Logout <5>`?
     pub fn set_hard_tolerance(&mut self, hard_tolerance: Duration) {
-        self.heartbeat_hard_tolerance = hard_tolerance;
+        self.timers.set_hard_tolerance(hard_tolerance);
     }
 
+    /// Waits for the next [`LlEvent`]. Returns [`None`] once the event loop
+    /// is dead, i.e. after a [`LlEvent::BadMessage`], [`LlEvent::IoError`] or
+    /// [`LlEvent::Logout`].
+    ///
+    /// Partially read messages are retained across calls, so it's safe to
+    /// call this method again after a timer event.
     pub async fn next_event<'a>(&'a mut self) -> Option<LlEvent<'a>> {
-        let mut buf_filled_len = 0;
-        let mut buf = self.decoder.fillable();
+        if self.is_message_ready {
+            self.decoder.clear();
+            self.bytes_filled = 0;
+            self.is_message_ready = false;
+        }
 
         loop {
             if !self.is_alive {
                 return None;
             }
 
-            let now = Instant::now();
-            let mut timer_heartbeat = Delay::new(now - self.last_heartbeat + self.heartbeat).fuse();
-            let mut timer_test_request =
-                Delay::new(now - self.last_reset + self.heartbeat_soft_tolerance).fuse();
-            let mut timer_logout =
-                Delay::new(now - self.last_reset + self.heartbeat_hard_tolerance).fuse();
+            let num_bytes_required = self.decoder.num_bytes_required();
+            if self.decoder.buffer().len() < num_bytes_required {
+                self.decoder.buffer().resize(num_bytes_required, 0);
+            }
+
+            let mut timer = match self.timers.next_deadline() {
+                Some(deadline) => {
+                    Delay::new(deadline.saturating_duration_since(Instant::now())).fuse()
+                }
+                None => Fuse::terminated(),
+            };
+            let buf = &mut self.decoder.buffer().as_mut_slice()[self.bytes_filled..];
             let mut read_result = self.input.read(buf).fuse();
 
             select! {
                 read_result = read_result => {
                     match read_result {
                         Err(e) => {
+                            self.is_alive = false;
                             return Some(LlEvent::IoError(e));
                         }
+                        Ok(0) => {
+                            self.is_alive = false;
+                            return Some(LlEvent::IoError(io::ErrorKind::UnexpectedEof.into()));
+                        }
                         Ok(num_bytes) => {
-                            buf_filled_len += num_bytes;
-                            if buf_filled_len < buf.len() {
+                            self.bytes_filled += num_bytes;
+                            if self.bytes_filled < num_bytes_required {
                                 continue;
                             }
 
-                            let result = self.decoder.try_parse();
-                            buf_filled_len = 0;
-                            buf = &mut self.decoder.fillable()[buf_filled_len..];
-
-                            match result {
+                            match self.decoder.try_parse() {
                                 Ok(Some(())) => {
+                                    self.is_message_ready = true;
                                     let msg = self.decoder.message();
                                     return Some(LlEvent::Message(msg));
                                 }
@@ -103,16 +126,16 @@ where
                         }
                     };
                 },
-                () = timer_heartbeat => {
-                    self.last_heartbeat = Instant::now();
-                    return Some(LlEvent::Heartbeat);
-                },
-                () = timer_test_request => {
-                    return Some(LlEvent::TestRequest);
-                },
-                () = timer_logout => {
-                    self.is_alive = false;
-                    return Some(LlEvent::Logout);
+                () = timer => {
+                    match self.timers.poll(Instant::now()) {
+                        Some(TimerEvent::Heartbeat) => return Some(LlEvent::Heartbeat),
+                        Some(TimerEvent::TestRequest) => return Some(LlEvent::TestRequest),
+                        Some(TimerEvent::Logout) => {
+                            self.is_alive = false;
+                            return Some(LlEvent::Logout);
+                        }
+                        None => continue,
+                    }
                 }
             }
         }
@@ -120,12 +143,13 @@ where
 
     /// Resets the FIX counterparty's `Heartbeat <0>` -associated timers.
     pub fn ping_heartbeat(&mut self) {
-        self.last_reset = Instant::now();
+        self.timers.on_inbound(Instant::now());
     }
 }
 
 /// A low level event produced by a [`LlEventLoop`].
 #[derive(Debug)]
+#[deprecated(note = "`LlEventLoop` is deprecated")]
 pub enum LlEvent<'a> {
     /// Incoming FIX message.
     Message(Message<'a, &'a [u8]>),
@@ -148,41 +172,32 @@ pub enum LlEvent<'a> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::session::testing::{duplex, DuplexStream};
     use crate::tagvalue::Decoder;
-    use tokio::io::AsyncWriteExt;
-    use tokio::net::{TcpListener, TcpStream};
-    use tokio_util::compat::*;
-
-    async fn produce_events(events: Vec<(&'static [u8], Duration)>) -> TcpStream {
-        let tcp_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let local_addr = tcp_listener.local_addr().unwrap();
+    use futures::AsyncWriteExt;
 
+    fn produce_events(events: Vec<(&'static [u8], Duration)>) -> DuplexStream {
+        let (input, mut counterparty) = duplex(4096);
         tokio::spawn(async move {
-            let mut stream = TcpStream::connect(local_addr).await.unwrap();
             for (event_bytes, delay) in events.iter() {
-                stream.write(event_bytes).await.unwrap();
+                counterparty.write_all(event_bytes).await.unwrap();
                 tokio::time::sleep(*delay).await;
             }
         });
-
-        tcp_listener.accept().await.unwrap().0
+        input
     }
 
-    async fn new_event_loop(
-        events: Vec<(&'static [u8], Duration)>,
-    ) -> LlEventLoop<Compat<TcpStream>> {
-        let input = produce_events(events).await;
-
+    fn new_event_loop(events: Vec<(&'static [u8], Duration)>) -> LlEventLoop<DuplexStream> {
         LlEventLoop::new(
             Decoder::new(crate::Dictionary::fix44()).streaming(vec![]),
-            input.compat(),
+            produce_events(events),
             Duration::from_secs(3),
         )
     }
 
     #[tokio::test]
     async fn dead_input_triggers_logout() {
-        let mut event_loop = new_event_loop(vec![(b"8", Duration::from_secs(10))]).await;
+        let mut event_loop = new_event_loop(vec![(b"8", Duration::from_secs(10))]);
         let event = event_loop.next_event().await;
         assert!(matches!(event, Some(LlEvent::Heartbeat)));
         let event = event_loop.next_event().await;
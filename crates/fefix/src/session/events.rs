@@ -0,0 +1,153 @@
+use super::{
+    BusinessReject, BusinessRejectReason, Reject, ResendRequestRange, SessionRejectReason,
+};
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+
+/// A notable event of a [`FixConnection`](super::FixConnection), as reported
+/// to [`Backend::on_session_event`](super::Backend::on_session_event).
+///
+/// Every event is also counted by
+/// [`FixConnection::metrics`](super::FixConnection::metrics).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SessionEvent {
+    /// The `Logon <A>` handshake is over and both counterparties are logged
+    /// on.
+    LoggedOn,
+    /// We've sent a `Logout <5>`, either to start the logout handshake or to
+    /// confirm the counterparty's.
+    LogoutSent {
+        /// The `Text <58>` of the `Logout <5>`.
+        text: String,
+    },
+    /// The counterparty has sent a `Logout <5>`.
+    LogoutReceived {
+        /// The `Text <58>` of the `Logout <5>`, if any.
+        text: Option<String>,
+    },
+    /// An inbound message has a higher `MsgSeqNum <34>` than expected. A
+    /// `ResendRequest <2>` follows.
+    GapDetected {
+        /// The expected `MsgSeqNum <34>`.
+        expected: u64,
+        /// The `MsgSeqNum <34>` of the inbound message.
+        received: u64,
+    },
+    /// We've sent a `ResendRequest <2>`.
+    ResendRequestSent(ResendRequestRange),
+    /// The counterparty has sent a `ResendRequest <2>`, for the messages
+    /// within this range.
+    ResendRequestReceived(Range<u64>),
+    /// We've sent a `Reject <3>`.
+    RejectSent(Reject),
+    /// The counterparty has sent a `Reject <3>`.
+    RejectReceived {
+        /// The `RefSeqNum <45>` of the `Reject <3>`, if valid.
+        ref_seq_num: Option<u64>,
+        /// The `SessionRejectReason <373>` of the `Reject <3>`, if valid.
+        reason: Option<SessionRejectReason>,
+    },
+    /// We've sent a `BusinessMessageReject <j>`.
+    BusinessRejectSent(BusinessReject),
+    /// The counterparty has sent a `BusinessMessageReject <j>`.
+    BusinessRejectReceived {
+        /// The `RefSeqNum <45>` of the `BusinessMessageReject <j>`, if valid.
+        ref_seq_num: Option<u64>,
+        /// The `BusinessRejectReason <380>` of the `BusinessMessageReject
+        /// <j>`, if valid.
+        reason: Option<BusinessRejectReason>,
+    },
+    /// The counterparty has been silent for too long, even after a
+    /// `TestRequest <1>`. The FIX session is logged out.
+    HeartbeatTimeout,
+    /// The counterparty has answered our `TestRequest <1>`.
+    TestRequestAnswered {
+        /// The time between our `TestRequest <1>` and the `Heartbeat <0>`
+        /// that echoes it.
+        latency: Duration,
+    },
+    /// An inbound message with a wrong `BodyLength <9>` or `CheckSum <10>`
+    /// has been ignored, as if it had never been received.
+    GarbledMessageIgnored {
+        /// What's wrong with the message.
+        text: String,
+    },
+}
+
+impl fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LoggedOn => write!(f, "Logged on"),
+            Self::LogoutSent { text } => write!(f, "Sent Logout <5>: {}", text),
+            Self::LogoutReceived { text } => {
+                write!(f, "Received Logout <5>: {}", text.as_deref().unwrap_or("-"))
+            }
+            Self::GapDetected { expected, received } => write!(
+                f,
+                "MsgSeqNum <34> too high, expected {}, received {}",
+                expected, received
+            ),
+            Self::ResendRequestSent(range) => write!(
+                f,
+                "Sent ResendRequest <2> from {} to {}",
+                range.begin_seq_no(),
+                range.end_seq_no()
+            ),
+            Self::ResendRequestReceived(range) => write!(
+                f,
+                "Received ResendRequest <2> from {} to {}",
+                range.start,
+                range.end.saturating_sub(1)
+            ),
+            Self::RejectSent(reject) => write!(
+                f,
+                "Sent Reject <3> of {}: {}",
+                reject.ref_seq_num,
+                reject.reason.description()
+            ),
+            Self::RejectReceived {
+                ref_seq_num,
+                reason,
+            } => write!(
+                f,
+                "Received Reject <3> of {}: {}",
+                Optional(ref_seq_num),
+                Optional(&reason.map(|reason| reason.description()))
+            ),
+            Self::BusinessRejectSent(reject) => write!(
+                f,
+                "Sent BusinessMessageReject <j> of {}: {}",
+                Optional(&reject.ref_seq_num),
+                reject.reason.description()
+            ),
+            Self::BusinessRejectReceived {
+                ref_seq_num,
+                reason,
+            } => write!(
+                f,
+                "Received BusinessMessageReject <j> of {}: {}",
+                Optional(ref_seq_num),
+                Optional(&reason.map(|reason| reason.description()))
+            ),
+            Self::HeartbeatTimeout => write!(f, "Heartbeat timeout"),
+            Self::TestRequestAnswered { latency } => {
+                write!(f, "TestRequest <1> answered in {:?}", latency)
+            }
+            Self::GarbledMessageIgnored { text } => write!(f, "Ignored garbled message: {}", text),
+        }
+    }
+}
+
+/// Displays an optional value, or `-` if it's missing.
+struct Optional<'a, T>(&'a Option<T>);
+
+impl<T: fmt::Display> fmt::Display for Optional<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(value) => value.fmt(f),
+            None => f.write_str("-"),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::{OutboundMessage, SendError};
+use futures::channel::{mpsc, oneshot};
+use futures::SinkExt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Reports the outcome of a [`SendRequest`].
+pub(crate) type Completer = oneshot::Sender<Result<u64, SendError>>;
+
+/// An application message enqueued by a [`SessionHandle`].
+#[derive(Debug)]
+pub(crate) struct SendRequest {
+    pub msg: OutboundMessage,
+    pub completer: Completer,
+}
+
+/// A cloneable handle for sending application messages on a
+/// [`FixConnection`](super::FixConnection) from other tasks, as returned by
+/// [`FixConnection::handle`](super::FixConnection::handle).
+///
+/// Messages are enqueued in a bounded queue, which the
+/// [`FixConnection`](super::FixConnection) drains in order while it's logged
+/// on, filling in the `StandardHeader` and persisting each message to its
+/// [`MessageStore`](super::MessageStore) before writing it. The queue holds
+/// up to [`Configure::send_queue_capacity`](super::Configure::send_queue_capacity)
+/// messages, plus one for each [`SessionHandle`]; beyond that,
+/// [`SessionHandle::send`] waits for room and [`SessionHandle::try_send`]
+/// fails with [`SendError::QueueFull`].
+#[derive(Debug, Clone)]
+pub struct SessionHandle {
+    sender: mpsc::Sender<SendRequest>,
+}
+
+impl SessionHandle {
+    pub(crate) fn channel(capacity: usize) -> (Self, mpsc::Receiver<SendRequest>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        (Self { sender }, receiver)
+    }
+
+    /// Enqueues `msg`, waiting for room in the queue if needed. The returned
+    /// [`Completion`] resolves once `msg` has been written.
+    pub async fn send(&mut self, msg: OutboundMessage) -> Result<Completion, SendError> {
+        let (request, completion) = request(msg)?;
+        self.sender
+            .send(request)
+            .await
+            .map_err(|_| SendError::Closed)?;
+        Ok(completion)
+    }
+
+    /// Like [`SessionHandle::send`], but it fails with
+    /// [`SendError::QueueFull`] rather than waiting for room in the queue.
+    pub fn try_send(&mut self, msg: OutboundMessage) -> Result<Completion, SendError> {
+        let (request, completion) = request(msg)?;
+        self.sender.try_send(request).map_err(|err| {
+            if err.is_full() {
+                SendError::QueueFull
+            } else {
+                SendError::Closed
+            }
+        })?;
+        Ok(completion)
+    }
+}
+
+fn request(msg: OutboundMessage) -> Result<(SendRequest, Completion), SendError> {
+    if msg.is_session_message() {
+        return Err(SendError::SessionMessage);
+    }
+    let (completer, receiver) = oneshot::channel();
+    Ok((SendRequest { msg, completer }, Completion { receiver }))
+}
+
+/// A [`Future`] which resolves to the `MsgSeqNum <34>` of a message enqueued
+/// by a [`SessionHandle`] once it has been written, or to the reason why it
+/// was not.
+///
+/// [`SendError::Disconnected`] means that the transport was closed first. The
+/// message might have been persisted nonetheless, in which case it will be
+/// resent if the counterparty asks for it.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Completion {
+    receiver: oneshot::Receiver<Result<u64, SendError>>,
+}
+
+impl Future for Completion {
+    type Output = Result<u64, SendError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| result.unwrap_or(Err(SendError::Disconnected)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn full_queues_apply_backpressure() {
+        let (mut handle, _receiver) = SessionHandle::channel(1);
+        assert!(handle.try_send(OutboundMessage::new(b"D")).is_ok());
+        assert!(handle.try_send(OutboundMessage::new(b"D")).is_ok());
+        assert_eq!(
+            handle.try_send(OutboundMessage::new(b"D")).err(),
+            Some(SendError::QueueFull)
+        );
+    }
+
+    #[test]
+    fn session_messages_are_refused() {
+        let (mut handle, _receiver) = SessionHandle::channel(1);
+        assert_eq!(
+            handle.try_send(OutboundMessage::new(b"0")).err(),
+            Some(SendError::SessionMessage)
+        );
+    }
+
+    #[test]
+    fn dropped_sessions_close_the_queue() {
+        let (mut handle, receiver) = SessionHandle::channel(1);
+        drop(receiver);
+        assert_eq!(
+            handle.try_send(OutboundMessage::new(b"D")).err(),
+            Some(SendError::Closed)
+        );
+    }
+}
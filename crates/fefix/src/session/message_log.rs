@@ -0,0 +1,416 @@
+use super::message_len;
+use crate::tagvalue::{DecodeError, Decoder, Message};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H:%M:%S%.6f";
+const ROTATED_TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S%.6f";
+const SEPARATOR: &[u8] = b" : ";
+const MESSAGES: &str = "messages";
+const EVENTS: &str = "event";
+const CURRENT: &str = "current";
+
+/// An audit trail of all raw FIX messages and notable events of a
+/// [`FixConnection`](super::FixConnection), as set by
+/// [`FixConnection::with_log`](super::FixConnection::with_log).
+///
+/// Logging errors are never fatal to the FIX session.
+pub trait MessageLog: fmt::Debug {
+    /// Logs a whole inbound FIX message, as received.
+    fn on_incoming(&mut self, message: &[u8]) -> io::Result<()>;
+
+    /// Logs a whole outbound FIX message, as sent.
+    fn on_outgoing(&mut self, message: &[u8]) -> io::Result<()>;
+
+    /// Logs a [`SessionEvent`](super::SessionEvent) or any other remark.
+    fn on_event(&mut self, text: &str) -> io::Result<()>;
+}
+
+/// Whether a logged FIX message was received or sent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Received from the counterparty.
+    Incoming,
+    /// Sent to the counterparty.
+    Outgoing,
+}
+
+impl Direction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Incoming => "IN",
+            Self::Outgoing => "OUT",
+        }
+    }
+}
+
+/// A [`MessageLog`] which writes QuickFIX-style log files.
+///
+/// FIX messages go to `{prefix}.messages.current.log`, one per line, as
+/// `{timestamp} {IN|OUT} : {message}`; the message is written verbatim, SOH
+/// separators included. Events go to `{prefix}.event.current.log`, as
+/// `{timestamp} : {text}`. Timestamps are UTC, with microseconds.
+///
+/// Existing files are appended to. Once a file would grow past
+/// [`FileLog::with_max_file_size`], it's renamed to
+/// `{prefix}.{messages|event}.{timestamp}.log` and a new one is started, so
+/// that rotated files sort by age. Use [`FileLogReader`] to read them back.
+#[derive(Debug)]
+pub struct FileLog {
+    messages: LogFile,
+    events: LogFile,
+}
+
+impl FileLog {
+    /// Opens the log files named after `prefix` in `dir`, creating them (and
+    /// `dir`) if needed.
+    pub fn open(dir: impl AsRef<Path>, prefix: &str) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            messages: LogFile::open(dir, prefix, MESSAGES)?,
+            events: LogFile::open(dir, prefix, EVENTS)?,
+        })
+    }
+
+    /// Rotates each log file once it reaches `max_file_size` bytes. Files are
+    /// never rotated by default.
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.messages.max_len = Some(max_file_size);
+        self.events.max_len = Some(max_file_size);
+        self
+    }
+
+    /// Returns the path of the current messages log file.
+    pub fn messages_path(&self) -> &Path {
+        &self.messages.path
+    }
+
+    /// Returns the path of the current event log file.
+    pub fn events_path(&self) -> &Path {
+        &self.events.path
+    }
+
+    fn log_message(
+        &mut self,
+        direction: Direction,
+        message: &[u8],
+        timestamp: DateTime<Utc>,
+    ) -> io::Result<()> {
+        let mut line = format!(
+            "{} {}",
+            timestamp.format(TIMESTAMP_FORMAT),
+            direction.as_str()
+        )
+        .into_bytes();
+        line.extend_from_slice(SEPARATOR);
+        line.extend_from_slice(message);
+        line.push(b'\n');
+        self.messages.write_line(&line, timestamp)
+    }
+
+    fn log_event(&mut self, text: &str, timestamp: DateTime<Utc>) -> io::Result<()> {
+        let line = format!("{} : {}\n", timestamp.format(TIMESTAMP_FORMAT), text);
+        self.events.write_line(line.as_bytes(), timestamp)
+    }
+}
+
+impl MessageLog for FileLog {
+    fn on_incoming(&mut self, message: &[u8]) -> io::Result<()> {
+        self.log_message(Direction::Incoming, message, Utc::now())
+    }
+
+    fn on_outgoing(&mut self, message: &[u8]) -> io::Result<()> {
+        self.log_message(Direction::Outgoing, message, Utc::now())
+    }
+
+    fn on_event(&mut self, text: &str) -> io::Result<()> {
+        self.log_event(text, Utc::now())
+    }
+}
+
+/// A single log file of a [`FileLog`], with its rotation state.
+#[derive(Debug)]
+struct LogFile {
+    dir: PathBuf,
+    prefix: String,
+    kind: &'static str,
+    path: PathBuf,
+    file: File,
+    len: u64,
+    max_len: Option<u64>,
+}
+
+impl LogFile {
+    fn open(dir: &Path, prefix: &str, kind: &'static str) -> io::Result<Self> {
+        let path = dir.join(file_name(prefix, kind, CURRENT));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            prefix: prefix.to_string(),
+            kind,
+            path,
+            file,
+            len,
+            max_len: None,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8], timestamp: DateTime<Utc>) -> io::Result<()> {
+        let len = line.len() as u64;
+        if matches!(self.max_len, Some(max_len) if self.len > 0 && self.len + len > max_len) {
+            self.rotate(timestamp)?;
+        }
+        self.file.write_all(line)?;
+        self.len += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self, timestamp: DateTime<Utc>) -> io::Result<()> {
+        self.file.flush()?;
+        let suffix = timestamp.format(ROTATED_TIMESTAMP_FORMAT).to_string();
+        let rotated = self.dir.join(file_name(&self.prefix, self.kind, &suffix));
+        fs::rename(&self.path, rotated)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn file_name(prefix: &str, kind: &str, suffix: &str) -> String {
+    format!("{}.{}.{}.log", prefix, kind, suffix)
+}
+
+/// A FIX message read back by a [`FileLogReader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// When the FIX message was logged.
+    pub timestamp: DateTime<Utc>,
+    /// Whether the FIX message was received or sent, if logged. Plain
+    /// QuickFIX logs don't record it.
+    pub direction: Option<Direction>,
+    /// The raw FIX message, SOH separators included.
+    pub message: Vec<u8>,
+}
+
+impl LogEntry {
+    /// Decodes the FIX message of `self` with `decoder`.
+    pub fn decode<'a>(
+        &'a self,
+        decoder: &'a mut Decoder,
+    ) -> Result<Message<'a, &'a [u8]>, DecodeError> {
+        decoder.decode(&self.message[..])
+    }
+}
+
+/// An [`Iterator`] over the FIX messages in the log files of a [`FileLog`],
+/// oldest first.
+///
+/// Each file is read in full before its messages are yielded; rotate with
+/// [`FileLog::with_max_file_size`] to keep files small. A malformed line
+/// yields an error of kind [`io::ErrorKind::InvalidData`] and the rest of
+/// its file is skipped.
+#[derive(Debug)]
+pub struct FileLogReader {
+    paths: VecDeque<PathBuf>,
+    path: PathBuf,
+    bytes: Vec<u8>,
+    offset: usize,
+}
+
+impl FileLogReader {
+    /// Reads the messages log files named after `prefix` in `dir`: rotated
+    /// ones first, then the current one.
+    pub fn open(dir: impl AsRef<Path>, prefix: &str) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let start = format!("{}.{}.", prefix, MESSAGES);
+        let current = file_name(prefix, MESSAGES, CURRENT);
+        let mut rotated = vec![];
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(&start) && name.ends_with(".log") && name != current {
+                rotated.push(dir.join(name.as_ref()));
+            }
+        }
+        rotated.sort();
+        let mut paths = VecDeque::from(rotated);
+        let current = dir.join(current);
+        if current.exists() {
+            paths.push_back(current);
+        }
+        Ok(Self::from_paths(paths))
+    }
+
+    /// Reads a single messages log file, e.g. one written by QuickFIX.
+    pub fn from_path(path: impl Into<PathBuf>) -> Self {
+        Self::from_paths(VecDeque::from([path.into()]))
+    }
+
+    fn from_paths(paths: VecDeque<PathBuf>) -> Self {
+        Self {
+            paths,
+            path: PathBuf::new(),
+            bytes: vec![],
+            offset: 0,
+        }
+    }
+
+    fn next_entry(&mut self) -> Result<Option<LogEntry>, String> {
+        let rest = &self.bytes[self.offset..];
+        let start = rest
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let rest = &rest[start..];
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        let separator = rest
+            .windows(SEPARATOR.len())
+            .position(|window| window == SEPARATOR)
+            .ok_or("missing separator")?;
+        let header = std::str::from_utf8(&rest[..separator]).map_err(|_| "invalid header")?;
+        let (timestamp, direction) = match header.rsplit_once(' ') {
+            Some((timestamp, "IN")) => (timestamp, Some(Direction::Incoming)),
+            Some((timestamp, "OUT")) => (timestamp, Some(Direction::Outgoing)),
+            _ => (header, None),
+        };
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, "%Y%m%d-%H:%M:%S%.f")
+            .map_err(|_| "invalid timestamp")?;
+        let message = &rest[separator + SEPARATOR.len()..];
+        let len = message_len(message).ok_or("truncated or invalid FIX message")?;
+        let entry = LogEntry {
+            timestamp: Utc.from_utc_datetime(&timestamp),
+            direction,
+            message: message[..len].to_vec(),
+        };
+        self.offset += start + separator + SEPARATOR.len() + len;
+        Ok(Some(entry))
+    }
+}
+
+impl Iterator for FileLogReader {
+    type Item = io::Result<LogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_entry() {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => {
+                    self.path = self.paths.pop_front()?;
+                    self.offset = 0;
+                    match fs::read(&self.path) {
+                        Ok(bytes) => self.bytes = bytes,
+                        Err(err) => {
+                            self.bytes.clear();
+                            return Some(Err(err));
+                        }
+                    }
+                }
+                Err(reason) => {
+                    let err = io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}: {} at byte {}",
+                            self.path.display(),
+                            reason,
+                            self.offset
+                        ),
+                    );
+                    self.offset = self.bytes.len();
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dictionary, FieldMap};
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("fefix-log-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn timestamp(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 123_000).unwrap()
+    }
+
+    fn message(msg_seq_num: u64) -> Vec<u8> {
+        let body = format!("35=0\x0134={}\x01", msg_seq_num);
+        let mut message = format!("8=FIX.4.4\x019={}\x01{}", body.len(), body).into_bytes();
+        let checksum = message.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        message.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        message
+    }
+
+    #[test]
+    fn messages_roundtrip_across_rotations() {
+        let dir = temp_dir();
+        {
+            let mut log = FileLog::open(&dir, "FIX.4.4-A-B")
+                .unwrap()
+                .with_max_file_size(100);
+            for i in 1..=4 {
+                log.log_message(Direction::Outgoing, &message(i), timestamp(i as i64))
+                    .unwrap();
+            }
+            log.log_event("Logged on", timestamp(5)).unwrap();
+        }
+        let mut log = FileLog::open(&dir, "FIX.4.4-A-B").unwrap();
+        log.log_message(Direction::Incoming, &message(1), timestamp(6))
+            .unwrap();
+        let rotated = fs::read_dir(&dir).unwrap().count() - 2;
+        assert_eq!(rotated, 3);
+        let events = fs::read_to_string(log.events_path()).unwrap();
+        assert_eq!(events, "20200913-12:26:45.000123 : Logged on\n");
+
+        let entries = FileLogReader::open(&dir, "FIX.4.4-A-B")
+            .unwrap()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        let expected = (1..=4)
+            .map(|i| (timestamp(i), Direction::Outgoing, message(i as u64)))
+            .chain([(timestamp(6), Direction::Incoming, message(1))]);
+        assert_eq!(entries.len(), 5);
+        for (entry, (timestamp, direction, message)) in entries.iter().zip(expected) {
+            assert_eq!(entry.timestamp, timestamp);
+            assert_eq!(entry.direction, Some(direction));
+            assert_eq!(entry.message, message);
+        }
+        let mut decoder = Decoder::new(Dictionary::fix44());
+        let msg = entries[1].decode(&mut decoder).unwrap();
+        assert_eq!(msg.get::<u64>(34), Ok(2));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn plain_quickfix_logs_are_read() {
+        let dir = temp_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("messages.log");
+        let mut contents = b"20200913-12:26:41.000 : ".to_vec();
+        contents.extend_from_slice(&message(7));
+        contents.extend_from_slice(b"\n20200913-12:26:42.000 : 8=FIX.4.4\x019=50\x01");
+        fs::write(&path, contents).unwrap();
+        let mut reader = FileLogReader::from_path(&path);
+        let entry = reader.next().unwrap().unwrap();
+        assert_eq!(entry.direction, None);
+        assert_eq!(entry.message, message(7));
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
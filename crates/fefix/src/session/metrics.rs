@@ -0,0 +1,85 @@
+use super::SessionEvent;
+use std::time::Duration;
+
+/// Counters of a [`FixConnection`](super::FixConnection), as returned by
+/// [`FixConnection::metrics`](super::FixConnection::metrics).
+///
+/// All counters are cumulative across reconnections and only ever grow, so
+/// that they can be exported as they are to metrics systems. Traffic
+/// counters cover the inputs of
+/// [`FixConnection::on_bytes`](super::FixConnection::on_bytes) and the
+/// outputs of [`Action::Write`](super::Action::Write).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionMetrics {
+    /// The number of inbound messages.
+    pub messages_in: u64,
+    /// The number of outbound messages.
+    pub messages_out: u64,
+    /// The number of inbound bytes.
+    pub bytes_in: u64,
+    /// The number of outbound bytes.
+    pub bytes_out: u64,
+    /// See [`SessionEvent::LoggedOn`].
+    pub logons: u64,
+    /// See [`SessionEvent::LogoutSent`].
+    pub logouts_sent: u64,
+    /// See [`SessionEvent::LogoutReceived`].
+    pub logouts_received: u64,
+    /// See [`SessionEvent::GapDetected`].
+    pub gaps_detected: u64,
+    /// See [`SessionEvent::ResendRequestSent`].
+    pub resend_requests_sent: u64,
+    /// See [`SessionEvent::ResendRequestReceived`].
+    pub resend_requests_received: u64,
+    /// See [`SessionEvent::RejectSent`].
+    pub rejects_sent: u64,
+    /// See [`SessionEvent::RejectReceived`].
+    pub rejects_received: u64,
+    /// See [`SessionEvent::BusinessRejectSent`].
+    pub business_rejects_sent: u64,
+    /// See [`SessionEvent::BusinessRejectReceived`].
+    pub business_rejects_received: u64,
+    /// See [`SessionEvent::HeartbeatTimeout`].
+    pub heartbeat_timeouts: u64,
+    /// See [`SessionEvent::TestRequestAnswered`].
+    pub test_requests_answered: u64,
+    /// The latency of the last [`SessionEvent::TestRequestAnswered`], if any.
+    pub last_test_request_latency: Option<Duration>,
+    /// See [`SessionEvent::GarbledMessageIgnored`].
+    pub garbled_messages_ignored: u64,
+}
+
+impl SessionMetrics {
+    /// Updates the counters of `self` with `event`.
+    pub(crate) fn record(&mut self, event: &SessionEvent) {
+        match event {
+            SessionEvent::LoggedOn => self.logons += 1,
+            SessionEvent::LogoutSent { .. } => self.logouts_sent += 1,
+            SessionEvent::LogoutReceived { .. } => self.logouts_received += 1,
+            SessionEvent::GapDetected { .. } => self.gaps_detected += 1,
+            SessionEvent::ResendRequestSent(_) => self.resend_requests_sent += 1,
+            SessionEvent::ResendRequestReceived(_) => self.resend_requests_received += 1,
+            SessionEvent::RejectSent(_) => self.rejects_sent += 1,
+            SessionEvent::RejectReceived { .. } => self.rejects_received += 1,
+            SessionEvent::BusinessRejectSent(_) => self.business_rejects_sent += 1,
+            SessionEvent::BusinessRejectReceived { .. } => self.business_rejects_received += 1,
+            SessionEvent::HeartbeatTimeout => self.heartbeat_timeouts += 1,
+            SessionEvent::TestRequestAnswered { latency } => {
+                self.test_requests_answered += 1;
+                self.last_test_request_latency = Some(*latency);
+            }
+            SessionEvent::GarbledMessageIgnored { .. } => self.garbled_messages_ignored += 1,
+        }
+    }
+
+    /// Counts the inbound `bytes` of a single read.
+    pub(crate) fn record_inbound_bytes(&mut self, bytes: &[u8]) {
+        self.bytes_in += bytes.len() as u64;
+    }
+
+    /// Counts `num_messages` outbound messages, made up of `bytes`.
+    pub(crate) fn record_outbound(&mut self, num_messages: usize, bytes: &[u8]) {
+        self.messages_out += num_messages as u64;
+        self.bytes_out += bytes.len() as u64;
+    }
+}
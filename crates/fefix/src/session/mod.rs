@@ -7,24 +7,63 @@
 
 pub mod backends;
 mod config;
-//mod connection; FIXME
+mod connection;
 mod environment;
 mod errs;
 mod event_loop;
+mod events;
+mod handle;
 mod heartbeat_rule;
+mod message_log;
+mod metrics;
+mod mode;
+mod outbound;
+mod reconnect;
+mod reject;
+mod replay;
 mod resend_request_range;
+mod schedule;
 mod seq_numbers;
+mod server;
+mod settings;
+mod state;
+mod store;
+#[cfg(any(test, feature = "session-testing"))]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "session-testing")))]
+pub mod testing;
+mod throttle;
+mod timers;
 
 use crate::tagvalue::Message;
 use crate::{FieldType, SetField};
 pub use config::{Config, Configure};
-// pub use connection::*; FIXME
+pub use connection::*;
 pub use environment::Environment;
 pub use event_loop::*;
+pub use events::SessionEvent;
+use handle::{Completer, SendRequest};
+pub use handle::{Completion, SessionHandle};
 pub use heartbeat_rule::HeartbeatRule;
+pub use message_log::{Direction, FileLog, FileLogReader, LogEntry, MessageLog};
+pub use metrics::SessionMetrics;
+pub use mode::SessionMode;
+pub use outbound::{OutboundMessage, SendError};
+pub use reconnect::{Backoff, ConnectionAttempt};
+pub use reject::{BusinessReject, BusinessRejectReason, Reject, SessionRejectReason};
+pub use replay::{
+    Divergence, FieldDivergence, Pace, Replay, ReplayError, ReplayReport, DEFAULT_IGNORED_TAGS,
+};
 pub use resend_request_range::ResendRequestRange;
+pub use schedule::{Schedule, ScheduleDays};
 pub use seq_numbers::{SeqNumberError, SeqNumbers};
+pub use server::{SessionKey, SessionServer};
+pub use settings::{SessionSettings, SettingsError};
+pub use state::SessionState;
 use std::ops::Range;
+pub use store::{FileStore, MemoryStore, MessageStore};
+use throttle::Throttler;
+pub use throttle::{Rate, Throttle, WhenThrottled};
+pub use timers::{HeartbeatTimers, TimerEvent};
 
 /// The owner of a [`FixConnection`]. It can react to events, store incoming
 /// messages, send messages, etc..
@@ -73,6 +112,19 @@ pub trait Backend: Clone {
         }
     }
 
+    /// Callback for rejecting an application `message` that
+    /// [`Backend::on_inbound_app_message`] failed to process with `error`.
+    /// [`None`] means the message is dropped without a
+    /// `BusinessMessageReject <j>`.
+    fn business_reject(
+        &mut self,
+        message: Message<&[u8]>,
+        error: &Self::Error,
+    ) -> Option<BusinessReject> {
+        let text = String::from_utf8_lossy(&error.to_bytes()).into_owned();
+        Some(BusinessReject::new(&message, BusinessRejectReason::Other).with_text(text))
+    }
+
     /// Callback for processing `ResendRequest` messages.
     fn on_resend_request(&mut self, range: Range<u64>) -> Result<(), Self::Error>;
 
@@ -80,8 +132,35 @@ pub trait Backend: Clone {
     /// is established with the counterparty.
     fn on_successful_handshake(&mut self) -> Result<(), Self::Error>;
 
+    /// Callback for transitions of the [`SessionState`] of a [`FixConnection`]
+    /// from `old` to `new`.
+    #[inline]
+    fn on_state_change(&mut self, old: SessionState, new: SessionState) -> Result<(), Self::Error> {
+        let _ = (old, new);
+        Ok(())
+    }
+
+    /// Callback for notable events of a [`FixConnection`], e.g. for logging
+    /// and monitoring.
+    #[inline]
+    fn on_session_event(&mut self, event: &SessionEvent) -> Result<(), Self::Error> {
+        let _ = event;
+        Ok(())
+    }
+
+    /// Callback for the end of each attempt of
+    /// [`FixConnection::start_with_reconnect`] to connect and run a FIX
+    /// session.
+    #[inline]
+    fn on_connection_attempt(&mut self, attempt: &ConnectionAttempt) -> Result<(), Self::Error> {
+        let _ = attempt;
+        Ok(())
+    }
+
     fn fetch_messages(&mut self) -> Result<&[&[u8]], Self::Error>;
 
+    /// Unused: application messages are sent with [`FixConnection::send`] or
+    /// through a [`SessionHandle`].
     fn pending_message(&mut self) -> Option<&[u8]>;
 }
 
@@ -107,6 +186,12 @@ impl MsgSeqNumCounter {
     pub fn expected(&self) -> u64 {
         self.0 + 1
     }
+
+    /// Makes `expected` the next seq. number. `expected` must be at least 1.
+    #[inline]
+    pub fn set_expected(&mut self, expected: u64) {
+        self.0 = expected.saturating_sub(1);
+    }
 }
 
 impl Iterator for MsgSeqNumCounter {
@@ -117,6 +202,18 @@ impl Iterator for MsgSeqNumCounter {
     }
 }
 
-// FIXME
-#[derive(Debug)]
-pub struct FixConnection;
+/// Returns the length of the first message in `buffer`, if it's whole.
+fn message_len(buffer: &[u8]) -> Option<usize> {
+    let begin_string_len = buffer.iter().position(|b| *b == b'\x01')? + 1;
+    let rest = &buffer[begin_string_len..];
+    let body_length_len = rest.iter().position(|b| *b == b'\x01')? + 1;
+    let body_length: usize = std::str::from_utf8(rest.get(2..body_length_len - 1)?)
+        .ok()?
+        .parse()
+        .ok()?;
+    // `CheckSum <10>` is always seven bytes long.
+    let len = (begin_string_len + body_length_len)
+        .checked_add(body_length)?
+        .checked_add(7)?;
+    (buffer.len() >= len).then_some(len)
+}
@@ -0,0 +1,45 @@
+/// What a FIX session is used for, as returned by
+/// [`Configure::session_mode`](super::Configure::session_mode).
+///
+/// Inbound messages are always delivered to the
+/// [`Backend`](super::Backend) in `MsgSeqNum <34>` order, whatever the mode.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SessionMode {
+    /// A regular, two-way FIX session.
+    #[default]
+    Normal,
+    /// The receiving end of a drop-copy feed. Application messages are never
+    /// sent, not even `BusinessMessageReject <j>`:
+    /// [`FixConnection::send`](super::FixConnection::send) fails with
+    /// [`SendError::ReadOnly`](super::SendError::ReadOnly) and
+    /// [`Backend::business_reject`](super::Backend::business_reject) is
+    /// never called.
+    DropCopy,
+    /// A passive monitor. Like [`SessionMode::DropCopy`], and invalid inbound
+    /// messages aren't rejected with `Reject <3>` either: only the messages
+    /// needed to keep the FIX session alive are ever sent.
+    ReadOnly,
+}
+
+impl SessionMode {
+    /// Returns `true` if and only if `self` allows sending application
+    /// messages.
+    ///
+    /// ```
+    /// use fefix::session::SessionMode;
+    ///
+    /// assert!(SessionMode::Normal.allows_application_messages());
+    /// assert!(!SessionMode::DropCopy.allows_application_messages());
+    /// assert!(!SessionMode::ReadOnly.allows_application_messages());
+    /// ```
+    pub fn allows_application_messages(&self) -> bool {
+        matches!(self, Self::Normal)
+    }
+
+    /// Returns `true` if and only if `self` allows rejecting inbound messages
+    /// with `Reject <3>`.
+    pub fn allows_rejects(&self) -> bool {
+        !matches!(self, Self::ReadOnly)
+    }
+}
@@ -0,0 +1,106 @@
+use super::connection::is_admin_msg_type;
+use crate::{FieldType, SetField};
+use std::time::Duration;
+
+/// An application message to be sent by
+/// [`FixConnection::send`](super::FixConnection::send).
+///
+/// Only the body fields are up to the application: the `StandardHeader`
+/// (CompIDs, `MsgSeqNum <34>`, `SendingTime <52>`, etc.) and the
+/// `StandardTrailer` are written by the FIX session itself.
+///
+/// ```
+/// use fefix::session::OutboundMessage;
+/// use fefix::SetField;
+///
+/// let mut msg = OutboundMessage::new(b"D");
+/// msg.set(11, "order-1");
+/// msg.set(38, 100u32);
+/// assert_eq!(msg.msg_type(), b"D");
+/// assert_eq!(msg.fields()[1], (38, b"100".to_vec()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboundMessage {
+    msg_type: Vec<u8>,
+    fields: Vec<(u32, Vec<u8>)>,
+}
+
+impl OutboundMessage {
+    /// Creates a new [`OutboundMessage`] of type `msg_type` without any body
+    /// fields.
+    pub fn new(msg_type: &[u8]) -> Self {
+        Self {
+            msg_type: msg_type.to_vec(),
+            fields: vec![],
+        }
+    }
+
+    /// Returns the `MsgType <35>` of `self`.
+    pub fn msg_type(&self) -> &[u8] {
+        &self.msg_type
+    }
+
+    /// Returns the serialized body fields of `self`, in order.
+    pub fn fields(&self) -> &[(u32, Vec<u8>)] {
+        &self.fields
+    }
+
+    /// Returns `true` if `self` is a session-level message, which only the
+    /// FIX session itself may send.
+    pub(crate) fn is_session_message(&self) -> bool {
+        is_admin_msg_type(&self.msg_type) || self.msg_type == b"3"
+    }
+
+    /// Writes the body fields of `self` to `msg`.
+    pub(crate) fn set_fields(&self, msg: &mut impl SetField<u32>) {
+        for (tag, value) in &self.fields {
+            msg.set(*tag, value.as_slice());
+        }
+    }
+}
+
+impl SetField<u32> for OutboundMessage {
+    fn set_with<'a, V>(&'a mut self, tag: u32, value: V, settings: V::SerializeSettings)
+    where
+        V: FieldType<'a>,
+    {
+        let mut buffer = Vec::new();
+        value.serialize_with(&mut buffer, settings);
+        self.fields.push((tag, buffer));
+    }
+}
+
+/// The type returned in the event of a failed
+/// [`FixConnection::send`](super::FixConnection::send) or
+/// [`SessionHandle::send`](super::SessionHandle::send).
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SendError {
+    /// Application messages can only be sent while logged on.
+    #[error("The FIX session is not logged on.")]
+    NotLoggedOn,
+    /// Session-level messages, e.g. `Heartbeat <0>` or `Logout <5>`, are
+    /// only ever sent by the FIX session itself.
+    #[error("Session-level messages can't be sent by the application.")]
+    SessionMessage,
+    /// The [`SessionMode`](super::SessionMode) doesn't allow application
+    /// messages.
+    #[error("The FIX session is read-only.")]
+    ReadOnly,
+    /// The outbound [`Throttle`](super::Throttle) is exceeded and it's
+    /// configured to reject messages.
+    #[error("Outbound throttle exceeded, retry in {retry_after:?}.")]
+    Throttled {
+        /// How long until the message would be allowed.
+        retry_after: Duration,
+    },
+    /// The queue of [`SessionHandle`](super::SessionHandle) is full.
+    #[error("The outbound queue is full.")]
+    QueueFull,
+    /// The [`FixConnection`](super::FixConnection) behind a
+    /// [`SessionHandle`](super::SessionHandle) has been dropped.
+    #[error("The FIX connection has been dropped.")]
+    Closed,
+    /// The transport was closed before the message could be written.
+    #[error("The transport was closed before the message was written.")]
+    Disconnected,
+}
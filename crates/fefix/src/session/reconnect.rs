@@ -0,0 +1,117 @@
+use super::ConnectionError;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// Exponential backoff between the reconnection attempts of
+/// [`FixConnection::start_with_reconnect`](super::FixConnection::start_with_reconnect).
+///
+/// The n-th consecutive failed attempt is followed by a delay of
+/// `initial * multiplier^(n - 1)`, capped at `max`. Every delay is then
+/// shortened by a random fraction of up to `jitter`, so that many initiators
+/// don't reconnect in lockstep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    /// The delay after the first failed attempt.
+    pub initial: Duration,
+    /// The maximum delay between two attempts.
+    pub max: Duration,
+    /// The growth factor of consecutive delays.
+    pub multiplier: u32,
+    /// The maximum fraction of each delay that is randomly cut, between 0
+    /// and 1.
+    pub jitter: f64,
+    /// The number of consecutive failed attempts after which we give up.
+    /// [`None`] means "never give up".
+    pub max_attempts: Option<u32>,
+}
+
+impl Backoff {
+    /// Returns how long to wait after the `attempt`-th consecutive failed
+    /// attempt, starting from 1. Returns [`None`] if we should give up.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        let random = RandomState::new().build_hasher().finish();
+        self.delay_with_random(attempt, random as f64 / u64::MAX as f64)
+    }
+
+    /// Like [`Backoff::delay`], but with a given `random` value between 0 and
+    /// 1 rather than a new one.
+    fn delay_with_random(&self, attempt: u32, random: f64) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+        let factor = self
+            .multiplier
+            .checked_pow(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self.initial.saturating_mul(factor).min(self.max);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random.clamp(0.0, 1.0);
+        Some(delay.mul_f64(1.0 - jitter))
+    }
+}
+
+impl Default for Backoff {
+    /// One second, doubling up to a minute, with 20% jitter and no limit on
+    /// the number of attempts.
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            multiplier: 2,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+/// The outcome of an attempt of
+/// [`FixConnection::start_with_reconnect`](super::FixConnection::start_with_reconnect)
+/// to connect and run a FIX session, as reported to
+/// [`Backend::on_connection_attempt`](super::Backend::on_connection_attempt).
+#[derive(Debug)]
+pub struct ConnectionAttempt<'a> {
+    /// The number of consecutive failed attempts so far, including this
+    /// one if it failed. The count starts again after each successful
+    /// logon.
+    pub failures: u32,
+    /// The index of the endpoint that was used.
+    pub endpoint: usize,
+    /// How the attempt ended: either a graceful logout or the error that
+    /// ended the FIX session or prevented it from starting.
+    pub result: &'a Result<(), ConnectionError>,
+    /// How long until the next attempt. [`None`] means there won't be one.
+    pub retry_in: Option<Duration>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delays_grow_up_to_the_max() {
+        let backoff = Backoff {
+            max: Duration::from_secs(5),
+            ..Backoff::default()
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|attempt| backoff.delay_with_random(attempt, 0.0).unwrap())
+            .collect();
+        let secs = |n| Duration::from_secs(n);
+        assert_eq!(delays, vec![secs(1), secs(2), secs(4), secs(5), secs(5)]);
+        assert_eq!(
+            backoff.delay_with_random(1, 1.0),
+            Some(Duration::from_millis(800))
+        );
+        assert!(backoff.delay(1000).unwrap() >= Duration::from_secs(4));
+    }
+
+    #[test]
+    fn give_up_after_max_attempts() {
+        let backoff = Backoff {
+            max_attempts: Some(3),
+            ..Backoff::default()
+        };
+        assert!(backoff.delay(2).is_some());
+        assert_eq!(backoff.delay(3), None);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::tagvalue::{DecodeError, FieldErrorKind, Message};
+use crate::{FieldMap, FieldType, FieldValueError, SetField};
+
+const REF_SEQ_NUM: u32 = 45;
+const MSG_SEQ_NUM: u32 = 34;
+const MSG_TYPE: u32 = 35;
+const TEXT: u32 = 58;
+const REF_TAG_ID: u32 = 371;
+const REF_MSG_TYPE: u32 = 372;
+const SESSION_REJECT_REASON: u32 = 373;
+const BUSINESS_REJECT_REF_ID: u32 = 379;
+const BUSINESS_REJECT_REASON: u32 = 380;
+
+/// The values of `SessionRejectReason <373>`, i.e. why a message was
+/// rejected with `Reject <3>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, FieldType)]
+pub enum SessionRejectReason {
+    /// Invalid tag number.
+    #[fefix(variant = "0")]
+    InvalidTagNumber,
+    /// Required tag missing.
+    #[fefix(variant = "1")]
+    RequiredTagMissing,
+    /// Tag not defined for this message type.
+    #[fefix(variant = "2")]
+    TagNotDefinedForThisMessageType,
+    /// Undefined tag.
+    #[fefix(variant = "3")]
+    UndefinedTag,
+    /// Tag specified without a value.
+    #[fefix(variant = "4")]
+    TagSpecifiedWithoutAValue,
+    /// Value is incorrect (out of range) for this tag.
+    #[fefix(variant = "5")]
+    ValueIsIncorrect,
+    /// Incorrect data format for value.
+    #[fefix(variant = "6")]
+    IncorrectDataFormatForValue,
+    /// Decryption problem.
+    #[fefix(variant = "7")]
+    DecryptionProblem,
+    /// Signature problem.
+    #[fefix(variant = "8")]
+    SignatureProblem,
+    /// CompID problem.
+    #[fefix(variant = "9")]
+    CompIdProblem,
+    /// SendingTime accuracy problem.
+    #[fefix(variant = "10")]
+    SendingTimeAccuracyProblem,
+    /// Invalid MsgType.
+    #[fefix(variant = "11")]
+    InvalidMsgType,
+    /// XML validation error.
+    #[fefix(variant = "12")]
+    XmlValidationError,
+    /// Tag appears more than once.
+    #[fefix(variant = "13")]
+    TagAppearsMoreThanOnce,
+    /// Tag specified out of required order.
+    #[fefix(variant = "14")]
+    TagSpecifiedOutOfRequiredOrder,
+    /// Repeating group fields out of order.
+    #[fefix(variant = "15")]
+    RepeatingGroupFieldsOutOfOrder,
+    /// Incorrect NumInGroup count for repeating group.
+    #[fefix(variant = "16")]
+    IncorrectNumInGroupCountForRepeatingGroup,
+    /// Non "data" value includes field delimiter (`SOH` character).
+    #[fefix(variant = "17")]
+    NonDataValueIncludesFieldDelimiter,
+    /// Invalid or unsupported application version.
+    #[fefix(variant = "18")]
+    InvalidUnsupportedApplicationVersion,
+    /// Other.
+    #[fefix(variant = "99")]
+    Other,
+}
+
+impl SessionRejectReason {
+    /// Returns the reason for rejecting a message because of `err`, which
+    /// arose while reading one of its fields.
+    pub fn for_field_error<E>(err: &FieldValueError<E>) -> Self {
+        match err {
+            FieldValueError::Missing => Self::RequiredTagMissing,
+            FieldValueError::Invalid(_) => Self::IncorrectDataFormatForValue,
+        }
+    }
+
+    /// Returns the reason for rejecting a message that failed to decode with
+    /// `err`, or [`None`] if the message is garbled and should be ignored
+    /// instead (e.g. a `BodyLength <9>` or `CheckSum <10>` mismatch).
+    pub fn for_decode_error(err: &DecodeError) -> Option<Self> {
+        match err {
+            DecodeError::Field { kind, .. } => Some(match kind {
+                FieldErrorKind::InvalidTagNumber => Self::InvalidTagNumber,
+                FieldErrorKind::TagWithoutValue => Self::TagSpecifiedWithoutAValue,
+                FieldErrorKind::ValueIsIncorrect => Self::ValueIsIncorrect,
+                FieldErrorKind::TagAppearsMoreThanOnce => Self::TagAppearsMoreThanOnce,
+                FieldErrorKind::GroupFieldsOutOfOrder => Self::RepeatingGroupFieldsOutOfOrder,
+                FieldErrorKind::IncorrectNumInGroupCount => {
+                    Self::IncorrectNumInGroupCountForRepeatingGroup
+                }
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns a human-readable description of `self`, suitable for
+    /// `Text <58>`.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::InvalidTagNumber => "Invalid tag number",
+            Self::RequiredTagMissing => "Required tag missing",
+            Self::TagNotDefinedForThisMessageType => "Tag not defined for this message type",
+            Self::UndefinedTag => "Undefined tag",
+            Self::TagSpecifiedWithoutAValue => "Tag specified without a value",
+            Self::ValueIsIncorrect => "Value is incorrect (out of range) for this tag",
+            Self::IncorrectDataFormatForValue => "Incorrect data format for value",
+            Self::DecryptionProblem => "Decryption problem",
+            Self::SignatureProblem => "Signature problem",
+            Self::CompIdProblem => "CompID problem",
+            Self::SendingTimeAccuracyProblem => "SendingTime accuracy problem",
+            Self::InvalidMsgType => "Invalid MsgType",
+            Self::XmlValidationError => "XML validation error",
+            Self::TagAppearsMoreThanOnce => "Tag appears more than once",
+            Self::TagSpecifiedOutOfRequiredOrder => "Tag specified out of required order",
+            Self::RepeatingGroupFieldsOutOfOrder => "Repeating group fields out of order",
+            Self::IncorrectNumInGroupCountForRepeatingGroup => {
+                "Incorrect NumInGroup count for repeating group"
+            }
+            Self::NonDataValueIncludesFieldDelimiter => {
+                "Non \"data\" value includes field delimiter"
+            }
+            Self::InvalidUnsupportedApplicationVersion => {
+                "Invalid or unsupported application version"
+            }
+            Self::Other => "Other",
+        }
+    }
+}
+
+/// The values of `BusinessRejectReason <380>`, i.e. why an application
+/// message was rejected with `BusinessMessageReject <j>`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, FieldType)]
+pub enum BusinessRejectReason {
+    /// Other.
+    #[fefix(variant = "0")]
+    Other,
+    /// Unknown ID.
+    #[fefix(variant = "1")]
+    UnknownId,
+    /// Unknown security.
+    #[fefix(variant = "2")]
+    UnknownSecurity,
+    /// Unsupported message type.
+    #[fefix(variant = "3")]
+    UnsupportedMessageType,
+    /// Application not available.
+    #[fefix(variant = "4")]
+    ApplicationNotAvailable,
+    /// Conditionally required field missing.
+    #[fefix(variant = "5")]
+    ConditionallyRequiredFieldMissing,
+    /// Not authorized.
+    #[fefix(variant = "6")]
+    NotAuthorized,
+    /// DeliverToFirm not available at this time.
+    #[fefix(variant = "7")]
+    DeliverToFirmNotAvailableAtThisTime,
+    /// Throttle limit exceeded.
+    #[fefix(variant = "8")]
+    ThrottleLimitExceeded,
+    /// Throttle limit exceeded, session will be disconnected.
+    #[fefix(variant = "9")]
+    ThrottleLimitExceededSessionWillBeDisconnected,
+    /// Throttled messages rejected on request.
+    #[fefix(variant = "10")]
+    ThrottledMessagesRejectedOnRequest,
+    /// Invalid price increment.
+    #[fefix(variant = "18")]
+    InvalidPriceIncrement,
+}
+
+impl BusinessRejectReason {
+    /// Returns a human-readable description of `self`, suitable for
+    /// `Text <58>`.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Other => "Other",
+            Self::UnknownId => "Unknown ID",
+            Self::UnknownSecurity => "Unknown security",
+            Self::UnsupportedMessageType => "Unsupported message type",
+            Self::ApplicationNotAvailable => "Application not available",
+            Self::ConditionallyRequiredFieldMissing => "Conditionally required field missing",
+            Self::NotAuthorized => "Not authorized",
+            Self::DeliverToFirmNotAvailableAtThisTime => "DeliverToFirm not available at this time",
+            Self::ThrottleLimitExceeded => "Throttle limit exceeded",
+            Self::ThrottleLimitExceededSessionWillBeDisconnected => {
+                "Throttle limit exceeded, session will be disconnected"
+            }
+            Self::ThrottledMessagesRejectedOnRequest => "Throttled messages rejected on request",
+            Self::InvalidPriceIncrement => "Invalid price increment",
+        }
+    }
+}
+
+/// The body of a `Reject <3>` message, which rejects an inbound message
+/// that violates the session layer rules.
+///
+/// ```
+/// use fefix::session::{Reject, SessionRejectReason};
+/// use fefix::tagvalue::Decoder;
+/// use fefix::Dictionary;
+///
+/// let mut decoder = Decoder::new(Dictionary::fix44());
+/// let offender = decoder
+///     .decode(&b"8=FIX.4.4\x019=25\x0135=4\x0134=7\x0149=A\x0156=B\x0136=X\x0110=139\x01"[..])
+///     .unwrap();
+/// let reject = Reject::new(&offender, SessionRejectReason::IncorrectDataFormatForValue)
+///     .with_ref_tag_id(36);
+/// assert_eq!(reject.ref_seq_num, 7);
+/// assert_eq!(reject.ref_msg_type.as_deref(), Some(&b"4"[..]));
+/// assert_eq!(reject.text, "Incorrect data format for value");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reject {
+    /// `RefSeqNum <45>`, i.e. the `MsgSeqNum <34>` of the rejected message.
+    pub ref_seq_num: u64,
+    /// `RefTagID <371>`, i.e. the offending field of the rejected message.
+    pub ref_tag_id: Option<u32>,
+    /// `RefMsgType <372>`, i.e. the `MsgType <35>` of the rejected message.
+    pub ref_msg_type: Option<Vec<u8>>,
+    /// `SessionRejectReason <373>`.
+    pub reason: SessionRejectReason,
+    /// `Text <58>`.
+    pub text: String,
+}
+
+impl Reject {
+    /// Creates a new [`Reject`] of `offender` because of `reason`, with the
+    /// description of `reason` as its text.
+    pub fn new(offender: &Message<&[u8]>, reason: SessionRejectReason) -> Self {
+        Self {
+            ref_seq_num: offender.get(MSG_SEQ_NUM).unwrap_or_default(),
+            ref_tag_id: None,
+            ref_msg_type: offender.get_raw(MSG_TYPE).map(<[u8]>::to_vec),
+            reason,
+            text: reason.description().to_string(),
+        }
+    }
+
+    /// Creates a new [`Reject`] of `offender` because reading its field
+    /// `tag` failed with `err`.
+    pub fn for_field_error<E>(
+        offender: &Message<&[u8]>,
+        tag: u32,
+        err: &FieldValueError<E>,
+    ) -> Self {
+        Self::new(offender, SessionRejectReason::for_field_error(err)).with_ref_tag_id(tag)
+    }
+
+    /// Sets [`Reject::ref_tag_id`] to `tag`.
+    pub fn with_ref_tag_id(mut self, tag: u32) -> Self {
+        self.ref_tag_id = Some(tag);
+        self
+    }
+
+    /// Sets [`Reject::text`] to `text`.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    /// Writes the body fields of a `Reject <3>` to `msg`.
+    pub fn set_fields(&self, msg: &mut impl SetField<u32>) {
+        msg.set(REF_SEQ_NUM, self.ref_seq_num);
+        if let Some(ref_tag_id) = self.ref_tag_id {
+            msg.set(REF_TAG_ID, ref_tag_id);
+        }
+        if let Some(ref_msg_type) = &self.ref_msg_type {
+            msg.set(REF_MSG_TYPE, ref_msg_type.as_slice());
+        }
+        msg.set(SESSION_REJECT_REASON, self.reason);
+        msg.set(TEXT, self.text.as_str());
+    }
+}
+
+/// The body of a `BusinessMessageReject <j>` message, which rejects an
+/// inbound application message that is valid at the session layer but can't
+/// be processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusinessReject {
+    /// `RefSeqNum <45>`, i.e. the `MsgSeqNum <34>` of the rejected message.
+    pub ref_seq_num: Option<u64>,
+    /// `RefMsgType <372>`, i.e. the `MsgType <35>` of the rejected message.
+    pub ref_msg_type: Vec<u8>,
+    /// `BusinessRejectRefID <379>`, i.e. the business-level ID of the
+    /// rejected message, e.g. its `ClOrdID <11>`.
+    pub business_reject_ref_id: Option<String>,
+    /// `BusinessRejectReason <380>`.
+    pub reason: BusinessRejectReason,
+    /// `Text <58>`.
+    pub text: String,
+}
+
+impl BusinessReject {
+    /// Creates a new [`BusinessReject`] of `offender` because of `reason`,
+    /// with the description of `reason` as its text.
+    pub fn new(offender: &Message<&[u8]>, reason: BusinessRejectReason) -> Self {
+        Self {
+            ref_seq_num: offender.get(MSG_SEQ_NUM).ok(),
+            ref_msg_type: offender.get_raw(MSG_TYPE).unwrap_or_default().to_vec(),
+            business_reject_ref_id: None,
+            reason,
+            text: reason.description().to_string(),
+        }
+    }
+
+    /// Sets [`BusinessReject::business_reject_ref_id`] to `id`.
+    pub fn with_business_reject_ref_id(mut self, id: impl Into<String>) -> Self {
+        self.business_reject_ref_id = Some(id.into());
+        self
+    }
+
+    /// Sets [`BusinessReject::text`] to `text`.
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    /// Writes the body fields of a `BusinessMessageReject <j>` to `msg`.
+    pub fn set_fields(&self, msg: &mut impl SetField<u32>) {
+        if let Some(ref_seq_num) = self.ref_seq_num {
+            msg.set(REF_SEQ_NUM, ref_seq_num);
+        }
+        msg.set(REF_MSG_TYPE, self.ref_msg_type.as_slice());
+        if let Some(id) = &self.business_reject_ref_id {
+            msg.set(BUSINESS_REJECT_REF_ID, id.as_str());
+        }
+        msg.set(BUSINESS_REJECT_REASON, self.reason);
+        msg.set(TEXT, self.text.as_str());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reject_reasons_roundtrip() {
+        for value in ["0", "5", "10", "18", "99"] {
+            let reason = SessionRejectReason::deserialize(value.as_bytes()).unwrap();
+            assert_eq!(reason.to_bytes(), value.as_bytes());
+        }
+        assert!(SessionRejectReason::deserialize(b"19").is_err());
+        let reason = BusinessRejectReason::deserialize(b"3").unwrap();
+        assert_eq!(reason, BusinessRejectReason::UnsupportedMessageType);
+        assert_eq!(reason.description(), "Unsupported message type");
+    }
+
+    #[test]
+    fn field_errors_map_to_reject_reasons() {
+        assert_eq!(
+            SessionRejectReason::for_field_error::<()>(&FieldValueError::Missing),
+            SessionRejectReason::RequiredTagMissing
+        );
+        assert_eq!(
+            SessionRejectReason::for_field_error(&FieldValueError::Invalid(())),
+            SessionRejectReason::IncorrectDataFormatForValue
+        );
+    }
+
+    #[test]
+    fn decode_errors_map_to_reject_reasons() {
+        let err = DecodeError::Field {
+            offset: 20,
+            tag: Some(11),
+            kind: FieldErrorKind::TagWithoutValue,
+        };
+        assert_eq!(
+            SessionRejectReason::for_decode_error(&err),
+            Some(SessionRejectReason::TagSpecifiedWithoutAValue)
+        );
+        let err = DecodeError::CheckSum {
+            declared: 0,
+            actual: 1,
+        };
+        assert_eq!(SessionRejectReason::for_decode_error(&err), None);
+    }
+}
@@ -0,0 +1,545 @@
+use super::{
+    message_len, Action, Backend, Configure, Direction, FixConnection, LogEntry, MessageStore,
+    OutboundMessage, Role, SendError, Verify,
+};
+use crate::tagvalue::DecoderStreaming;
+use crate::SetField;
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Tags which differ from one run to the next and are ignored by default:
+/// `BodyLength <9>`, `CheckSum <10>`, `SendingTime <52>`, `TestReqID <112>`
+/// and `OrigSendingTime <122>`.
+pub const DEFAULT_IGNORED_TAGS: &[u32] = &[9, 10, 52, 112, 122];
+
+/// `StandardHeader` and `StandardTrailer` tags, which are written by the FIX
+/// session itself rather than by the application.
+const HEADER_AND_TRAILER_TAGS: &[u32] = &[
+    8, 9, 10, 34, 35, 43, 49, 50, 52, 56, 57, 89, 93, 97, 115, 116, 122, 128, 129, 142, 143, 144,
+    145, 347, 369, 464, 627, 628, 629, 630, 1128, 1129,
+];
+
+/// How fast a [`Replay`] goes through the recorded session.
+///
+/// The [`FixConnection`] always sees the recorded timing, whatever the pace:
+/// only the wall-clock time that the replay takes changes.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Pace {
+    /// No waiting at all.
+    #[default]
+    Unpaced,
+    /// As fast as the recorded session went.
+    Original,
+    /// This many times faster than the recorded session went.
+    Accelerated(f64),
+}
+
+/// Re-drives a recorded FIX session, e.g. one read back by a
+/// [`FileLogReader`](super::FileLogReader), against a fresh
+/// [`FixConnection`], and compares the outbound messages it produces to the
+/// recorded outbound ones.
+///
+/// Recorded inbound messages are fed to
+/// [`FixConnection::on_bytes`], recorded outbound application messages to
+/// [`FixConnection::send`], and [`FixConnection::on_tick`] is called
+/// whenever a timer armed by the [`FixConnection`] falls due in between. The
+/// [`FixConnection`] is the initiator if and only if the first recorded
+/// message is an outbound `Logon <A>`. Entries without a
+/// [`Direction`] are outbound if and only if their `SenderCompID <49>` is
+/// [`Configure::sender_comp_id`].
+///
+/// The recording must cover a single connection. The replay stops at the
+/// first divergence, or when the [`FixConnection`] closes the transport.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    entries: Vec<LogEntry>,
+    pace: Pace,
+    ignored_tags: Vec<u32>,
+}
+
+impl Replay {
+    /// Creates a new [`Replay`] of `entries`, in order.
+    pub fn new(entries: impl IntoIterator<Item = LogEntry>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+            pace: Pace::default(),
+            ignored_tags: DEFAULT_IGNORED_TAGS.to_vec(),
+        }
+    }
+
+    /// Sets the [`Pace`] of `self`. [`Pace::Unpaced`] by default.
+    pub fn with_pace(mut self, pace: Pace) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    /// Ignores `tags`, rather than [`DEFAULT_IGNORED_TAGS`], when comparing
+    /// outbound messages.
+    pub fn with_ignored_tags(mut self, tags: &[u32]) -> Self {
+        self.ignored_tags = tags.to_vec();
+        self
+    }
+
+    /// Replays `self` against `conn`, which must not be connected, decoding
+    /// inbound messages with `decoder`. Paced replays block the current
+    /// thread while waiting.
+    pub fn run<B, C, V, S>(
+        &self,
+        conn: &mut FixConnection<B, C, V, S>,
+        decoder: DecoderStreaming<Vec<u8>>,
+    ) -> Result<ReplayReport, ReplayError>
+    where
+        B: Backend,
+        C: Configure,
+        V: Verify,
+        S: MessageStore,
+    {
+        let mut report = ReplayReport::default();
+        let first = match self.entries.first() {
+            Some(entry) => entry,
+            None => return Ok(report),
+        };
+        let started_at = Instant::now();
+        let start = first.timestamp;
+        let role = match self.direction(first, conn.config()) {
+            Direction::Outgoing if msg_type(&first.message) == Some(b"A") => Role::Initiator,
+            _ => Role::Acceptor,
+        };
+        let mut run = Run {
+            expected: VecDeque::new(),
+            actual: VecDeque::new(),
+            deadline: None,
+            disconnected: false,
+        };
+        run.on_actions(conn.on_connected(role, decoder, start, started_at));
+        for (i, entry) in self.entries.iter().enumerate() {
+            if run.disconnected {
+                break;
+            }
+            let elapsed = (entry.timestamp - start).to_std().unwrap_or_default();
+            let now = started_at + elapsed;
+            self.wait(started_at, elapsed);
+            while let Some(deadline) = run.deadline.filter(|deadline| *deadline <= now) {
+                run.deadline = None;
+                run.on_actions(conn.on_tick(deadline));
+            }
+            match self.direction(entry, conn.config()) {
+                Direction::Incoming => {
+                    report.messages_in += 1;
+                    run.on_actions(conn.on_bytes(&entry.message, now));
+                }
+                Direction::Outgoing => {
+                    run.expected.push_back(entry.message.clone());
+                    let msg = app_message(&entry.message).ok_or(ReplayError::InvalidEntry(i))?;
+                    if let Some(msg) = msg {
+                        run.on_actions(conn.send(msg, now)?);
+                    }
+                }
+            }
+            if let Some(divergence) = self.compare(&mut run, &mut report, false) {
+                report.divergence = Some(divergence);
+                break;
+            }
+        }
+        conn.on_disconnected();
+        if report.divergence.is_none() {
+            report.divergence = self.compare(&mut run, &mut report, true);
+        }
+        Ok(report)
+    }
+
+    fn direction(&self, entry: &LogEntry, config: &impl Configure) -> Direction {
+        entry.direction.unwrap_or_else(|| {
+            let sender_comp_id = fields(&entry.message)
+                .into_iter()
+                .find(|(tag, _)| *tag == 49)
+                .map(|(_, value)| value);
+            if sender_comp_id.as_deref().map(str::as_bytes) == Some(config.sender_comp_id()) {
+                Direction::Outgoing
+            } else {
+                Direction::Incoming
+            }
+        })
+    }
+
+    fn wait(&self, started_at: Instant, elapsed: Duration) {
+        let elapsed = match self.pace {
+            Pace::Unpaced => return,
+            Pace::Original => elapsed,
+            Pace::Accelerated(factor) => elapsed.div_f64(factor),
+        };
+        let wait = (started_at + elapsed).saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Compares recorded and actual outbound messages pairwise. Once `done`,
+    /// unpaired messages are divergences too.
+    fn compare(&self, run: &mut Run, report: &mut ReplayReport, done: bool) -> Option<Divergence> {
+        loop {
+            let (expected, actual) = match (run.expected.front(), run.actual.front()) {
+                (Some(expected), Some(actual)) => (Some(expected), Some(actual)),
+                (None, None) => return None,
+                (expected, actual) if done => (expected, actual),
+                _ => return None,
+            };
+            let fields = self.diff(expected, actual);
+            if !fields.is_empty() {
+                return Some(Divergence {
+                    index: report.messages_out,
+                    expected: expected.cloned(),
+                    actual: actual.cloned(),
+                    fields,
+                });
+            }
+            run.expected.pop_front();
+            run.actual.pop_front();
+            report.messages_out += 1;
+        }
+    }
+
+    fn diff(&self, expected: Option<&Vec<u8>>, actual: Option<&Vec<u8>>) -> Vec<FieldDivergence> {
+        let expected = expected.map(|msg| fields(msg)).unwrap_or_default();
+        let actual = actual.map(|msg| fields(msg)).unwrap_or_default();
+        let mut tags: Vec<u32> = vec![];
+        for (tag, _) in expected.iter().chain(actual.iter()) {
+            if !tags.contains(tag) && !self.ignored_tags.contains(tag) {
+                tags.push(*tag);
+            }
+        }
+        let values = |fields: &[(u32, String)], tag: u32| -> Vec<String> {
+            fields
+                .iter()
+                .filter(|(t, _)| *t == tag)
+                .map(|(_, value)| value.clone())
+                .collect()
+        };
+        tags.into_iter()
+            .filter_map(|tag| {
+                let expected = values(&expected, tag);
+                let actual = values(&actual, tag);
+                (expected != actual).then_some(FieldDivergence {
+                    tag,
+                    expected,
+                    actual,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The state of a single [`Replay::run`].
+struct Run {
+    expected: VecDeque<Vec<u8>>,
+    actual: VecDeque<Vec<u8>>,
+    deadline: Option<Instant>,
+    disconnected: bool,
+}
+
+impl Run {
+    fn on_actions(&mut self, actions: Vec<Action>) {
+        self.deadline = None;
+        for action in actions {
+            match action {
+                Action::Write(bytes) => {
+                    let mut bytes = &bytes[..];
+                    while let Some(len) = message_len(bytes) {
+                        self.actual.push_back(bytes[..len].to_vec());
+                        bytes = &bytes[len..];
+                    }
+                }
+                Action::ArmTimer(deadline) => self.deadline = Some(deadline),
+                Action::Disconnect(_) => self.disconnected = true,
+            }
+        }
+    }
+}
+
+/// The outcome of a [`Replay::run`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of recorded inbound messages that were replayed.
+    pub messages_in: usize,
+    /// The number of outbound messages that matched the recorded ones.
+    pub messages_out: usize,
+    /// The first outbound message that didn't match, if any.
+    pub divergence: Option<Divergence>,
+}
+
+/// An outbound message that doesn't match its recorded counterpart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The position of the outbound message, starting from zero.
+    pub index: usize,
+    /// The recorded outbound message, if any.
+    pub expected: Option<Vec<u8>>,
+    /// The outbound message of the [`FixConnection`], if any.
+    pub actual: Option<Vec<u8>>,
+    /// The fields that differ, in order of appearance.
+    pub fields: Vec<FieldDivergence>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Outbound message #{} diverges", self.index)?;
+        match (&self.expected, &self.actual) {
+            (Some(_), None) => return write!(f, ": recorded, but never sent"),
+            (None, Some(_)) => return write!(f, ": sent, but never recorded"),
+            _ => {}
+        }
+        for field in &self.fields {
+            write!(f, "\n  {}", field)?;
+        }
+        Ok(())
+    }
+}
+
+/// A field of a [`Divergence`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDivergence {
+    /// The tag of the field.
+    pub tag: u32,
+    /// The recorded values, one per occurrence of `tag`.
+    pub expected: Vec<String>,
+    /// The actual values, one per occurrence of `tag`.
+    pub actual: Vec<String>,
+}
+
+impl fmt::Display for FieldDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<{}>: expected {:?}, got {:?}",
+            self.tag, self.expected, self.actual
+        )
+    }
+}
+
+/// The type returned in the event of a failed [`Replay::run`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReplayError {
+    /// A recorded message has no `MsgType <35>`.
+    #[error("Entry #{0} isn't a valid FIX message.")]
+    InvalidEntry(usize),
+    /// A recorded outbound application message couldn't be sent.
+    #[error("A recorded application message couldn't be sent: {0}")]
+    Send(#[from] SendError),
+}
+
+/// Splits `msg` into its fields, in order.
+fn fields(msg: &[u8]) -> Vec<(u32, String)> {
+    msg.split(|b| *b == b'\x01')
+        .filter_map(|field| {
+            let field = String::from_utf8_lossy(field);
+            let (tag, value) = field.split_once('=')?;
+            Some((tag.parse().ok()?, value.to_string()))
+        })
+        .collect()
+}
+
+fn msg_type(msg: &[u8]) -> Option<&[u8]> {
+    msg.split(|b| *b == b'\x01')
+        .find_map(|field| field.strip_prefix(b"35="))
+}
+
+/// Rebuilds the recorded application message `msg`, or returns [`None`] if
+/// it's a session message. Fails if `msg` has no `MsgType <35>`.
+fn app_message(msg: &[u8]) -> Option<Option<OutboundMessage>> {
+    let mut app_msg = OutboundMessage::new(msg_type(msg)?);
+    if app_msg.is_session_message() {
+        return Some(None);
+    }
+    for (tag, value) in fields(msg) {
+        if !HEADER_AND_TRAILER_TAGS.contains(&tag) {
+            app_msg.set(tag, value.as_str());
+        }
+    }
+    Some(Some(app_msg))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session::{Config, Environment, SessionState};
+    use crate::tagvalue::{Decoder, Message};
+    use crate::Dictionary;
+    use chrono::{DateTime, Utc};
+    use std::ops::Range;
+
+    #[derive(Debug, Clone, Default)]
+    struct NullBackend;
+
+    impl Backend for NullBackend {
+        type Error = u32;
+
+        fn sender_comp_id(&self) -> &[u8] {
+            b""
+        }
+
+        fn target_comp_id(&self) -> &[u8] {
+            b""
+        }
+
+        fn environment(&self) -> Environment {
+            Environment::Production { allow_test: true }
+        }
+
+        fn on_inbound_app_message(&mut self, _message: Message<&[u8]>) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_outbound_message(&mut self, _message: &[u8]) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_resend_request(&mut self, _range: Range<u64>) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_successful_handshake(&mut self) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn fetch_messages(&mut self) -> Result<&[&[u8]], u32> {
+            Ok(&[])
+        }
+
+        fn pending_message(&mut self) -> Option<&[u8]> {
+            None
+        }
+    }
+
+    fn decoder() -> DecoderStreaming<Vec<u8>> {
+        Decoder::new(Dictionary::fix44()).streaming(vec![])
+    }
+
+    fn entries(bytes: &[u8], direction: Direction, timestamp: DateTime<Utc>) -> Vec<LogEntry> {
+        let mut entries = vec![];
+        let mut bytes = bytes;
+        while let Some(len) = message_len(bytes) {
+            entries.push(LogEntry {
+                timestamp,
+                direction: Some(direction),
+                message: bytes[..len].to_vec(),
+            });
+            bytes = &bytes[len..];
+        }
+        entries
+    }
+
+    fn at(timestamp: DateTime<Utc>, secs: u64) -> DateTime<Utc> {
+        timestamp + chrono::Duration::seconds(secs as i64)
+    }
+
+    fn written(actions: Vec<Action>) -> Vec<u8> {
+        actions
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Write(bytes) => Some(bytes),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Records a session between two [`FixConnection`]s: a logon, an order
+    /// and a heartbeat.
+    fn record(config: &Config) -> Vec<LogEntry> {
+        let t0 = Instant::now();
+        let utc0 = Utc::now();
+        let mut counterparty_config = config.clone();
+        counterparty_config.sender_comp_id = config.target_comp_id.clone();
+        counterparty_config.target_comp_id = config.sender_comp_id.clone();
+        // Outbound `SendingTime <52>` follows the wall clock, not `now`.
+        counterparty_config.max_allowed_latency = Duration::from_secs(3600);
+        let mut us = FixConnection::new(config.clone(), NullBackend);
+        let mut them = FixConnection::new(counterparty_config, NullBackend);
+        them.on_connected(Role::Acceptor, decoder(), utc0, t0);
+        let mut log = vec![];
+        for secs in [0, 1, 31] {
+            let now = t0 + Duration::from_secs(secs);
+            let mut outbound = written(match secs {
+                0 => us.on_connected(Role::Initiator, decoder(), utc0, now),
+                1 => {
+                    let mut order = OutboundMessage::new(b"D");
+                    order.set(11, "order-1");
+                    us.send(order, now).unwrap()
+                }
+                _ => us.on_tick(now),
+            });
+            while !outbound.is_empty() {
+                log.extend(entries(&outbound, Direction::Outgoing, at(utc0, secs)));
+                let inbound = written(them.on_bytes(&outbound, now));
+                log.extend(entries(&inbound, Direction::Incoming, at(utc0, secs)));
+                outbound = written(us.on_bytes(&inbound, now));
+            }
+        }
+        assert_eq!(us.state(), SessionState::Active);
+        log
+    }
+
+    #[test]
+    fn identical_sessions_dont_diverge() {
+        let config = Config::default();
+        let recorded = record(&config);
+        let msg_types = recorded
+            .iter()
+            .map(|entry| (entry.direction, msg_type(&entry.message).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            msg_types,
+            vec![
+                (Some(Direction::Outgoing), &b"A"[..]),
+                (Some(Direction::Incoming), &b"A"[..]),
+                (Some(Direction::Outgoing), &b"D"[..]),
+                (Some(Direction::Outgoing), &b"0"[..]),
+            ]
+        );
+        let mut conn = FixConnection::new(config, NullBackend);
+        let report = Replay::new(recorded).run(&mut conn, decoder()).unwrap();
+        assert_eq!(
+            report,
+            ReplayReport {
+                messages_in: 1,
+                messages_out: 3,
+                divergence: None,
+            }
+        );
+    }
+
+    #[test]
+    fn the_first_divergence_is_reported_field_by_field() {
+        let config = Config::default();
+        let mut recorded = record(&config);
+        let mut other_config = config.clone();
+        other_config.heartbeat = config.heartbeat * 2;
+        let mut conn = FixConnection::new(other_config, NullBackend);
+        let report = Replay::new(recorded.clone())
+            .run(&mut conn, decoder())
+            .unwrap();
+        let divergence = report.divergence.unwrap();
+        assert_eq!(divergence.index, 0);
+        assert_eq!(
+            divergence.fields,
+            vec![FieldDivergence {
+                tag: 108,
+                expected: vec![config.heartbeat.as_secs().to_string()],
+                actual: vec![(config.heartbeat.as_secs() * 2).to_string()],
+            }]
+        );
+
+        // Without a direction, entries are told apart by their CompIDs.
+        for entry in &mut recorded {
+            entry.direction = None;
+        }
+        recorded.push(recorded[3].clone());
+        let mut conn = FixConnection::new(config, NullBackend);
+        let report = Replay::new(recorded).run(&mut conn, decoder()).unwrap();
+        let divergence = report.divergence.unwrap();
+        assert_eq!(report.messages_out, 3);
+        assert_eq!(divergence.actual, None);
+        assert!(divergence.to_string().ends_with("recorded, but never sent"));
+    }
+}
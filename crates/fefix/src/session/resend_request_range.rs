@@ -1,12 +1,71 @@
+use std::ops::Range;
+
 /// The `MsgSeqNum` range in a `ResendRequest` message.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ResendRequestRange {
-    start: usize,
-    end: Option<usize>,
+    start: u64,
+    end: Option<u64>,
 }
 
 impl ResendRequestRange {
-    pub fn new(start: usize, end: Option<usize>) -> Self {
+    /// Creates a new [`ResendRequestRange`] from `start` up to `end`, both
+    /// inclusive. [`None`] means "up to the most recent message".
+    pub fn new(start: u64, end: Option<u64>) -> Self {
         Self { start, end }
     }
+
+    /// Creates a new [`ResendRequestRange`] from the values of `BeginSeqNo
+    /// <7>` and `EndSeqNo <16>`, where zero means "infinity".
+    pub fn from_seq_nos(begin_seq_no: u64, end_seq_no: u64) -> Self {
+        Self::new(begin_seq_no, Some(end_seq_no).filter(|n| *n != 0))
+    }
+
+    /// Returns the first seq. number in the range.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the last seq. number in the range, if any.
+    pub fn end(&self) -> Option<u64> {
+        self.end
+    }
+
+    /// Returns the value of `BeginSeqNo <7>`.
+    pub fn begin_seq_no(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the value of `EndSeqNo <16>`.
+    pub fn end_seq_no(&self) -> u64 {
+        self.end.unwrap_or(0)
+    }
+
+    /// Returns the seq. numbers in the range that have already been used, given
+    /// the seq. number of the next message. The returned [`Range`] is
+    /// exclusive, and empty if `BeginSeqNo <7>` is past `EndSeqNo <16>` or
+    /// hasn't been used yet.
+    ///
+    /// ```
+    /// use fefix::session::ResendRequestRange;
+    ///
+    /// let range = ResendRequestRange::from_seq_nos(3, 0);
+    /// assert_eq!(range.to_range(10), 3..10);
+    /// let range = ResendRequestRange::from_seq_nos(3, 50);
+    /// assert_eq!(range.to_range(10), 3..10);
+    /// let range = ResendRequestRange::from_seq_nos(3, 5);
+    /// assert_eq!(range.to_range(10), 3..6);
+    /// let range = ResendRequestRange::from_seq_nos(3, u64::MAX);
+    /// assert_eq!(range.to_range(10), 3..10);
+    /// let range = ResendRequestRange::from_seq_nos(5, 3);
+    /// assert!(range.to_range(10).is_empty());
+    /// let range = ResendRequestRange::from_seq_nos(100, 0);
+    /// assert!(range.to_range(10).is_empty());
+    /// ```
+    pub fn to_range(&self, next_msg_seq_num: u64) -> Range<u64> {
+        let end = match self.end {
+            Some(end) => end.saturating_add(1).min(next_msg_seq_num),
+            None => next_msg_seq_num,
+        };
+        self.start.min(end)..end
+    }
 }
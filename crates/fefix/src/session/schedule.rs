@@ -0,0 +1,271 @@
+use crate::field_types::Tz;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use std::ops::Range;
+
+/// The trading window(s) of a FIX session, e.g. "from 17:05 to 17:00 of the
+/// next day, New York time, starting from Sunday to Thursday".
+///
+/// Each window is a separate FIX session: seq. numbers start again from 1
+/// and no message is ever resent across windows. Outside of its windows, a
+/// FIX session must be logged out.
+///
+/// Times are local to a [`Tz`], which is a fixed offset from UTC. Daylight
+/// saving time changes must be handled by updating the [`Schedule`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    start_time: NaiveTime,
+    end_time: NaiveTime,
+    days: ScheduleDays,
+    tz: Tz,
+}
+
+/// The days on which the windows of a [`Schedule`] begin and end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleDays {
+    /// A new window begins every day at the start time and lasts until the
+    /// next end time, possibly on the day after. Windows only begin on these
+    /// days; an empty list means every day of the week.
+    Daily(Vec<Weekday>),
+    /// A single window per week, from the start time of `start` up to the
+    /// end time of `end`.
+    Weekly {
+        /// The day on which the window begins.
+        start: Weekday,
+        /// The day on which the window ends.
+        end: Weekday,
+    },
+}
+
+impl Schedule {
+    /// Creates a new [`Schedule`] with a window that begins every day at
+    /// `start_time` and ends at `end_time`. If `end_time` is not later than
+    /// `start_time`, windows end on the following day.
+    pub fn daily(start_time: NaiveTime, end_time: NaiveTime, tz: Tz) -> Self {
+        Self {
+            start_time,
+            end_time,
+            days: ScheduleDays::Daily(vec![]),
+            tz,
+        }
+    }
+
+    /// Creates a new [`Schedule`] with a single window per week, from
+    /// `start_time` on `start_day` up to `end_time` on `end_day`.
+    pub fn weekly(
+        start_day: Weekday,
+        start_time: NaiveTime,
+        end_day: Weekday,
+        end_time: NaiveTime,
+        tz: Tz,
+    ) -> Self {
+        Self {
+            start_time,
+            end_time,
+            days: ScheduleDays::Weekly {
+                start: start_day,
+                end: end_day,
+            },
+            tz,
+        }
+    }
+
+    /// Only lets daily windows begin on `days`, or on every day if `days` is
+    /// empty. Weekly schedules are left unchanged.
+    pub fn on_days(mut self, days: impl IntoIterator<Item = Weekday>) -> Self {
+        if let ScheduleDays::Daily(_) = self.days {
+            self.days = ScheduleDays::Daily(days.into_iter().collect());
+        }
+        self
+    }
+
+    /// Returns the local time at which windows begin.
+    pub fn start_time(&self) -> NaiveTime {
+        self.start_time
+    }
+
+    /// Returns the local time at which windows end.
+    pub fn end_time(&self) -> NaiveTime {
+        self.end_time
+    }
+
+    /// Returns the days on which windows begin and end.
+    pub fn days(&self) -> &ScheduleDays {
+        &self.days
+    }
+
+    /// Returns the [`Tz`] of [`Schedule::start_time`] and
+    /// [`Schedule::end_time`].
+    pub fn tz(&self) -> Tz {
+        self.tz
+    }
+
+    /// Returns the window that contains `now`, if any.
+    ///
+    /// ```
+    /// use chrono::{NaiveTime, TimeZone, Utc};
+    /// use fefix::field_types::Tz;
+    /// use fefix::session::Schedule;
+    /// use fefix::FieldType;
+    ///
+    /// let new_york = Tz::deserialize(b"-05").unwrap();
+    /// let schedule = Schedule::daily(
+    ///     NaiveTime::from_hms_opt(17, 5, 0).unwrap(),
+    ///     NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+    ///     new_york,
+    /// );
+    /// let now = Utc.with_ymd_and_hms(2024, 1, 10, 22, 2, 0).unwrap();
+    /// assert_eq!(schedule.current_window(now), None);
+    /// let now = Utc.with_ymd_and_hms(2024, 1, 10, 22, 10, 0).unwrap();
+    /// let window = schedule.current_window(now).unwrap();
+    /// assert_eq!(window.start, Utc.with_ymd_and_hms(2024, 1, 10, 22, 5, 0).unwrap());
+    /// assert_eq!(window.end, Utc.with_ymd_and_hms(2024, 1, 11, 22, 0, 0).unwrap());
+    /// ```
+    pub fn current_window(&self, now: DateTime<Utc>) -> Option<Range<DateTime<Utc>>> {
+        let local = self.to_local(now);
+        // Windows last at most a week.
+        (0..=7)
+            .map(|days_ago| local.date() - Duration::days(days_ago))
+            .filter_map(|date| self.window_starting_on(date))
+            .find(|window| window.contains(&local))
+            .map(|window| self.to_utc(window.start)..self.to_utc(window.end))
+    }
+
+    /// Returns `true` if and only if `now` is within one of the windows of
+    /// `self`.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.current_window(now).is_some()
+    }
+
+    /// Returns the beginning of the first window that begins after `now`.
+    pub fn next_window_start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let local = self.to_local(now);
+        let start = (0..=7)
+            .map(|days_ahead| local.date() + Duration::days(days_ahead))
+            .filter_map(|date| self.window_starting_on(date))
+            .map(|window| window.start)
+            .find(|start| *start > local)
+            // At least one window begins every week.
+            .unwrap_or(local + Duration::weeks(1));
+        self.to_utc(start)
+    }
+
+    /// Returns the window of `self` which begins on the local `date`, if any.
+    fn window_starting_on(&self, date: NaiveDate) -> Option<Range<NaiveDateTime>> {
+        let start = date.and_time(self.start_time);
+        let duration = match &self.days {
+            ScheduleDays::Daily(days) => {
+                if !days.is_empty() && !days.contains(&start.weekday()) {
+                    return None;
+                }
+                positive_modulo(self.end_time - self.start_time, Duration::days(1))
+            }
+            ScheduleDays::Weekly { start: day, end } => {
+                if start.weekday() != *day {
+                    return None;
+                }
+                let days =
+                    i64::from(end.num_days_from_monday()) - i64::from(day.num_days_from_monday());
+                positive_modulo(
+                    Duration::days(days) + (self.end_time - self.start_time),
+                    Duration::weeks(1),
+                )
+            }
+        };
+        Some(start..start + duration)
+    }
+
+    fn to_local(&self, utc: DateTime<Utc>) -> NaiveDateTime {
+        utc.naive_utc() + Duration::seconds(i64::from(self.tz.offset_as_secs()))
+    }
+
+    fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        let utc = local - Duration::seconds(i64::from(self.tz.offset_as_secs()));
+        DateTime::from_naive_utc_and_offset(utc, Utc)
+    }
+}
+
+/// Wraps `duration` into `(0, period]`, so that a window which ends at its
+/// own start time lasts a whole `period`.
+fn positive_modulo(duration: Duration, period: Duration) -> Duration {
+    let secs = duration.num_seconds().rem_euclid(period.num_seconds());
+    if secs == 0 {
+        period
+    } else {
+        Duration::seconds(secs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FieldType;
+    use chrono::TimeZone;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn utc(d: u32, h: u32, m: u32) -> DateTime<Utc> {
+        // January 2024 begins on a Monday.
+        Utc.with_ymd_and_hms(2024, 1, d, h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn daily_schedule_restricted_to_some_days() {
+        let new_york = Tz::deserialize(b"-05").unwrap();
+        // Sunday to Thursday, 17:05 to 17:00 of the next day.
+        let schedule = Schedule::daily(time(17, 5), time(17, 0), new_york).on_days([
+            Weekday::Sun,
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+        ]);
+        // Friday 17:30 in New York.
+        assert!(!schedule.is_active(utc(5, 22, 30)));
+        // Saturday 12:00 in New York.
+        assert!(!schedule.is_active(utc(6, 17, 0)));
+        assert_eq!(schedule.next_window_start(utc(6, 17, 0)), utc(7, 22, 5));
+        // Monday 09:00 in New York, within Sunday's window.
+        assert_eq!(
+            schedule.current_window(utc(8, 14, 0)),
+            Some(utc(7, 22, 5)..utc(8, 22, 0))
+        );
+        // Friday 09:00 in New York, within Thursday's window.
+        assert_eq!(
+            schedule.current_window(utc(5, 14, 0)),
+            Some(utc(4, 22, 5)..utc(5, 22, 0))
+        );
+    }
+
+    #[test]
+    fn weekly_schedule() {
+        let schedule = Schedule::weekly(
+            Weekday::Sun,
+            time(17, 5),
+            Weekday::Fri,
+            time(17, 0),
+            Tz::UTC,
+        );
+        assert_eq!(
+            schedule.current_window(utc(10, 0, 0)),
+            Some(utc(7, 17, 5)..utc(12, 17, 0))
+        );
+        assert!(!schedule.is_active(utc(12, 17, 0)));
+        assert!(!schedule.is_active(utc(14, 17, 4)));
+        assert_eq!(schedule.next_window_start(utc(12, 17, 0)), utc(14, 17, 5));
+    }
+
+    #[test]
+    fn same_start_and_end_time_means_a_whole_day() {
+        let schedule = Schedule::daily(time(6, 0), time(6, 0), Tz::UTC);
+        assert_eq!(
+            schedule.current_window(utc(10, 5, 59)),
+            Some(utc(9, 6, 0)..utc(10, 6, 0))
+        );
+        assert_eq!(
+            schedule.current_window(utc(10, 6, 0)),
+            Some(utc(10, 6, 0)..utc(11, 6, 0))
+        );
+    }
+}
@@ -0,0 +1,518 @@
+use super::connection::next_message;
+use super::{
+    errs, Backend, Config, Configure, ConnectionError, FixConnection, MemoryStore, MessageStore,
+    Verifier, Verify,
+};
+use crate::tagvalue::{Decoder, DecoderStreaming, Message};
+use crate::{Dictionary, FieldMap};
+use futures::io::Cursor;
+use futures::{select, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt};
+use futures_timer::Delay;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+use std::marker::Unpin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+const BEGIN_STRING: u32 = 8;
+const MSG_TYPE: u32 = 35;
+const SENDER_COMP_ID: u32 = 49;
+const TARGET_COMP_ID: u32 = 56;
+
+type Sessions<B, C, V, S> = HashMap<SessionKey, Option<FixConnection<B, C, V, S>>>;
+
+/// Identifies a FIX session from our own point of view.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    /// The `BeginString <8>` of the FIX session.
+    pub begin_string: Vec<u8>,
+    /// Our own CompID, i.e. the `SenderCompID <49>` of outbound messages.
+    pub sender_comp_id: Vec<u8>,
+    /// The counterparty's CompID, i.e. the `TargetCompID <56>` of outbound
+    /// messages.
+    pub target_comp_id: Vec<u8>,
+}
+
+impl SessionKey {
+    /// Returns the [`SessionKey`] of a FIX session configured with `config`.
+    pub fn of_config(config: &impl Configure) -> Self {
+        Self {
+            begin_string: config.begin_string().to_vec(),
+            sender_comp_id: config.sender_comp_id().to_vec(),
+            target_comp_id: config.target_comp_id().to_vec(),
+        }
+    }
+
+    /// Returns the [`SessionKey`] of the FIX session that an inbound `msg`
+    /// belongs to. The CompIDs of `msg` are swapped, since it was sent by
+    /// the counterparty.
+    pub fn of_inbound(msg: &Message<&[u8]>) -> Self {
+        Self {
+            begin_string: msg.get_raw(BEGIN_STRING).unwrap_or_default().to_vec(),
+            sender_comp_id: msg.get_raw(TARGET_COMP_ID).unwrap_or_default().to_vec(),
+            target_comp_id: msg.get_raw(SENDER_COMP_ID).unwrap_or_default().to_vec(),
+        }
+    }
+}
+
+/// An acceptor that hosts many FIX sessions behind a single listening
+/// address.
+///
+/// Each FIX session is a [`FixConnection`], complete with its own
+/// [`Backend`] and [`MessageStore`], and it's registered with
+/// [`SessionServer::add_session`]. The first `Logon <A>` of every transport
+/// is routed to the FIX session with the same [`SessionKey`]. Transports are
+/// closed without a reply if the [`SessionKey`] is unknown or if its FIX
+/// session is already logged on.
+///
+/// [`SessionServer`] is cheap to clone: all clones share the same FIX
+/// sessions.
+#[derive(Debug)]
+pub struct SessionServer<B, C = Config, V = Verifier<C>, S = MemoryStore> {
+    dictionary: Dictionary,
+    app_dictionaries: Vec<(Vec<u8>, Dictionary)>,
+    logon_timeout: Duration,
+    sessions: Arc<Mutex<Sessions<B, C, V, S>>>,
+}
+
+impl<B, C, V, S> Clone for SessionServer<B, C, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            dictionary: self.dictionary.clone(),
+            app_dictionaries: self.app_dictionaries.clone(),
+            logon_timeout: self.logon_timeout,
+            sessions: self.sessions.clone(),
+        }
+    }
+}
+
+impl<B, C, V, S> SessionServer<B, C, V, S>
+where
+    B: Backend,
+    C: Configure,
+    V: Verify,
+    S: MessageStore,
+{
+    /// Creates a new [`SessionServer`] without any FIX sessions. Inbound
+    /// messages are decoded according to `dictionary`.
+    pub fn new(dictionary: Dictionary) -> Self {
+        Self {
+            dictionary,
+            app_dictionaries: vec![],
+            logon_timeout: Duration::from_secs(10),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Decodes application messages of FIXT.1.1 sessions with
+    /// `ApplVerID <1128>` equal to `appl_ver_id` according to `dict`. See
+    /// [`Decoder::add_app_dictionary`].
+    pub fn add_app_dictionary(&mut self, appl_ver_id: &[u8], dict: Dictionary) {
+        self.app_dictionaries.push((appl_ver_id.to_vec(), dict));
+    }
+
+    /// Sets how long to wait for the first `Logon <A>` of each transport. Ten
+    /// seconds by default.
+    pub fn set_logon_timeout(&mut self, logon_timeout: Duration) {
+        self.logon_timeout = logon_timeout;
+    }
+
+    /// Registers a new FIX session, which is identified by the
+    /// [`SessionKey`] of its configuration options. Returns `false` if a FIX
+    /// session with the same [`SessionKey`] was already registered, in which
+    /// case nothing changes.
+    pub fn add_session(&self, conn: FixConnection<B, C, V, S>) -> bool {
+        let key = SessionKey::of_config(conn.config());
+        match self.sessions().entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Some(conn));
+                true
+            }
+        }
+    }
+
+    /// Returns `true` if and only if the FIX session identified by `key` is
+    /// currently running on a transport.
+    pub fn is_running(&self, key: &SessionKey) -> bool {
+        matches!(self.sessions().get(key), Some(None))
+    }
+
+    /// Calls `f` with the FIX session identified by `key`, unless it's
+    /// either unknown or running.
+    pub fn with_session<R>(
+        &self,
+        key: &SessionKey,
+        f: impl FnOnce(&mut FixConnection<B, C, V, S>) -> R,
+    ) -> Option<R> {
+        self.sessions().get_mut(key)?.as_mut().map(f)
+    }
+
+    /// Serves a single transport until its FIX session is over, or until
+    /// its first `Logon <A>` is refused. The transport is closed before
+    /// returning.
+    pub async fn handle<I, O>(&self, mut input: I, mut output: O) -> Result<(), ConnectionError>
+    where
+        I: AsyncRead + Unpin,
+        O: AsyncWrite + Unpin,
+    {
+        let routed = select! {
+            result = self.read_logon(&mut input).fuse() => result,
+            () = Delay::new(self.logon_timeout).fuse() => Err(ConnectionError::Timeout),
+        };
+        let (key, conn, bytes_read) = match routed {
+            Ok(routed) => routed,
+            Err(err) => {
+                output.close().await.ok();
+                return Err(err);
+            }
+        };
+        let mut checked_out = CheckedOut {
+            sessions: &self.sessions,
+            key,
+            conn: None,
+        };
+        let conn = checked_out.conn.get_or_insert(conn);
+        // The `Logon <A>` must be decoded once again by the FIX session.
+        let input = Cursor::new(bytes_read).chain(input);
+        conn.accept(input, output, self.decoder()).await
+    }
+
+    /// Accepts TCP connections from `listener` and serves each of them on
+    /// its own task, forever.
+    #[cfg(feature = "utils-tokio")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "utils-tokio")))]
+    pub async fn serve(&self, listener: tokio::net::TcpListener) -> io::Result<()>
+    where
+        FixConnection<B, C, V, S>: Send + 'static,
+    {
+        use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                let (input, output) = stream.into_split();
+                server
+                    .handle(input.compat(), output.compat_write())
+                    .await
+                    .ok();
+            });
+        }
+    }
+
+    /// Reads from `input` until the first message is complete, and checks out
+    /// the FIX session it belongs to. All bytes read so far are returned too.
+    async fn read_logon<I>(
+        &self,
+        input: &mut I,
+    ) -> Result<(SessionKey, FixConnection<B, C, V, S>, Vec<u8>), ConnectionError>
+    where
+        I: AsyncRead + Unpin,
+    {
+        let mut decoder = self.decoder();
+        let mut bytes_read = vec![];
+        let mut buffer = vec![0; 4096];
+        loop {
+            let num_bytes = input.read(&mut buffer).await?;
+            if num_bytes == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            bytes_read.extend_from_slice(&buffer[..num_bytes]);
+            if next_message(&mut decoder, &mut &buffer[..num_bytes])? {
+                break;
+            }
+        }
+        let logon = decoder.message();
+        if logon.get_raw(MSG_TYPE) != Some(b"A") {
+            return Err(ConnectionError::Logon(errs::logon_expected()));
+        }
+        let key = SessionKey::of_inbound(&logon);
+        let conn = match self.sessions().get_mut(&key) {
+            Some(session) => session
+                .take()
+                .ok_or_else(|| ConnectionError::Logon(errs::already_logged_on()))?,
+            None => return Err(ConnectionError::Logon(errs::unknown_session())),
+        };
+        Ok((key, conn, bytes_read))
+    }
+
+    fn decoder(&self) -> DecoderStreaming<Vec<u8>> {
+        let mut decoder = Decoder::new(self.dictionary.clone());
+        for (appl_ver_id, dict) in &self.app_dictionaries {
+            decoder.add_app_dictionary(appl_ver_id, dict.clone());
+        }
+        decoder.streaming(vec![])
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, Sessions<B, C, V, S>> {
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A FIX session that's been taken out of a [`SessionServer`] to run on a
+/// transport. It's put back when dropped, even if the future that runs it is
+/// cancelled halfway through.
+struct CheckedOut<'a, B, C, V, S>
+where
+    B: Backend,
+    C: Configure,
+    V: Verify,
+    S: MessageStore,
+{
+    sessions: &'a Mutex<Sessions<B, C, V, S>>,
+    key: SessionKey,
+    conn: Option<FixConnection<B, C, V, S>>,
+}
+
+impl<B, C, V, S> Drop for CheckedOut<'_, B, C, V, S>
+where
+    B: Backend,
+    C: Configure,
+    V: Verify,
+    S: MessageStore,
+{
+    fn drop(&mut self) {
+        if let Some(mut conn) = self.conn.take() {
+            // The transport is gone, whatever the FIX session thinks.
+            conn.on_disconnected();
+            let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+            sessions.insert(self.key.clone(), Some(conn));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::field_types::Timestamp;
+    use crate::session::{Environment, SessionState};
+    use crate::tagvalue::Encoder;
+    use crate::SetField;
+    use std::ops::Range;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, DuplexStream};
+    use tokio_util::compat::*;
+
+    #[derive(Debug, Clone, Default)]
+    struct NullBackend;
+
+    impl Backend for NullBackend {
+        type Error = u32;
+
+        fn sender_comp_id(&self) -> &[u8] {
+            b"SERVER"
+        }
+
+        fn target_comp_id(&self) -> &[u8] {
+            b""
+        }
+
+        fn environment(&self) -> Environment {
+            Environment::Production { allow_test: true }
+        }
+
+        fn on_inbound_app_message(&mut self, _message: Message<&[u8]>) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_outbound_message(&mut self, _message: &[u8]) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_resend_request(&mut self, _range: Range<u64>) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_successful_handshake(&mut self) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn fetch_messages(&mut self) -> Result<&[&[u8]], u32> {
+            Ok(&[])
+        }
+
+        fn pending_message(&mut self) -> Option<&[u8]> {
+            None
+        }
+    }
+
+    fn server() -> SessionServer<NullBackend> {
+        let server = SessionServer::new(Dictionary::fix44());
+        for client in ["CLIENT_A", "CLIENT_B"] {
+            let mut config = Config::default();
+            config.sender_comp_id = "SERVER".to_string();
+            config.target_comp_id = client.to_string();
+            assert!(server.add_session(FixConnection::new(config, NullBackend)));
+        }
+        server
+    }
+
+    fn key(client: &str) -> SessionKey {
+        SessionKey {
+            begin_string: b"FIX.4.4".to_vec(),
+            sender_comp_id: b"SERVER".to_vec(),
+            target_comp_id: client.as_bytes().to_vec(),
+        }
+    }
+
+    fn client_msg(client: &str, msg_type: &[u8], msg_seq_num: u64) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut encoder = Encoder::new();
+        let mut msg = encoder.start_message(b"FIX.4.4", &mut buffer, msg_type);
+        msg.set(SENDER_COMP_ID, client);
+        msg.set(TARGET_COMP_ID, "SERVER");
+        msg.set(34, msg_seq_num);
+        msg.set(52, Timestamp::utc_now());
+        if msg_type == b"A" {
+            msg.set(98, 0);
+            msg.set(108, 30);
+        }
+        msg.done();
+        buffer
+    }
+
+    async fn read_message(stream: &mut DuplexStream) -> Option<Vec<u8>> {
+        let mut msg = vec![];
+        loop {
+            msg.push(stream.read_u8().await.ok()?);
+            if msg.len() > 8 && msg[msg.len() - 8..].starts_with(b"\x0110=") {
+                return Some(msg);
+            }
+        }
+    }
+
+    fn handle(
+        server: &SessionServer<NullBackend>,
+        stream: DuplexStream,
+    ) -> impl std::future::Future<Output = Result<(), ConnectionError>> + '_ {
+        let (input, output) = tokio::io::split(stream);
+        server.handle(input.compat(), output.compat_write())
+    }
+
+    #[tokio::test]
+    async fn logon_is_routed_by_comp_ids() {
+        let server = server();
+        let (stream, mut client) = tokio::io::duplex(4096);
+        let client = tokio::spawn(async move {
+            client
+                .write_all(&client_msg("CLIENT_B", b"A", 1))
+                .await
+                .unwrap();
+            read_message(&mut client).await.unwrap();
+            client
+                .write_all(&client_msg("CLIENT_B", b"5", 2))
+                .await
+                .unwrap();
+            read_message(&mut client).await.unwrap();
+        });
+        handle(&server, stream).await.unwrap();
+        client.await.unwrap();
+
+        let seq_numbers = |client| {
+            server
+                .with_session(&key(client), |conn| conn.seq_numbers().next_inbound())
+                .unwrap()
+        };
+        assert_eq!(seq_numbers("CLIENT_A"), 1);
+        assert_eq!(seq_numbers("CLIENT_B"), 3);
+    }
+
+    #[tokio::test]
+    async fn unknown_counterparty_is_disconnected() {
+        let server = server();
+        let (stream, mut client) = tokio::io::duplex(4096);
+        client
+            .write_all(&client_msg("CLIENT_C", b"A", 1))
+            .await
+            .unwrap();
+        let result = handle(&server, stream).await;
+
+        let text = errs::unknown_session();
+        assert!(matches!(result, Err(ConnectionError::Logon(t)) if t == text));
+        assert_eq!(read_message(&mut client).await, None);
+    }
+
+    #[tokio::test]
+    async fn session_cannot_be_logged_on_twice() {
+        let server = server();
+        let (stream, mut client) = tokio::io::duplex(4096);
+        let first = tokio::spawn({
+            let server = server.clone();
+            async move { handle(&server, stream).await }
+        });
+        client
+            .write_all(&client_msg("CLIENT_A", b"A", 1))
+            .await
+            .unwrap();
+        read_message(&mut client).await.unwrap();
+        assert!(server.is_running(&key("CLIENT_A")));
+        assert!(server.with_session(&key("CLIENT_A"), |_| ()).is_none());
+
+        let (stream, mut intruder) = tokio::io::duplex(4096);
+        intruder
+            .write_all(&client_msg("CLIENT_A", b"A", 1))
+            .await
+            .unwrap();
+        let result = handle(&server, stream).await;
+        let text = errs::already_logged_on();
+        assert!(matches!(result, Err(ConnectionError::Logon(t)) if t == text));
+
+        client
+            .write_all(&client_msg("CLIENT_A", b"5", 2))
+            .await
+            .unwrap();
+        read_message(&mut client).await.unwrap();
+        first.await.unwrap().unwrap();
+        assert!(!server.is_running(&key("CLIENT_A")));
+        let state = server.with_session(&key("CLIENT_A"), |conn| conn.state());
+        assert_eq!(state, Some(SessionState::Disconnected));
+    }
+
+    #[tokio::test]
+    async fn cancelled_session_is_put_back() {
+        let server = server();
+        let (stream, mut client) = tokio::io::duplex(4096);
+        client
+            .write_all(&client_msg("CLIENT_A", b"A", 1))
+            .await
+            .unwrap();
+        let handled = tokio::time::timeout(Duration::from_millis(200), handle(&server, stream));
+        let client = async {
+            read_message(&mut client).await.unwrap();
+            client
+        };
+        let (result, _client) = futures::join!(handled, client);
+        assert!(result.is_err());
+
+        assert!(!server.is_running(&key("CLIENT_A")));
+        let state = server.with_session(&key("CLIENT_A"), |conn| conn.state());
+        assert_eq!(state, Some(SessionState::Disconnected));
+    }
+
+    #[cfg(feature = "utils-tokio")]
+    #[tokio::test]
+    async fn serve_over_tcp() {
+        let server = server();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local_addr = listener.local_addr().unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.serve(listener).await }
+        });
+        let mut client = tokio::net::TcpStream::connect(local_addr).await.unwrap();
+        client
+            .write_all(&client_msg("CLIENT_A", b"A", 1))
+            .await
+            .unwrap();
+        client
+            .write_all(&client_msg("CLIENT_A", b"5", 2))
+            .await
+            .unwrap();
+        let mut replies = vec![];
+        client.read_to_end(&mut replies).await.unwrap();
+        let replies = String::from_utf8(replies).unwrap();
+        assert!(replies.contains("\x0135=A\x01"));
+        assert!(replies.contains("\x0135=5\x01"));
+    }
+}
@@ -0,0 +1,713 @@
+use super::{Backoff, Config, Role, Schedule, SessionKey};
+use crate::field_types::Tz;
+use crate::tagvalue::appl_ver_id;
+use crate::{Dictionary, FieldType};
+use chrono::{NaiveTime, Weekday};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The settings of a single FIX session, as read from a QuickFIX-style
+/// settings file by [`SessionSettings::load`].
+///
+/// Such files are made of a `[DEFAULT]` section, whose keys are shared by
+/// all FIX sessions, and one `[SESSION]` section per FIX session:
+///
+/// ```text
+/// [DEFAULT]
+/// ConnectionType=initiator
+/// HeartBtInt=30
+/// StartTime=08:00:00
+/// EndTime=17:00:00
+/// DataDictionary=FIX44.xml
+///
+/// [SESSION]
+/// BeginString=FIX.4.4
+/// SenderCompID=ME
+/// TargetCompID=VENUE
+/// SocketConnectHost=fix.venue.com
+/// SocketConnectPort=9876
+/// ```
+///
+/// Only a subset of QuickFIX keys is supported, and any other key is an
+/// error rather than being silently ignored:
+///
+/// - `ConnectionType` (required): either `initiator` or `acceptor`.
+/// - `BeginString`, `SenderCompID`, `TargetCompID` (required).
+/// - `HeartBtInt`, `LogoutTimeout`, `MaxLatency`, `ReconnectInterval`: in
+///   seconds.
+/// - `StartTime`, `EndTime` (`HH:MM:SS`), `StartDay`, `EndDay`, `Weekdays`,
+///   `TimeZone`, `NonStopSession`: see [`Schedule`]. `TimeZone` must be
+///   either `UTC` or a fixed offset such as `-05:00`.
+/// - `SocketConnectHost`, `SocketConnectPort`, and their numbered variants
+///   (e.g. `SocketConnectHost1`) for failover, in order.
+/// - `SocketAcceptAddress`, `SocketAcceptPort`.
+/// - `ResetOnLogon`, `EnableNextExpectedMsgSeqNum`: `Y` or `N`.
+/// - `DataDictionary`: the path of a QuickFIX XML specification file,
+///   relative to the settings file.
+/// - `TransportDataDictionary`, `AppDataDictionary`: like `DataDictionary`,
+///   but for FIXT.1.1 sessions. `TransportDataDictionary` takes the place of
+///   `DataDictionary`.
+/// - `DefaultApplVerID`: either a FIX version such as `FIX.5.0SP2` or an
+///   `ApplVerID <1128>` value such as `9`.
+///
+/// Lines starting with `#` are comments.
+#[derive(Debug, Clone)]
+pub struct SessionSettings {
+    /// The configuration options of the [`FixConnection`](super::FixConnection).
+    pub config: Config,
+    /// Whether the FIX session initiates the transport connection or accepts
+    /// it.
+    pub role: Role,
+    /// The addresses to connect to, in order of preference. Initiators only.
+    pub connect_endpoints: Vec<(String, u16)>,
+    /// The address to listen on. Acceptors only.
+    pub accept_endpoint: Option<(String, u16)>,
+    /// The [`Dictionary`] loaded from `DataDictionary` or
+    /// `TransportDataDictionary`, if any.
+    pub dictionary: Option<Dictionary>,
+    /// The [`Dictionary`] loaded from `AppDataDictionary`, if any.
+    pub app_dictionary: Option<Dictionary>,
+}
+
+/// The type returned in the event of an invalid QuickFIX-style settings file.
+/// Line numbers start from 1.
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    /// The settings file couldn't be read.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The line is neither a section header nor a `key=value` pair.
+    #[error("Line {line}: expected `[SECTION]` or `key=value`.")]
+    Syntax {
+        /// The line number.
+        line: usize,
+    },
+    /// The section header is neither `[DEFAULT]` nor `[SESSION]`.
+    #[error("Line {line}: unknown section `[{name}]`.")]
+    UnknownSection {
+        /// The line number.
+        line: usize,
+        /// The name of the section.
+        name: String,
+    },
+    /// The key is not supported.
+    #[error("Line {line}: unknown key `{key}`.")]
+    UnknownKey {
+        /// The line number.
+        line: usize,
+        /// The unknown key.
+        key: String,
+    },
+    /// The key appears more than once in the same section.
+    #[error("Line {line}: `{key}` is already set in this section.")]
+    DuplicateKey {
+        /// The line number of the second occurrence.
+        line: usize,
+        /// The repeated key.
+        key: String,
+    },
+    /// The value can't be parsed.
+    #[error("Line {line}: invalid value `{value}` for `{key}`.")]
+    InvalidValue {
+        /// The line number.
+        line: usize,
+        /// The key.
+        key: String,
+        /// The invalid value.
+        value: String,
+    },
+    /// The key can't be used together with another key of the same FIX
+    /// session.
+    #[error("Line {line}: `{key}` conflicts with `{other}`.")]
+    ConflictingKeys {
+        /// The line number.
+        line: usize,
+        /// The conflicting key.
+        key: String,
+        /// The key it conflicts with.
+        other: String,
+    },
+    /// A required key is missing from the FIX session.
+    #[error("Line {line}: `{key}` is required by this session.")]
+    MissingKey {
+        /// The line number of the `[SESSION]` header.
+        line: usize,
+        /// The missing key.
+        key: String,
+    },
+    /// Another FIX session has the same BeginString and CompIDs.
+    #[error("Line {line}: session `{session}` is defined twice.")]
+    DuplicateSession {
+        /// The line number of the second `[SESSION]` header.
+        line: usize,
+        /// The BeginString and CompIDs of the FIX session.
+        session: String,
+    },
+    /// The `DataDictionary` file couldn't be read or parsed.
+    #[error("Line {line}: can't load `{}`: {reason}", path.display())]
+    Dictionary {
+        /// The line number.
+        line: usize,
+        /// The path of the dictionary file.
+        path: PathBuf,
+        /// What went wrong.
+        reason: String,
+    },
+}
+
+const KEYS: &[&str] = &[
+    "ConnectionType",
+    "BeginString",
+    "SenderCompID",
+    "TargetCompID",
+    "HeartBtInt",
+    "LogoutTimeout",
+    "MaxLatency",
+    "ReconnectInterval",
+    "StartTime",
+    "EndTime",
+    "StartDay",
+    "EndDay",
+    "Weekdays",
+    "TimeZone",
+    "NonStopSession",
+    "SocketAcceptAddress",
+    "SocketAcceptPort",
+    "ResetOnLogon",
+    "EnableNextExpectedMsgSeqNum",
+    "DataDictionary",
+    "TransportDataDictionary",
+    "AppDataDictionary",
+    "DefaultApplVerID",
+];
+
+const INITIATOR_KEYS: &[&str] = &[
+    "ReconnectInterval",
+    "SocketConnectHost",
+    "SocketConnectPort",
+];
+const ACCEPTOR_KEYS: &[&str] = &["SocketAcceptAddress", "SocketAcceptPort"];
+
+impl SessionSettings {
+    /// Reads all FIX sessions from the QuickFIX-style settings file at
+    /// `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>, SettingsError> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path)?;
+        Self::parse(&input, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Parses all FIX sessions from the contents of a QuickFIX-style settings
+    /// file. `DataDictionary` paths are relative to `dir`.
+    pub fn parse(input: &str, dir: &Path) -> Result<Vec<Self>, SettingsError> {
+        let mut defaults = Section::default();
+        let mut sessions: Vec<Section> = vec![];
+        let mut current: Option<&mut Section> = None;
+        for (i, line) in input.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = if name.eq_ignore_ascii_case("DEFAULT") {
+                    Some(&mut defaults)
+                } else if name.eq_ignore_ascii_case("SESSION") {
+                    sessions.push(Section::new(line_number));
+                    sessions.last_mut()
+                } else {
+                    return Err(SettingsError::UnknownSection {
+                        line: line_number,
+                        name: name.to_string(),
+                    });
+                };
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(SettingsError::Syntax { line: line_number })?;
+            let section = current
+                .as_deref_mut()
+                .ok_or(SettingsError::Syntax { line: line_number })?;
+            section.insert(key.trim(), value.trim(), line_number)?;
+        }
+
+        let mut dictionaries = HashMap::new();
+        let mut keys = HashSet::new();
+        let mut settings = vec![];
+        for session in sessions {
+            let session = Merged {
+                section: &session,
+                defaults: &defaults,
+            };
+            let s = Self::from_section(session, dir, &mut dictionaries)?;
+            let key = SessionKey::of_config(&s.config);
+            if !keys.insert(key) {
+                return Err(SettingsError::DuplicateSession {
+                    line: session.section.line,
+                    session: format!(
+                        "{}:{}->{}",
+                        s.config.begin_string, s.config.sender_comp_id, s.config.target_comp_id
+                    ),
+                });
+            }
+            settings.push(s);
+        }
+        Ok(settings)
+    }
+
+    fn from_section(
+        session: Merged,
+        dir: &Path,
+        dictionaries: &mut HashMap<PathBuf, Dictionary>,
+    ) -> Result<Self, SettingsError> {
+        let role = match session.required("ConnectionType")? {
+            ("initiator", _) => Role::Initiator,
+            ("acceptor", _) => Role::Acceptor,
+            (value, line) => return Err(invalid_value("ConnectionType", value, line)),
+        };
+        // Keys of the other role are only allowed in `[DEFAULT]`, which may
+        // be shared by initiators and acceptors.
+        let other_keys = match role {
+            Role::Initiator => ACCEPTOR_KEYS,
+            Role::Acceptor => INITIATOR_KEYS,
+        };
+        for (key, &(_, line)) in session.section.entries.iter() {
+            if other_keys.iter().any(|k| key.starts_with(k)) {
+                return Err(SettingsError::ConflictingKeys {
+                    line,
+                    key: key.clone(),
+                    other: "ConnectionType".to_string(),
+                });
+            }
+        }
+
+        let mut config = Config::default();
+        config.begin_string = session.required("BeginString")?.0.to_string();
+        config.sender_comp_id = session.required("SenderCompID")?.0.to_string();
+        config.target_comp_id = session.required("TargetCompID")?.0.to_string();
+        if let Some(heartbeat) = session.parse("HeartBtInt", seconds)? {
+            config.heartbeat = heartbeat;
+        }
+        if let Some(logout_timeout) = session.parse("LogoutTimeout", seconds)? {
+            config.logout_timeout = logout_timeout;
+        }
+        if let Some(max_latency) = session.parse("MaxLatency", seconds)? {
+            config.max_allowed_latency = max_latency;
+        }
+        config.schedule = session.schedule()?;
+        if let Some(reset_on_logon) = session.parse("ResetOnLogon", yes_no)? {
+            config.reset_on_logon = reset_on_logon;
+        }
+        if let Some(enable) = session.parse("EnableNextExpectedMsgSeqNum", yes_no)? {
+            config.enable_next_expected_msg_seq_num = enable;
+        }
+        config.default_appl_ver_id = session.parse("DefaultApplVerID", default_appl_ver_id)?;
+
+        let mut settings = Self {
+            config,
+            role,
+            connect_endpoints: vec![],
+            accept_endpoint: None,
+            dictionary: None,
+            app_dictionary: None,
+        };
+        match role {
+            Role::Initiator => {
+                if let Some(interval) = session.parse("ReconnectInterval", seconds)? {
+                    settings.config.backoff = Backoff {
+                        initial: interval,
+                        max: interval,
+                        multiplier: 1,
+                        ..Backoff::default()
+                    };
+                }
+                settings.connect_endpoints = session.connect_endpoints()?;
+            }
+            Role::Acceptor => {
+                if let Some(port) = session.parse("SocketAcceptPort", |s| s.parse().ok())? {
+                    let address = session.get("SocketAcceptAddress").map(|(a, _)| a);
+                    let address = address.unwrap_or("0.0.0.0").to_string();
+                    settings.accept_endpoint = Some((address, port));
+                }
+            }
+        }
+        if let (Some(_), Some((_, line))) = (
+            session.get("DataDictionary"),
+            session.get("TransportDataDictionary"),
+        ) {
+            return Err(session.conflict(line, "TransportDataDictionary", "DataDictionary"));
+        }
+        let mut load = |key| -> Result<Option<Dictionary>, SettingsError> {
+            let (path, line) = match session.get(key) {
+                Some(entry) => entry,
+                None => return Ok(None),
+            };
+            let path = dir.join(path);
+            if let Some(dictionary) = dictionaries.get(&path) {
+                return Ok(Some(dictionary.clone()));
+            }
+            let dictionary = load_dictionary(&path, line)?;
+            dictionaries.insert(path, dictionary.clone());
+            Ok(Some(dictionary))
+        };
+        settings.dictionary = match load("DataDictionary")? {
+            Some(dictionary) => Some(dictionary),
+            None => load("TransportDataDictionary")?,
+        };
+        settings.app_dictionary = load("AppDataDictionary")?;
+        Ok(settings)
+    }
+}
+
+/// The `key=value` pairs of a section, with their line numbers.
+#[derive(Debug, Default)]
+struct Section {
+    line: usize,
+    entries: HashMap<String, (String, usize)>,
+}
+
+impl Section {
+    fn new(line: usize) -> Self {
+        Self {
+            line,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, key: &str, value: &str, line: usize) -> Result<(), SettingsError> {
+        let is_endpoint_key = ["SocketConnectHost", "SocketConnectPort"]
+            .iter()
+            .filter_map(|prefix| key.strip_prefix(prefix))
+            .any(|n| n.bytes().all(|b| b.is_ascii_digit()));
+        if !is_endpoint_key && !KEYS.contains(&key) {
+            return Err(SettingsError::UnknownKey {
+                line,
+                key: key.to_string(),
+            });
+        }
+        if self.entries.contains_key(key) {
+            return Err(SettingsError::DuplicateKey {
+                line,
+                key: key.to_string(),
+            });
+        }
+        self.entries
+            .insert(key.to_string(), (value.to_string(), line));
+        Ok(())
+    }
+}
+
+/// A `[SESSION]` section, on top of `[DEFAULT]`.
+#[derive(Debug, Copy, Clone)]
+struct Merged<'a> {
+    section: &'a Section,
+    defaults: &'a Section,
+}
+
+impl<'a> Merged<'a> {
+    fn get(&self, key: &str) -> Option<(&'a str, usize)> {
+        self.section
+            .entries
+            .get(key)
+            .or_else(|| self.defaults.entries.get(key))
+            .map(|(value, line)| (value.as_str(), *line))
+    }
+
+    fn required(&self, key: &str) -> Result<(&'a str, usize), SettingsError> {
+        self.get(key).ok_or_else(|| self.missing(key))
+    }
+
+    fn missing(&self, key: &str) -> SettingsError {
+        SettingsError::MissingKey {
+            line: self.section.line,
+            key: key.to_string(),
+        }
+    }
+
+    fn parse<T>(
+        &self,
+        key: &str,
+        f: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, SettingsError> {
+        self.get(key)
+            .map(|(value, line)| f(value).ok_or_else(|| invalid_value(key, value, line)))
+            .transpose()
+    }
+
+    fn schedule(&self) -> Result<Option<Schedule>, SettingsError> {
+        let start_time = self.parse("StartTime", time)?;
+        let end_time = self.parse("EndTime", time)?;
+        if self.parse("NonStopSession", yes_no)? == Some(true) {
+            if let Some((_, line)) = self.get("StartTime").or_else(|| self.get("EndTime")) {
+                return Err(self.conflict(line, "StartTime", "NonStopSession"));
+            }
+            return Ok(None);
+        }
+        let (start_time, end_time) = match (start_time, end_time) {
+            (None, None) => return Ok(None),
+            (Some(_), None) => return Err(self.missing("EndTime")),
+            (None, Some(_)) => return Err(self.missing("StartTime")),
+            (Some(start), Some(end)) => (start, end),
+        };
+        let tz = self.parse("TimeZone", tz)?.unwrap_or(Tz::UTC);
+        let start_day = self.parse("StartDay", weekday)?;
+        let end_day = self.parse("EndDay", weekday)?;
+        let weekdays = self.parse("Weekdays", |s| {
+            s.split(',').map(weekday).collect::<Option<Vec<_>>>()
+        })?;
+        match (start_day, end_day, weekdays) {
+            (None, None, weekdays) => Ok(Some(
+                Schedule::daily(start_time, end_time, tz).on_days(weekdays.unwrap_or_default()),
+            )),
+            (_, _, Some(_)) => {
+                let (_, line) = self.required("Weekdays")?;
+                Err(self.conflict(line, "Weekdays", "StartDay"))
+            }
+            (Some(start_day), Some(end_day), None) => Ok(Some(Schedule::weekly(
+                start_day, start_time, end_day, end_time, tz,
+            ))),
+            (Some(_), None, None) => Err(self.missing("EndDay")),
+            (None, Some(_), None) => Err(self.missing("StartDay")),
+        }
+    }
+
+    /// Returns `SocketConnectHost` and `SocketConnectPort`, followed by
+    /// `SocketConnectHost1` and `SocketConnectPort1`, and so on.
+    fn connect_endpoints(&self) -> Result<Vec<(String, u16)>, SettingsError> {
+        let mut endpoints = vec![];
+        for n in 0.. {
+            let suffix = if n == 0 {
+                String::new()
+            } else {
+                format!("{}", n)
+            };
+            let host_key = format!("SocketConnectHost{}", suffix);
+            let port_key = format!("SocketConnectPort{}", suffix);
+            let port = self.parse(&port_key, |s| s.parse::<u16>().ok())?;
+            match (self.get(&host_key), port) {
+                (None, None) => break,
+                (Some((host, _)), Some(port)) => endpoints.push((host.to_string(), port)),
+                (Some(_), None) => return Err(self.missing(&port_key)),
+                (None, Some(_)) => return Err(self.missing(&host_key)),
+            }
+        }
+        Ok(endpoints)
+    }
+
+    fn conflict(&self, line: usize, key: &str, other: &str) -> SettingsError {
+        SettingsError::ConflictingKeys {
+            line,
+            key: key.to_string(),
+            other: other.to_string(),
+        }
+    }
+}
+
+fn invalid_value(key: &str, value: &str, line: usize) -> SettingsError {
+    SettingsError::InvalidValue {
+        line,
+        key: key.to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn load_dictionary(path: &Path, line: usize) -> Result<Dictionary, SettingsError> {
+    let error = |reason: String| SettingsError::Dictionary {
+        line,
+        path: path.to_path_buf(),
+        reason,
+    };
+    let spec = std::fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+    Dictionary::from_quickfix_spec(&spec).map_err(|err| error(format!("{:?}", err)))
+}
+
+fn seconds(s: &str) -> Option<Duration> {
+    s.parse().ok().map(Duration::from_secs)
+}
+
+fn default_appl_ver_id(s: &str) -> Option<String> {
+    match appl_ver_id(s) {
+        Some(id) => Some(id.to_string()),
+        None if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => Some(s.to_string()),
+        None => None,
+    }
+}
+
+fn yes_no(s: &str) -> Option<bool> {
+    match s {
+        "Y" => Some(true),
+        "N" => Some(false),
+        _ => None,
+    }
+}
+
+fn time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S").ok()
+}
+
+fn weekday(s: &str) -> Option<Weekday> {
+    s.trim().parse().ok()
+}
+
+fn tz(s: &str) -> Option<Tz> {
+    match s {
+        "UTC" | "GMT" => Some(Tz::UTC),
+        _ => Tz::deserialize(s.as_bytes()).ok(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    const SETTINGS: &str = "
+# Shared by all sessions.
+[DEFAULT]
+ConnectionType=initiator
+HeartBtInt=20
+StartTime=08:00:00
+EndTime=17:00:00
+TimeZone=-05:00
+SocketAcceptPort=9000
+
+[SESSION]
+BeginString=FIX.4.4
+SenderCompID=ME
+TargetCompID=VENUE_A
+SocketConnectHost=primary.venue-a.com
+SocketConnectPort=9876
+SocketConnectHost1=backup.venue-a.com
+SocketConnectPort1=9877
+ResetOnLogon=Y
+
+[SESSION]
+ConnectionType=acceptor
+BeginString=FIX.4.2
+SenderCompID=ME
+TargetCompID=CLIENT
+HeartBtInt=60
+StartDay=Sunday
+EndDay=Friday
+";
+
+    fn parse(input: &str) -> Result<Vec<SessionSettings>, SettingsError> {
+        SessionSettings::parse(input, Path::new(""))
+    }
+
+    #[test]
+    fn sessions_inherit_defaults() {
+        let sessions = parse(SETTINGS).unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        let venue = &sessions[0];
+        assert_eq!(venue.role, Role::Initiator);
+        assert_eq!(venue.config.target_comp_id, "VENUE_A");
+        assert_eq!(venue.config.heartbeat, Duration::from_secs(20));
+        assert_eq!(
+            venue.connect_endpoints,
+            vec![
+                ("primary.venue-a.com".to_string(), 9876),
+                ("backup.venue-a.com".to_string(), 9877)
+            ]
+        );
+        assert_eq!(venue.accept_endpoint, None);
+        assert!(venue.config.reset_on_logon);
+        let schedule = venue.config.schedule.as_ref().unwrap();
+        // 13:00 in New York.
+        assert!(schedule.is_active(Utc.with_ymd_and_hms(2024, 1, 10, 18, 0, 0).unwrap()));
+
+        let client = &sessions[1];
+        assert_eq!(client.role, Role::Acceptor);
+        assert_eq!(client.config.begin_string, "FIX.4.2");
+        assert_eq!(client.config.heartbeat, Duration::from_secs(60));
+        assert_eq!(client.accept_endpoint, Some(("0.0.0.0".to_string(), 9000)));
+        assert!(!client.config.reset_on_logon);
+        let schedule = client.config.schedule.as_ref().unwrap();
+        assert_eq!(
+            schedule.days(),
+            &crate::session::ScheduleDays::Weekly {
+                start: Weekday::Sun,
+                end: Weekday::Fri
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_and_conflicting_keys_are_errors() {
+        let input = format!("{}FileStorePath=store\n", SETTINGS);
+        assert!(matches!(
+            parse(&input),
+            Err(SettingsError::UnknownKey { line: 29, key }) if key == "FileStorePath"
+        ));
+        let input = format!("{}Weekdays=Mon,Tue\n", SETTINGS);
+        assert!(matches!(
+            parse(&input),
+            Err(SettingsError::ConflictingKeys { line: 29, .. })
+        ));
+        let input = format!("{}SocketConnectPort=1234\n", SETTINGS);
+        assert!(matches!(
+            parse(&input),
+            Err(SettingsError::ConflictingKeys { line: 29, key, .. }) if key == "SocketConnectPort"
+        ));
+        let input = format!("{}HeartBtInt=10\n", SETTINGS);
+        assert!(matches!(
+            parse(&input),
+            Err(SettingsError::DuplicateKey { line: 29, .. })
+        ));
+        let input = SETTINGS
+            .replace("FIX.4.2", "FIX.4.4")
+            .replace("CLIENT", "VENUE_A");
+        assert!(matches!(
+            parse(&input),
+            Err(SettingsError::DuplicateSession { line: 21, .. })
+        ));
+        let input = SETTINGS.replace("HeartBtInt=20", "HeartBtInt=twenty");
+        assert!(matches!(
+            parse(&input),
+            Err(SettingsError::InvalidValue { line: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn data_dictionary_is_relative_to_the_settings_file() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fefix-dictionary/src/resources/quickfix");
+        let input = format!("{}DataDictionary=FIX-4.2.xml\n", SETTINGS);
+        let sessions = SessionSettings::parse(&input, &dir).unwrap();
+        assert!(sessions[0].dictionary.is_none());
+        let dictionary = sessions[1].dictionary.as_ref().unwrap();
+        assert_eq!(dictionary.version(), "FIX.4.2");
+
+        let input = format!("{}DataDictionary=FIX-9.9.xml\n", SETTINGS);
+        let result = SessionSettings::parse(&input, &dir);
+        assert!(matches!(
+            result,
+            Err(SettingsError::Dictionary { line: 29, .. })
+        ));
+    }
+
+    #[test]
+    fn fixt_sessions_have_two_dictionaries() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../fefix-dictionary/src/resources/quickfix");
+        let input = format!(
+            "{}BeginString=FIXT.1.1\nTransportDataDictionary=FIXT-1.1.xml\n\
+             AppDataDictionary=FIX-5.0-SP2.xml\nDefaultApplVerID=FIX.5.0SP2\n",
+            SETTINGS.replace("BeginString=FIX.4.2\n", "")
+        );
+        let sessions = SessionSettings::parse(&input, &dir).unwrap();
+        let client = &sessions[1];
+        assert_eq!(client.config.default_appl_ver_id.as_deref(), Some("9"));
+        assert_eq!(client.dictionary.as_ref().unwrap().version(), "FIXT.1.1");
+        let app_dictionary = client.app_dictionary.as_ref().unwrap();
+        assert_eq!(app_dictionary.version(), "FIX.5.0-SP2");
+
+        let input = format!("{}DataDictionary=FIX-4.2.xml\n", input);
+        let result = SessionSettings::parse(&input, &dir);
+        assert!(matches!(result, Err(SettingsError::ConflictingKeys { .. })));
+    }
+}
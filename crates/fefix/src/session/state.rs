@@ -0,0 +1,67 @@
+/// The state of a FIX session, as seen by one of the two counterparties.
+///
+/// Every transition is reported to
+/// [`Backend::on_state_change`](super::Backend::on_state_change).
+///
+/// ```text
+///                  ┌──────────────┐
+///     ┌───────────►│ Disconnected │◄──────────────────────┐
+///     │            └──────────────┘                       │
+///     │           initiator │     │ acceptor              │
+///     │                     ▼     ▼                       │
+///     │        ┌───────────┐       ┌───────────────┐      │
+///     │        │ LogonSent │       │ LogonReceived │      │
+///     │        └───────────┘       └───────────────┘      │
+///     │                     │     │                       │
+///     │                     ▼     ▼                       │
+///     │  ┌──────────────────┐     ┌────────┐              │
+///     │  │ ResendInProgress │◄───►│ Active │              │
+///     │  └──────────────────┘     └────────┘              │
+///     │                          │          │             │
+///     │                          ▼          ▼             │
+///     │              ┌────────────┐    ┌────────────────┐ │
+///     └──────────────┤ LogoutSent │    │ LogoutReceived ├─┘
+///                    └────────────┘    └────────────────┘
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SessionState {
+    /// There's no transport connection with the counterparty.
+    #[default]
+    Disconnected,
+    /// We're the initiator and we've sent our `Logon <A>`, but the acceptor
+    /// hasn't replied yet.
+    LogonSent,
+    /// We're the acceptor and we've received the initiator's `Logon <A>`,
+    /// which is being validated.
+    LogonReceived,
+    /// Both counterparties are logged on and seq. numbers are in sync.
+    /// Application messages can be sent.
+    Active,
+    /// Both counterparties are logged on, but we've detected a seq. number gap
+    /// and we're waiting for the counterparty to fill it. Inbound messages are
+    /// queued in the meantime.
+    ResendInProgress,
+    /// We've sent a `Logout <5>` and we're waiting for the transport to be
+    /// closed.
+    LogoutSent,
+    /// The counterparty has sent a `Logout <5>`, which we're replying to.
+    LogoutReceived,
+}
+
+impl SessionState {
+    /// Returns `true` if and only if both counterparties are logged on, i.e.
+    /// `self` is either [`SessionState::Active`] or
+    /// [`SessionState::ResendInProgress`].
+    ///
+    /// ```
+    /// use fefix::session::SessionState;
+    ///
+    /// assert!(SessionState::Active.is_logged_on());
+    /// assert!(SessionState::ResendInProgress.is_logged_on());
+    /// assert!(!SessionState::LogonSent.is_logged_on());
+    /// ```
+    pub fn is_logged_on(&self) -> bool {
+        matches!(self, Self::Active | Self::ResendInProgress)
+    }
+}
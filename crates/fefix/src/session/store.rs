@@ -0,0 +1,583 @@
+use super::SeqNumbers;
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Storage for the state of a FIX session that must outlive a single
+/// connection: outbound messages, which might have to be resent, and seq.
+/// numbers.
+pub trait MessageStore {
+    /// The type of errors that can arise when reading from and writing to the
+    /// store. A failure makes [`FixConnection`](super::FixConnection) close
+    /// the transport with
+    /// [`ConnectionError::Store`](super::ConnectionError::Store).
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Stores the outbound `message` with `MsgSeqNum <34>` equal to
+    /// `msg_seq_num`, replacing any previous message with the same seq.
+    /// number.
+    fn store_outbound(&mut self, msg_seq_num: u64, message: &[u8]) -> Result<(), Self::Error>;
+
+    /// Returns all stored outbound messages with a seq. number within `range`,
+    /// in ascending order. Seq. numbers without a stored message are skipped.
+    fn fetch_outbound(&mut self, range: Range<u64>) -> Result<Vec<(u64, Vec<u8>)>, Self::Error>;
+
+    /// Returns the last persisted [`SeqNumbers`].
+    fn seq_numbers(&self) -> SeqNumbers;
+
+    /// Persists `seq_numbers`.
+    fn set_seq_numbers(&mut self, seq_numbers: SeqNumbers) -> Result<(), Self::Error>;
+
+    /// Returns the instant at which the FIX session was created, i.e. the
+    /// last time `self` was reset.
+    fn creation_time(&self) -> DateTime<Utc>;
+
+    /// Starts a new FIX session: discards all stored messages, sets both seq.
+    /// numbers back to 1 and updates the creation time.
+    fn reset(&mut self) -> Result<(), Self::Error>;
+}
+
+/// A [`MessageStore`] which keeps everything in memory. All state is lost on
+/// process exit.
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    messages: BTreeMap<u64, Vec<u8>>,
+    seq_numbers: SeqNumbers,
+    creation_time: DateTime<Utc>,
+}
+
+impl MemoryStore {
+    /// Creates an empty [`MemoryStore`] for a new FIX session.
+    pub fn new() -> Self {
+        Self {
+            messages: BTreeMap::new(),
+            seq_numbers: SeqNumbers::default(),
+            creation_time: Utc::now(),
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageStore for MemoryStore {
+    type Error = Infallible;
+
+    fn store_outbound(&mut self, msg_seq_num: u64, message: &[u8]) -> Result<(), Self::Error> {
+        self.messages.insert(msg_seq_num, message.to_vec());
+        Ok(())
+    }
+
+    fn fetch_outbound(&mut self, range: Range<u64>) -> Result<Vec<(u64, Vec<u8>)>, Self::Error> {
+        Ok(self
+            .messages
+            .range(range)
+            .map(|(msg_seq_num, msg)| (*msg_seq_num, msg.clone()))
+            .collect())
+    }
+
+    fn seq_numbers(&self) -> SeqNumbers {
+        self.seq_numbers
+    }
+
+    fn set_seq_numbers(&mut self, seq_numbers: SeqNumbers) -> Result<(), Self::Error> {
+        self.seq_numbers = seq_numbers;
+        Ok(())
+    }
+
+    fn creation_time(&self) -> DateTime<Utc> {
+        self.creation_time
+    }
+
+    fn reset(&mut self) -> Result<(), Self::Error> {
+        *self = Self::new();
+        Ok(())
+    }
+}
+
+/// A [`MessageStore`] backed by a single append-only journal file.
+///
+/// Every change is appended to the journal and flushed to disk with
+/// [`File::sync_data`] as soon as it happens, so the state of the FIX session
+/// survives process crashes and restarts: just [`FileStore::open`] the same
+/// path again. That's at least one disk flush per message in either
+/// direction, since each of them changes the seq. numbers; this bounds the
+/// throughput of the FIX session to that of the disk. Messages are read back
+/// from disk on demand; only their offsets are kept in memory.
+///
+/// Records which have been superseded, e.g. old seq. numbers, are dropped by
+/// [`FileStore::compact`]. This happens automatically once they take up more
+/// than 1 MiB and more than half of the journal. Outbound messages are only
+/// dropped on [`MessageStore::reset`], so the journal keeps growing with them
+/// until the end of the FIX session.
+///
+/// The journal is a sequence of records, each starting with an ASCII header
+/// line:
+///
+/// - `C <millis>`: the creation time, as milliseconds since the UNIX epoch.
+/// - `S <next inbound> <next outbound>`: seq. numbers.
+/// - `M <seq. number> <length>`: an outbound message, followed by `<length>`
+///   raw bytes and a newline.
+///
+/// A truncated last record, e.g. due to a crash in the middle of a write, is
+/// discarded when opening the journal. A record is only considered truncated
+/// if no other record follows it. Any other malformed content is reported as
+/// an [`io::ErrorKind::InvalidData`] error and left untouched.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    file: File,
+    offsets: BTreeMap<u64, (u64, usize)>,
+    seq_numbers: SeqNumbers,
+    creation_time: DateTime<Utc>,
+    len: u64,
+    /// The length of the last `S` record, which is garbage once superseded.
+    seq_numbers_len: u64,
+    /// The total length of all superseded records.
+    garbage: u64,
+    compaction_threshold: u64,
+}
+
+impl FileStore {
+    /// Opens the journal at `path`, creating it if it doesn't exist yet.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if `path` isn't a journal or
+    /// is corrupt, rather than overwriting it.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut journal = Vec::new();
+        file.read_to_end(&mut journal)?;
+        let mut store = Self {
+            path,
+            file,
+            offsets: BTreeMap::new(),
+            seq_numbers: SeqNumbers::default(),
+            creation_time: Utc::now(),
+            len: 0,
+            seq_numbers_len: 0,
+            garbage: 0,
+            compaction_threshold: COMPACTION_THRESHOLD,
+        };
+        if journal.is_empty() {
+            store.reset()?;
+        } else {
+            let valid_len = store.load(&journal)?;
+            if valid_len < journal.len() {
+                store.file.set_len(valid_len as u64)?;
+                store.file.sync_data()?;
+            }
+            store.len = valid_len as u64;
+        }
+        Ok(store)
+    }
+
+    /// Returns the path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Replays all records in `journal` and returns the length in bytes of the
+    /// well-formed ones, which is less than `journal.len()` only if the last
+    /// record is truncated.
+    fn load(&mut self, journal: &[u8]) -> io::Result<usize> {
+        let invalid = |offset: usize| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid journal record at byte {}", offset),
+            )
+        };
+        let mut valid_len = 0;
+        let mut has_creation_time = false;
+        while let Some(newline) = journal[valid_len..].iter().position(|b| *b == b'\n') {
+            let header_end = valid_len + newline + 1;
+            let header = std::str::from_utf8(&journal[valid_len..header_end - 1])
+                .map_err(|_| invalid(valid_len))?;
+            let mut words = header.split(' ');
+            let kind = words.next();
+            let numbers: Option<Vec<u64>> = words.map(|w| w.parse().ok()).collect();
+            match (kind, numbers.as_deref()) {
+                (Some("C"), Some(&[millis])) => match Utc.timestamp_millis_opt(millis as i64) {
+                    chrono::LocalResult::Single(creation_time) => {
+                        self.creation_time = creation_time;
+                        has_creation_time = true;
+                        valid_len = header_end;
+                    }
+                    _ => return Err(invalid(valid_len)),
+                },
+                (Some("S"), Some(&[inbound, outbound])) if has_creation_time => {
+                    self.seq_numbers = SeqNumbers {
+                        next_inbound: inbound,
+                        next_outbound: outbound,
+                    };
+                    self.garbage += self.seq_numbers_len;
+                    self.seq_numbers_len = (header_end - valid_len) as u64;
+                    valid_len = header_end;
+                }
+                (Some("M"), Some(&[msg_seq_num, len])) if has_creation_time => {
+                    let record_end = usize::try_from(len)
+                        .ok()
+                        .and_then(|len| header_end.checked_add(len)?.checked_add(1))
+                        .ok_or_else(|| invalid(valid_len))?;
+                    if journal.len() < record_end {
+                        if has_record_boundary(&journal[header_end..]) {
+                            // The length is wrong, not the end of the
+                            // journal.
+                            return Err(invalid(valid_len));
+                        }
+                        break;
+                    } else if journal[record_end - 1] != b'\n' {
+                        return Err(invalid(valid_len));
+                    }
+                    let old = self
+                        .offsets
+                        .insert(msg_seq_num, (header_end as u64, len as usize));
+                    if old.is_some() {
+                        self.garbage += (record_end - valid_len) as u64;
+                    }
+                    valid_len = record_end;
+                }
+                _ => return Err(invalid(valid_len)),
+            }
+        }
+        if has_creation_time {
+            Ok(valid_len)
+        } else {
+            Err(invalid(0))
+        }
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<u64> {
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(record)?;
+        self.file.sync_data()?;
+        self.len = offset + record.len() as u64;
+        Ok(offset)
+    }
+
+    /// Rewrites the journal with only the records that are still relevant,
+    /// i.e. the creation time, the current seq. numbers and the last version
+    /// of each outbound message.
+    ///
+    /// The new journal is written next to the old one and then renamed over
+    /// it, so a crash in the middle leaves the old journal untouched.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".compact");
+        let mut journal = BufWriter::new(File::create(&tmp_path)?);
+        let seq_numbers = format!(
+            "S {} {}\n",
+            self.seq_numbers.next_inbound, self.seq_numbers.next_outbound
+        );
+        let millis = self.creation_time.timestamp_millis();
+        let mut header = format!("C {}\n{}", millis, seq_numbers);
+        let mut len = 0;
+        let mut offsets = BTreeMap::new();
+        let mut message = Vec::new();
+        for (msg_seq_num, (offset, message_len)) in &self.offsets {
+            message.resize(*message_len, 0);
+            self.file.seek(SeekFrom::Start(*offset))?;
+            self.file.read_exact(&mut message)?;
+            header += &format!("M {} {}\n", msg_seq_num, message_len);
+            journal.write_all(header.as_bytes())?;
+            len += header.len() as u64;
+            offsets.insert(*msg_seq_num, (len, *message_len));
+            journal.write_all(&message)?;
+            journal.write_all(b"\n")?;
+            len += *message_len as u64 + 1;
+            header.clear();
+        }
+        journal.write_all(header.as_bytes())?;
+        len += header.len() as u64;
+        journal.into_inner()?.sync_all()?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        self.file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.offsets = offsets;
+        self.len = len;
+        self.seq_numbers_len = seq_numbers.len() as u64;
+        self.garbage = 0;
+        Ok(())
+    }
+
+    /// Compacts the journal if it's large and mostly made of superseded
+    /// records.
+    fn compact_if_needed(&mut self) -> io::Result<()> {
+        if self.garbage > self.compaction_threshold.max(self.len / 2) {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+/// See [`FileStore::compact`].
+const COMPACTION_THRESHOLD: u64 = 1 << 20;
+
+/// Returns the length in bytes of the journal record of an outbound message.
+fn message_record_len(msg_seq_num: u64, len: usize) -> u64 {
+    (format!("M {} {}\n", msg_seq_num, len).len() + len + 1) as u64
+}
+
+/// Returns `true` if `bytes` contain the header line of a journal record
+/// right after a newline.
+fn has_record_boundary(bytes: &[u8]) -> bool {
+    bytes
+        .split(|b| *b == b'\n')
+        .skip(1)
+        .any(|line| match std::str::from_utf8(line) {
+            Ok(line) => {
+                let mut words = line.split(' ');
+                let arity = match words.next() {
+                    Some("C") => 1,
+                    Some("S") | Some("M") => 2,
+                    _ => return false,
+                };
+                let numbers: Option<Vec<u64>> = words.map(|w| w.parse().ok()).collect();
+                numbers.is_some_and(|numbers| numbers.len() == arity)
+            }
+            Err(_) => false,
+        })
+}
+
+impl MessageStore for FileStore {
+    type Error = io::Error;
+
+    fn store_outbound(&mut self, msg_seq_num: u64, message: &[u8]) -> Result<(), Self::Error> {
+        let mut record = format!("M {} {}\n", msg_seq_num, message.len()).into_bytes();
+        let header_len = record.len() as u64;
+        record.extend_from_slice(message);
+        record.push(b'\n');
+        let offset = self.append(&record)?;
+        let old = self
+            .offsets
+            .insert(msg_seq_num, (offset + header_len, message.len()));
+        if let Some((_, len)) = old {
+            self.garbage += message_record_len(msg_seq_num, len);
+        }
+        self.compact_if_needed()
+    }
+
+    fn fetch_outbound(&mut self, range: Range<u64>) -> Result<Vec<(u64, Vec<u8>)>, Self::Error> {
+        let mut messages = Vec::new();
+        for (msg_seq_num, (offset, len)) in self.offsets.range(range) {
+            let mut message = vec![0; *len];
+            self.file.seek(SeekFrom::Start(*offset))?;
+            self.file.read_exact(&mut message)?;
+            messages.push((*msg_seq_num, message));
+        }
+        Ok(messages)
+    }
+
+    fn seq_numbers(&self) -> SeqNumbers {
+        self.seq_numbers
+    }
+
+    fn set_seq_numbers(&mut self, seq_numbers: SeqNumbers) -> Result<(), Self::Error> {
+        let record = format!(
+            "S {} {}\n",
+            seq_numbers.next_inbound, seq_numbers.next_outbound
+        );
+        self.append(record.as_bytes())?;
+        self.seq_numbers = seq_numbers;
+        self.garbage += self.seq_numbers_len;
+        self.seq_numbers_len = record.len() as u64;
+        self.compact_if_needed()
+    }
+
+    fn creation_time(&self) -> DateTime<Utc> {
+        self.creation_time
+    }
+
+    fn reset(&mut self) -> Result<(), Self::Error> {
+        let creation_time = Utc::now();
+        self.file.set_len(0)?;
+        self.append(format!("C {}\n", creation_time.timestamp_millis()).as_bytes())?;
+        self.offsets.clear();
+        self.seq_numbers = SeqNumbers::default();
+        self.creation_time = creation_time;
+        self.seq_numbers_len = 0;
+        self.garbage = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("fefix-store-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn seq_numbers(inbound: u64, outbound: u64) -> SeqNumbers {
+        SeqNumbers {
+            next_inbound: inbound,
+            next_outbound: outbound,
+        }
+    }
+
+    fn exercise(store: &mut impl MessageStore<Error = impl std::fmt::Debug>) {
+        store.store_outbound(1, b"foo").unwrap();
+        store.store_outbound(2, b"bar\n").unwrap();
+        store.store_outbound(4, b"").unwrap();
+        store.set_seq_numbers(seq_numbers(3, 5)).unwrap();
+        assert_eq!(
+            store.fetch_outbound(2..10).unwrap(),
+            vec![(2, b"bar\n".to_vec()), (4, vec![])]
+        );
+        assert_eq!(store.seq_numbers().next_inbound(), 3);
+        assert_eq!(store.seq_numbers().next_outbound(), 5);
+    }
+
+    #[test]
+    fn memory_store_reset() {
+        let store = &mut MemoryStore::new();
+        exercise(store);
+        let creation_time = store.creation_time();
+        store.reset().unwrap();
+        assert!(store.fetch_outbound(1..10).unwrap().is_empty());
+        assert_eq!(store.seq_numbers().next_inbound(), 1);
+        assert!(store.creation_time() >= creation_time);
+    }
+
+    #[test]
+    fn file_store_survives_reopening() {
+        let path = temp_path();
+        let creation_time = {
+            let store = &mut FileStore::open(&path).unwrap();
+            exercise(store);
+            store.creation_time()
+        };
+        let store = &mut FileStore::open(&path).unwrap();
+        assert_eq!(store.seq_numbers().next_inbound(), 3);
+        assert_eq!(store.seq_numbers().next_outbound(), 5);
+        assert_eq!(
+            store.creation_time().timestamp_millis(),
+            creation_time.timestamp_millis()
+        );
+        assert_eq!(
+            store.fetch_outbound(1..3).unwrap(),
+            vec![(1, b"foo".to_vec()), (2, b"bar\n".to_vec())]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_discards_truncated_record() {
+        let path = temp_path();
+        {
+            let store = &mut FileStore::open(&path).unwrap();
+            exercise(store);
+        }
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"M 5 100\nincomplete").unwrap();
+        let store = &mut FileStore::open(&path).unwrap();
+        assert_eq!(store.fetch_outbound(5..6).unwrap(), vec![]);
+        store.store_outbound(5, b"baz").unwrap();
+        let store = &mut FileStore::open(&path).unwrap();
+        assert_eq!(
+            store.fetch_outbound(5..6).unwrap(),
+            vec![(5, b"baz".to_vec())]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_refuses_to_open_other_files() {
+        let path = temp_path();
+        std::fs::write(&path, "not a journal\nC 0\n").unwrap();
+        let err = FileStore::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"not a journal\nC 0\n".to_vec()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_refuses_corrupt_records() {
+        let path = temp_path();
+        {
+            let store = &mut FileStore::open(&path).unwrap();
+            exercise(store);
+        }
+        let mut journal = std::fs::read(&path).unwrap();
+        let i = journal.windows(5).position(|w| w == b"M 2 4").unwrap();
+        journal[i] = b'X';
+        std::fs::write(&path, &journal).unwrap();
+        let err = FileStore::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), journal);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_refuses_corrupt_lengths_in_the_middle() {
+        let path = temp_path();
+        {
+            let store = &mut FileStore::open(&path).unwrap();
+            exercise(store);
+        }
+        let mut journal = std::fs::read(&path).unwrap();
+        let i = journal.windows(6).position(|w| w == b"M 1 3\n").unwrap();
+        journal.splice(i..i + 5, b"M 1 3000".iter().copied());
+        std::fs::write(&path, &journal).unwrap();
+        let err = FileStore::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), journal);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_compaction_drops_superseded_records() {
+        let path = temp_path();
+        let store = &mut FileStore::open(&path).unwrap();
+        store.compaction_threshold = 0;
+        exercise(store);
+        store.store_outbound(1, b"qux").unwrap();
+        for i in 0..100 {
+            store.set_seq_numbers(seq_numbers(3 + i, 5)).unwrap();
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        assert!(len < 100, "{} bytes", len);
+        let expected = vec![(1, b"qux".to_vec()), (2, b"bar\n".to_vec()), (4, vec![])];
+        assert_eq!(store.fetch_outbound(1..10).unwrap(), expected);
+        store.store_outbound(5, b"quux").unwrap();
+
+        let store = &mut FileStore::open(&path).unwrap();
+        assert_eq!(store.seq_numbers().next_inbound(), 102);
+        assert_eq!(store.fetch_outbound(1..5).unwrap(), expected);
+        assert_eq!(
+            store.fetch_outbound(5..6).unwrap(),
+            vec![(5, b"quux".to_vec())]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_store_reset() {
+        let path = temp_path();
+        let store = &mut FileStore::open(&path).unwrap();
+        exercise(store);
+        store.reset().unwrap();
+        assert!(store.fetch_outbound(1..10).unwrap().is_empty());
+        let store = &mut FileStore::open(&path).unwrap();
+        assert!(store.fetch_outbound(1..10).unwrap().is_empty());
+        assert_eq!(store.seq_numbers().next_outbound(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,614 @@
+//! Deterministic, in-memory test harnesses for FIX sessions.
+//!
+//! [`duplex`] connects two in-memory streams, one for the [`FixConnection`]
+//! under test and one for a scripted counterparty. A [`Script`] plays the part
+//! of the counterparty, one step after the other: it sends messages with a
+//! valid `StandardHeader` and expects the [`FixConnection`] to reply with
+//! specific messages.
+//!
+//! ```
+//! use fefix::session::testing::{duplex, Script};
+//! use fefix::session::{Config, Configure};
+//! use futures::AsyncReadExt;
+//!
+//! # async fn example() -> Result<(), fefix::session::testing::ScriptError> {
+//! let config = Config::default();
+//! let (stream, counterparty) = duplex(4096);
+//! let script = Script::new(&config)
+//!     .expect("A")
+//!     .send("A", &[(98, "0"), (108, "30")])
+//!     // Force a gap.
+//!     .send("0", &[(34, "5")])
+//!     .expect_fields("2", &[(7, "2"), (16, "0")]);
+//! let (input, output) = stream.split();
+//! // Run a `FixConnection` on `input` and `output`, e.g. with
+//! // `FixConnection::start`, concurrently with:
+//! let transcript = script.run(counterparty).await?;
+//! assert_eq!(transcript.len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`FixConnection`]: super::FixConnection
+
+use super::{message_len, Configure};
+use crate::field_types::Timestamp;
+use crate::tagvalue::Encoder;
+use crate::SetField;
+use futures::future::{select, Either};
+use futures::{pin_mut, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use futures_timer::Delay;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+const SOH: u8 = b'\x01';
+const MSG_TYPE: u32 = 35;
+const MSG_SEQ_NUM: u32 = 34;
+const SENDER_COMP_ID: u32 = 49;
+const SENDING_TIME: u32 = 52;
+const TARGET_COMP_ID: u32 = 56;
+
+/// Creates a pair of connected, in-memory [`DuplexStream`]s. Each direction
+/// buffers up to `capacity` bytes; writes beyond that wait for the other end
+/// to read.
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    let a_to_b = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let b_to_a = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let a = DuplexStream {
+        inbound: b_to_a.clone(),
+        outbound: a_to_b.clone(),
+    };
+    let b = DuplexStream {
+        inbound: a_to_b,
+        outbound: b_to_a,
+    };
+    (a, b)
+}
+
+/// One end of an in-memory transport, as returned by [`duplex`].
+///
+/// Closing or dropping either end is seen as an EOF by the other one, once
+/// it has read everything that was written before. Writing to an end whose
+/// counterpart has been dropped fails with [`io::ErrorKind::BrokenPipe`].
+#[derive(Debug)]
+pub struct DuplexStream {
+    inbound: Arc<Mutex<Pipe>>,
+    outbound: Arc<Mutex<Pipe>>,
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        lock(&self.inbound).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        lock(&self.outbound).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        lock(&self.outbound).close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        lock(&self.inbound).close();
+        lock(&self.outbound).close();
+    }
+}
+
+fn lock(pipe: &Mutex<Pipe>) -> MutexGuard<'_, Pipe> {
+    pipe.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A bounded, one-way byte buffer between the two ends of a [`duplex`].
+#[derive(Debug)]
+struct Pipe {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    is_closed: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Self {
+        Self {
+            buffer: VecDeque::new(),
+            capacity,
+            is_closed: false,
+            reader: None,
+            writer: None,
+        }
+    }
+
+    fn poll_read(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if self.buffer.is_empty() {
+            if self.is_closed || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            self.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let len = buf.len().min(self.buffer.len());
+        for (dst, src) in buf.iter_mut().zip(self.buffer.drain(..len)) {
+            *dst = src;
+        }
+        if let Some(writer) = self.writer.take() {
+            writer.wake();
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.is_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        let len = buf
+            .len()
+            .min(self.capacity.saturating_sub(self.buffer.len()));
+        if len == 0 && !buf.is_empty() {
+            self.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        self.buffer.extend(&buf[..len]);
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn close(&mut self) {
+        self.is_closed = true;
+        for waker in [self.reader.take(), self.writer.take()]
+            .into_iter()
+            .flatten()
+        {
+            waker.wake();
+        }
+    }
+}
+
+/// The scripted behavior of a FIX counterparty, i.e. a list of steps that
+/// [`Script::run`] carries out in order.
+///
+/// Messages sent by the script get a `StandardHeader` that matches the
+/// [`Configure`] implementor given to [`Script::new`], with CompIDs swapped
+/// and `MsgSeqNum <34>` values counting up from 1. Header fields can be
+/// overridden by passing them explicitly, e.g. `(34, "5")` to force a seq.
+/// number gap; later messages then count up from there.
+#[derive(Debug, Clone)]
+pub struct Script {
+    begin_string: Vec<u8>,
+    sender_comp_id: Vec<u8>,
+    target_comp_id: Vec<u8>,
+    timeout: Duration,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Send {
+        msg_type: String,
+        fields: Vec<(u32, String)>,
+    },
+    SendRaw(Vec<u8>),
+    Expect {
+        msg_type: String,
+        fields: Vec<(u32, String)>,
+    },
+    ExpectDisconnect,
+    Wait(Duration),
+    Disconnect,
+}
+
+impl Script {
+    /// Creates a new, empty [`Script`] for the counterparty of a
+    /// [`FixConnection`](super::FixConnection) configured with `config`.
+    pub fn new(config: &impl Configure) -> Self {
+        Self {
+            begin_string: config.begin_string().to_vec(),
+            sender_comp_id: config.target_comp_id().to_vec(),
+            target_comp_id: config.sender_comp_id().to_vec(),
+            timeout: Duration::from_secs(5),
+            steps: vec![],
+        }
+    }
+
+    /// Sets how long each expectation waits for a message before failing
+    /// with [`ScriptError::Timeout`]. Five seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends a message of type `msg_type` with `fields`.
+    pub fn send(mut self, msg_type: &str, fields: &[(u32, &str)]) -> Self {
+        self.steps.push(Step::Send {
+            msg_type: msg_type.to_string(),
+            fields: owned(fields),
+        });
+        self
+    }
+
+    /// Sends `bytes` as they are, e.g. a garbled message.
+    pub fn send_raw(mut self, bytes: &[u8]) -> Self {
+        self.steps.push(Step::SendRaw(bytes.to_vec()));
+        self
+    }
+
+    /// Expects the next message to be of type `msg_type`.
+    pub fn expect(self, msg_type: &str) -> Self {
+        self.expect_fields(msg_type, &[])
+    }
+
+    /// Expects the next message to be of type `msg_type` and to contain all
+    /// of `fields`.
+    pub fn expect_fields(mut self, msg_type: &str, fields: &[(u32, &str)]) -> Self {
+        self.steps.push(Step::Expect {
+            msg_type: msg_type.to_string(),
+            fields: owned(fields),
+        });
+        self
+    }
+
+    /// Expects the transport to be closed without any further messages.
+    pub fn expect_disconnect(mut self) -> Self {
+        self.steps.push(Step::ExpectDisconnect);
+        self
+    }
+
+    /// Does nothing for `duration`.
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Wait(duration));
+        self
+    }
+
+    /// Closes the transport. Any later steps are skipped.
+    pub fn disconnect(mut self) -> Self {
+        self.steps.push(Step::Disconnect);
+        self
+    }
+
+    /// Carries out all steps of `self` over `stream`. Returns all messages
+    /// received by the expectations, in order.
+    pub async fn run<S>(self, mut stream: S) -> Result<Vec<Vec<u8>>, ScriptError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut runner = Runner {
+            script: &self,
+            next_msg_seq_num: 1,
+            buffer: vec![],
+            received: vec![],
+        };
+        for (step, action) in self.steps.iter().enumerate() {
+            match action {
+                Step::Send { msg_type, fields } => {
+                    let msg = runner.encode(msg_type, fields);
+                    stream.write_all(&msg).await?;
+                    stream.flush().await?;
+                }
+                Step::SendRaw(bytes) => {
+                    stream.write_all(bytes).await?;
+                    stream.flush().await?;
+                }
+                Step::Expect { msg_type, fields } => {
+                    let msg = match runner.next_message(&mut stream, step).await? {
+                        Some(msg) => msg,
+                        None => return Err(ScriptError::Disconnected { step }),
+                    };
+                    let is_expected = field(&msg, MSG_TYPE) == Some(msg_type.as_bytes())
+                        && fields
+                            .iter()
+                            .all(|(tag, value)| field(&msg, *tag) == Some(value.as_bytes()));
+                    if !is_expected {
+                        let mut expected = vec![(MSG_TYPE, msg_type.clone())];
+                        expected.extend(fields.iter().cloned());
+                        return Err(ScriptError::UnexpectedMessage {
+                            step,
+                            expected: display_fields(&expected),
+                            received: display(&msg),
+                        });
+                    }
+                    runner.received.push(msg);
+                }
+                Step::ExpectDisconnect => {
+                    if let Some(msg) = runner.next_message(&mut stream, step).await? {
+                        return Err(ScriptError::UnexpectedMessage {
+                            step,
+                            expected: "disconnection".to_string(),
+                            received: display(&msg),
+                        });
+                    }
+                }
+                Step::Wait(duration) => Delay::new(*duration).await,
+                Step::Disconnect => {
+                    stream.close().await?;
+                    break;
+                }
+            }
+        }
+        Ok(runner.received)
+    }
+}
+
+/// The mutable state of [`Script::run`].
+struct Runner<'a> {
+    script: &'a Script,
+    next_msg_seq_num: u64,
+    buffer: Vec<u8>,
+    received: Vec<Vec<u8>>,
+}
+
+impl Runner<'_> {
+    fn encode(&mut self, msg_type: &str, fields: &[(u32, String)]) -> Vec<u8> {
+        let header_field = |tag| {
+            fields
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map(|(_, value)| value.as_str())
+        };
+        if let Some(msg_seq_num) = header_field(MSG_SEQ_NUM).and_then(|s| s.parse().ok()) {
+            self.next_msg_seq_num = msg_seq_num;
+        }
+        let mut buffer = Vec::new();
+        let mut encoder = Encoder::new();
+        let mut msg =
+            encoder.start_message(&self.script.begin_string, &mut buffer, msg_type.as_bytes());
+        match header_field(SENDER_COMP_ID) {
+            Some(sender_comp_id) => msg.set(SENDER_COMP_ID, sender_comp_id),
+            None => msg.set(SENDER_COMP_ID, &self.script.sender_comp_id[..]),
+        }
+        match header_field(TARGET_COMP_ID) {
+            Some(target_comp_id) => msg.set(TARGET_COMP_ID, target_comp_id),
+            None => msg.set(TARGET_COMP_ID, &self.script.target_comp_id[..]),
+        }
+        msg.set(MSG_SEQ_NUM, self.next_msg_seq_num);
+        match header_field(SENDING_TIME) {
+            Some(sending_time) => msg.set(SENDING_TIME, sending_time),
+            None => msg.set(SENDING_TIME, Timestamp::utc_now()),
+        }
+        for (tag, value) in fields {
+            if ![SENDER_COMP_ID, TARGET_COMP_ID, MSG_SEQ_NUM, SENDING_TIME].contains(tag) {
+                msg.set(*tag, value.as_str());
+            }
+        }
+        self.next_msg_seq_num += 1;
+        msg.done();
+        buffer
+    }
+
+    /// Returns the next whole message, or [`None`] if the transport is
+    /// closed first.
+    async fn next_message<S>(
+        &mut self,
+        stream: &mut S,
+        step: usize,
+    ) -> Result<Option<Vec<u8>>, ScriptError>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let timeout = Delay::new(self.script.timeout);
+        let read = async {
+            let mut chunk = [0; 1024];
+            loop {
+                if let Some(len) = message_len(&self.buffer) {
+                    return Ok(Some(self.buffer.drain(..len).collect()));
+                }
+                let num_bytes = stream.read(&mut chunk).await?;
+                if num_bytes == 0 {
+                    return Ok(None);
+                }
+                self.buffer.extend_from_slice(&chunk[..num_bytes]);
+            }
+        };
+        pin_mut!(read);
+        match select(read, timeout).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(ScriptError::Timeout { step }),
+        }
+    }
+}
+
+/// The type returned in the event of a failed [`Script::run`]. `step` is the
+/// index of the failed step.
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    /// A message didn't match the expectation.
+    #[error("Step {step}: expected {expected}, received {received}")]
+    UnexpectedMessage {
+        /// The index of the step.
+        step: usize,
+        /// The expected fields.
+        expected: String,
+        /// The received message.
+        received: String,
+    },
+    /// No message arrived in time.
+    #[error("Step {step}: timed out.")]
+    Timeout {
+        /// The index of the step.
+        step: usize,
+    },
+    /// The transport was closed while expecting a message.
+    #[error("Step {step}: the transport was closed.")]
+    Disconnected {
+        /// The index of the step.
+        step: usize,
+    },
+    /// I/O error at the transport layer.
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+fn owned(fields: &[(u32, &str)]) -> Vec<(u32, String)> {
+    fields
+        .iter()
+        .map(|(tag, value)| (*tag, value.to_string()))
+        .collect()
+}
+
+/// Returns the value of the first field with `tag` in `msg`.
+fn field(msg: &[u8], tag: u32) -> Option<&[u8]> {
+    let tag = tag.to_string();
+    msg.split(|b| *b == SOH)
+        .filter_map(|field| {
+            let equal_sign = field.iter().position(|b| *b == b'=')?;
+            Some((&field[..equal_sign], &field[equal_sign + 1..]))
+        })
+        .find(|(t, _)| *t == tag.as_bytes())
+        .map(|(_, value)| value)
+}
+
+fn display(msg: &[u8]) -> String {
+    String::from_utf8_lossy(msg).replace('\x01', "|")
+}
+
+fn display_fields(fields: &[(u32, String)]) -> String {
+    fields
+        .iter()
+        .map(|(tag, value)| format!("{}={}|", tag, value))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session::{Backend, Config, FixConnection, SessionState};
+    use crate::tagvalue::{Decoder, Message};
+    use crate::Dictionary;
+    use futures::future::join;
+    use futures::AsyncReadExt;
+
+    #[derive(Debug, Clone, Default)]
+    struct NoopBackend;
+
+    impl Backend for NoopBackend {
+        type Error = u32;
+
+        fn sender_comp_id(&self) -> &[u8] {
+            b"SENDER_COMP"
+        }
+
+        fn target_comp_id(&self) -> &[u8] {
+            b"TARGET_COMP"
+        }
+
+        fn on_inbound_app_message(&mut self, _message: Message<&[u8]>) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_outbound_message(&mut self, _message: &[u8]) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_resend_request(&mut self, _range: std::ops::Range<u64>) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn on_successful_handshake(&mut self) -> Result<(), u32> {
+            Ok(())
+        }
+
+        fn fetch_messages(&mut self) -> Result<&[&[u8]], u32> {
+            Ok(&[])
+        }
+
+        fn pending_message(&mut self) -> Option<&[u8]> {
+            None
+        }
+    }
+
+    #[test]
+    fn duplex_streams_are_connected() {
+        futures::executor::block_on(async {
+            let (mut a, mut b) = duplex(4);
+            let write = async {
+                a.write_all(b"8=FIX.4.4").await.unwrap();
+                a.close().await.unwrap();
+            };
+            let read = async {
+                let mut bytes = vec![];
+                b.read_to_end(&mut bytes).await.unwrap();
+                bytes
+            };
+            let ((), bytes) = join(write, read).await;
+            assert_eq!(bytes, b"8=FIX.4.4");
+        });
+    }
+
+    #[test]
+    fn messages_are_split_by_body_length() {
+        let msg = b"8=FIX.4.4\x019=5\x0135=0\x0110=161\x01";
+        assert_eq!(message_len(msg), Some(msg.len()));
+        assert_eq!(message_len(&msg[..msg.len() - 1]), None);
+        assert_eq!(
+            message_len(b"8=FIX.4.4\x019=18446744073709551615\x01"),
+            None
+        );
+        assert_eq!(field(msg, 35), Some(&b"0"[..]));
+    }
+
+    #[test]
+    fn gaps_are_filled_with_a_resend_request() {
+        let config = Config::default();
+        let script = Script::new(&config)
+            .expect_fields("A", &[(34, "1"), (49, "SENDER_COMP")])
+            .send("A", &[(98, "0"), (108, "30")])
+            .send("0", &[(34, "5")])
+            .expect_fields("2", &[(7, "2"), (16, "0")])
+            .send("4", &[(34, "2"), (123, "Y"), (36, "5")])
+            .send("5", &[(34, "6")])
+            .expect("5")
+            .expect_disconnect();
+        let (stream, counterparty) = duplex(4096);
+        let mut conn = FixConnection::new(config, NoopBackend);
+        let (input, output) = stream.split();
+        let decoder = Decoder::new(Dictionary::fix44()).streaming(vec![]);
+        let (result, transcript) = futures::executor::block_on(join(
+            conn.start(input, output, decoder),
+            script.run(counterparty),
+        ));
+        result.unwrap();
+        assert_eq!(transcript.unwrap().len(), 3);
+        assert_eq!(conn.state(), SessionState::Disconnected);
+    }
+
+    #[test]
+    fn unexpected_messages_fail_the_script() {
+        let config = Config::default();
+        let script = Script::new(&config).expect("0");
+        let (stream, counterparty) = duplex(4096);
+        let mut conn = FixConnection::new(config, NoopBackend);
+        let (input, output) = stream.split();
+        let decoder = Decoder::new(Dictionary::fix44()).streaming(vec![]);
+        let (_, result) = futures::executor::block_on(join(
+            conn.start(input, output, decoder),
+            script.run(counterparty),
+        ));
+        assert!(matches!(
+            result,
+            Err(ScriptError::UnexpectedMessage { step: 0, .. })
+        ));
+    }
+}
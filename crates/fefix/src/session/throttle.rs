@@ -0,0 +1,206 @@
+use std::time::{Duration, Instant};
+
+/// An outbound rate limit for the application messages of a FIX session,
+/// as returned by [`Configure::throttle`](super::Configure::throttle).
+///
+/// Every limit is a token bucket: up to [`Rate::messages`] can be sent in a
+/// burst, and then one more every `interval / messages`. A message must
+/// satisfy both the session-wide limit and the limit of its `MsgType <35>`,
+/// if any. Session-level messages are never throttled and don't count
+/// towards any limit.
+///
+/// ```
+/// use fefix::session::{Rate, Throttle, WhenThrottled};
+/// use std::time::Duration;
+///
+/// // At most 50 messages per second, of which at most 10 `NewOrderSingle <D>`.
+/// let throttle = Throttle::new(Rate::per_second(50))
+///     .with_msg_type_limit(b"D", Rate::per_second(10))
+///     .when_exceeded(WhenThrottled::Reject);
+/// assert_eq!(throttle.limit.interval, Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Throttle {
+    /// The limit across all application messages.
+    pub limit: Rate,
+    /// Additional limits for specific `MsgType <35>` values.
+    pub msg_type_limits: Vec<(Vec<u8>, Rate)>,
+    /// What to do with messages beyond the limits.
+    pub when_exceeded: WhenThrottled,
+}
+
+impl Throttle {
+    /// Creates a new [`Throttle`] which queues messages beyond `limit`.
+    pub fn new(limit: Rate) -> Self {
+        Self {
+            limit,
+            msg_type_limits: vec![],
+            when_exceeded: WhenThrottled::Queue,
+        }
+    }
+
+    /// Adds a `limit` for messages of type `msg_type`.
+    pub fn with_msg_type_limit(mut self, msg_type: &[u8], limit: Rate) -> Self {
+        self.msg_type_limits.push((msg_type.to_vec(), limit));
+        self
+    }
+
+    /// Sets [`Throttle::when_exceeded`].
+    pub fn when_exceeded(mut self, when_exceeded: WhenThrottled) -> Self {
+        self.when_exceeded = when_exceeded;
+        self
+    }
+}
+
+/// A number of messages per time interval.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rate {
+    /// The number of messages, which is also the maximum burst.
+    pub messages: u32,
+    /// The time interval.
+    pub interval: Duration,
+}
+
+impl Rate {
+    /// Creates a new [`Rate`] of `messages` per second.
+    pub fn per_second(messages: u32) -> Self {
+        Self {
+            messages,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What to do with application messages that exceed a [`Throttle`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WhenThrottled {
+    /// Hold them in order and send them as soon as the limits allow.
+    Queue,
+    /// Refuse them with
+    /// [`SendError::Throttled`](super::SendError::Throttled).
+    Reject,
+}
+
+/// The token buckets of a [`Throttle`].
+#[derive(Debug, Clone)]
+pub(crate) struct Throttler {
+    when_exceeded: WhenThrottled,
+    total: TokenBucket,
+    msg_types: Vec<(Vec<u8>, TokenBucket)>,
+}
+
+impl Throttler {
+    pub fn new(throttle: Throttle) -> Self {
+        Self {
+            when_exceeded: throttle.when_exceeded,
+            total: TokenBucket::new(throttle.limit),
+            msg_types: throttle
+                .msg_type_limits
+                .into_iter()
+                .map(|(msg_type, rate)| (msg_type, TokenBucket::new(rate)))
+                .collect(),
+        }
+    }
+
+    pub fn when_exceeded(&self) -> WhenThrottled {
+        self.when_exceeded
+    }
+
+    /// Returns how long until a message of type `msg_type` is allowed at
+    /// `now`, which is zero if it's allowed right away.
+    pub fn wait(&mut self, msg_type: &[u8], now: Instant) -> Duration {
+        let wait = self.total.wait(now);
+        match self.bucket(msg_type) {
+            Some(bucket) => wait.max(bucket.wait(now)),
+            None => wait,
+        }
+    }
+
+    /// Counts a message of type `msg_type` against the limits. It must be
+    /// allowed by [`Throttler::wait`].
+    pub fn take(&mut self, msg_type: &[u8]) {
+        self.total.take();
+        if let Some(bucket) = self.bucket(msg_type) {
+            bucket.take();
+        }
+    }
+
+    fn bucket(&mut self, msg_type: &[u8]) -> Option<&mut TokenBucket> {
+        self.msg_types
+            .iter_mut()
+            .find(|(t, _)| t.as_slice() == msg_type)
+            .map(|(_, bucket)| bucket)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated_at: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            tokens: f64::from(rate.messages),
+            updated_at: None,
+        }
+    }
+
+    fn wait(&mut self, now: Instant) -> Duration {
+        let capacity = f64::from(self.rate.messages);
+        if self.rate.interval.is_zero() {
+            return Duration::ZERO;
+        }
+        if let Some(updated_at) = self.updated_at {
+            let elapsed = now.saturating_duration_since(updated_at).as_secs_f64();
+            let refill = elapsed * capacity / self.rate.interval.as_secs_f64();
+            self.tokens = (self.tokens + refill).min(capacity);
+        }
+        self.updated_at = Some(now);
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if capacity == 0.0 {
+            Duration::MAX
+        } else {
+            self.rate.interval.mul_f64((1.0 - self.tokens) / capacity)
+        }
+    }
+
+    fn take(&mut self) {
+        if !self.rate.interval.is_zero() {
+            self.tokens -= 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bursts_up_to_the_limit_then_refills() {
+        let t0 = Instant::now();
+        let mut throttler = Throttler::new(Throttle::new(Rate::per_second(2)));
+        for _ in 0..2 {
+            assert_eq!(throttler.wait(b"D", t0), Duration::ZERO);
+            throttler.take(b"D");
+        }
+        assert_eq!(throttler.wait(b"D", t0), Duration::from_millis(500));
+        let t1 = t0 + Duration::from_millis(500);
+        assert_eq!(throttler.wait(b"D", t1), Duration::ZERO);
+    }
+
+    #[test]
+    fn msg_type_limits_apply_on_top() {
+        let t0 = Instant::now();
+        let throttle =
+            Throttle::new(Rate::per_second(10)).with_msg_type_limit(b"D", Rate::per_second(1));
+        let mut throttler = Throttler::new(throttle);
+        throttler.take(b"D");
+        assert_eq!(throttler.wait(b"D", t0), Duration::from_secs(1));
+        assert_eq!(throttler.wait(b"F", t0), Duration::ZERO);
+    }
+}
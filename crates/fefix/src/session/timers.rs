@@ -0,0 +1,187 @@
+use std::time::{Duration, Instant};
+
+/// Sans-IO bookkeeping of the `Heartbeat <0>` deadlines of a FIX session.
+///
+/// [`HeartbeatTimers`] never reads the system clock: the current time is
+/// always provided by the caller, which makes it possible to test heartbeat
+/// logic with a fake clock.
+///
+/// A zero `Heartbeat <0>` interval, i.e. `HeartBtInt <108>=0`, disables all
+/// timers.
+#[derive(Debug, Clone)]
+pub struct HeartbeatTimers {
+    heartbeat: Duration,
+    soft_multiplier: u32,
+    hard_multiplier: u32,
+    soft_tolerance: Duration,
+    hard_tolerance: Duration,
+    last_inbound: Instant,
+    last_outbound: Instant,
+    is_test_request_due: bool,
+}
+
+impl HeartbeatTimers {
+    /// Creates a new [`HeartbeatTimers`] with the provided `heartbeat`
+    /// interval, starting at `now`. Soft and hard tolerances are respectively
+    /// twice and three times `heartbeat`.
+    pub fn new(heartbeat: Duration, now: Instant) -> Self {
+        Self {
+            heartbeat,
+            soft_multiplier: 2,
+            hard_multiplier: 3,
+            soft_tolerance: heartbeat * 2,
+            hard_tolerance: heartbeat * 3,
+            last_inbound: now,
+            last_outbound: now,
+            is_test_request_due: false,
+        }
+    }
+
+    /// Sets the soft and hard tolerances to `soft` and `hard` times the
+    /// `Heartbeat <0>` interval, now and after every
+    /// [`HeartbeatTimers::set_heartbeat`].
+    pub fn with_tolerances(mut self, soft: u32, hard: u32) -> Self {
+        self.soft_multiplier = soft;
+        self.hard_multiplier = hard;
+        self.set_heartbeat(self.heartbeat);
+        self
+    }
+
+    /// Returns the `Heartbeat <0>` interval.
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// Changes the `Heartbeat <0>` interval, e.g. after it's been negotiated
+    /// via `Logon <A>`. Soft and hard tolerances are reset to the multiples of
+    /// the new interval given by [`HeartbeatTimers::with_tolerances`] (twice
+    /// and three times by default).
+    pub fn set_heartbeat(&mut self, heartbeat: Duration) {
+        self.heartbeat = heartbeat;
+        self.soft_tolerance = heartbeat.saturating_mul(self.soft_multiplier);
+        self.hard_tolerance = heartbeat.saturating_mul(self.hard_multiplier);
+    }
+
+    /// How long after the last inbound message should we send a `TestRequest
+    /// <1>`?
+    pub fn set_soft_tolerance(&mut self, soft_tolerance: Duration) {
+        self.soft_tolerance = soft_tolerance;
+    }
+
+    /// How long after the last inbound message should we give up on the FIX
+    /// counterparty?
+    pub fn set_hard_tolerance(&mut self, hard_tolerance: Duration) {
+        self.hard_tolerance = hard_tolerance;
+    }
+
+    /// Resets the FIX counterparty's timers. This should be called after
+    /// receiving any valid message.
+    pub fn on_inbound(&mut self, now: Instant) {
+        self.last_inbound = now;
+        self.is_test_request_due = false;
+    }
+
+    /// Resets the timer of our own outbound `Heartbeat <0>` messages. This
+    /// should be called after sending any message to the FIX counterparty.
+    pub fn on_outbound(&mut self, now: Instant) {
+        self.last_outbound = now;
+    }
+
+    /// Returns the instant at which [`HeartbeatTimers::poll`] will return the
+    /// next [`TimerEvent`], or [`None`] if timers are disabled.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.heartbeat.is_zero() {
+            return None;
+        }
+        let heartbeat = self.last_outbound + self.heartbeat;
+        let logout = self.last_inbound + self.hard_tolerance;
+        if self.is_test_request_due {
+            Some(heartbeat.min(logout))
+        } else {
+            Some(
+                heartbeat
+                    .min(logout)
+                    .min(self.last_inbound + self.soft_tolerance),
+            )
+        }
+    }
+
+    /// Returns the most urgent [`TimerEvent`] that is due at `now`, if any.
+    /// Every event is only returned once, except for
+    /// [`TimerEvent::Logout`].
+    pub fn poll(&mut self, now: Instant) -> Option<TimerEvent> {
+        if self.heartbeat.is_zero() {
+            None
+        } else if now >= self.last_inbound + self.hard_tolerance {
+            Some(TimerEvent::Logout)
+        } else if !self.is_test_request_due && now >= self.last_inbound + self.soft_tolerance {
+            self.is_test_request_due = true;
+            Some(TimerEvent::TestRequest)
+        } else if now >= self.last_outbound + self.heartbeat {
+            self.last_outbound = now;
+            Some(TimerEvent::Heartbeat)
+        } else {
+            None
+        }
+    }
+}
+
+/// A deadline that was reached by [`HeartbeatTimers`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// Time to send a new `HeartBeat <0>` message.
+    Heartbeat,
+    /// The FIX counterparty has exceeded the soft tolerance, and it's time to
+    /// send a `TestRequest <1>`.
+    TestRequest,
+    /// The FIX counterparty has exceeded the hard tolerance, and it's time to
+    /// disconnect.
+    Logout,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_follow_the_tolerances() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let timers = &mut HeartbeatTimers::new(Duration::from_secs(10), t0);
+        assert_eq!(timers.next_deadline(), Some(secs(10)));
+        assert_eq!(timers.poll(secs(5)), None);
+        assert_eq!(timers.poll(secs(10)), Some(TimerEvent::Heartbeat));
+        assert_eq!(timers.poll(secs(10)), None);
+        timers.on_outbound(secs(15));
+        assert_eq!(timers.next_deadline(), Some(secs(20)));
+        assert_eq!(timers.poll(secs(20)), Some(TimerEvent::TestRequest));
+        assert_eq!(timers.poll(secs(20)), None);
+        assert_eq!(timers.next_deadline(), Some(secs(25)));
+        assert_eq!(timers.poll(secs(30)), Some(TimerEvent::Logout));
+        assert_eq!(timers.poll(secs(31)), Some(TimerEvent::Logout));
+    }
+
+    #[test]
+    fn zero_heartbeat_disables_all_timers() {
+        let t0 = Instant::now();
+        let timers = &mut HeartbeatTimers::new(Duration::from_secs(10), t0);
+        timers.set_heartbeat(Duration::ZERO);
+        assert_eq!(timers.next_deadline(), None);
+        assert_eq!(timers.poll(t0), None);
+        assert_eq!(timers.poll(t0 + Duration::from_secs(3600)), None);
+    }
+
+    #[test]
+    fn inbound_messages_reset_the_tolerances() {
+        let t0 = Instant::now();
+        let secs = |n| t0 + Duration::from_secs(n);
+        let timers = &mut HeartbeatTimers::new(Duration::from_secs(10), t0);
+        timers.set_soft_tolerance(Duration::from_secs(15));
+        assert_eq!(timers.poll(secs(15)), Some(TimerEvent::TestRequest));
+        timers.on_inbound(secs(16));
+        timers.on_outbound(secs(16));
+        assert_eq!(timers.next_deadline(), Some(secs(26)));
+        assert_eq!(timers.poll(secs(29)), Some(TimerEvent::Heartbeat));
+        assert_eq!(timers.poll(secs(31)), Some(TimerEvent::TestRequest));
+    }
+}
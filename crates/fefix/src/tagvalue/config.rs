@@ -22,6 +22,12 @@ pub struct Config {
     /// Determines whether or not the decoder needs to have access to
     /// associative FIX fields. If turned off, only linear access is possible.
     pub should_decode_associative: bool,
+    /// Determines whether or not a tag that appears more than once is an
+    /// error, rather than the last occurrence overriding the others. Only
+    /// relevant when [`Config::should_decode_associative`] is on.
+    ///
+    /// This setting has no effect when encoding FIX messages.
+    pub reject_duplicate_tags: bool,
 }
 
 impl Default for Config {
@@ -31,6 +37,7 @@ impl Default for Config {
             max_message_size: Some(DEFAULT_MAX_MESSAGE_SIZE),
             verify_checksum: true,
             should_decode_associative: true,
+            reject_duplicate_tags: false,
         }
     }
 }
@@ -1,4 +1,4 @@
-use super::{Config, DecodeError, RawDecoder, RawDecoderStreaming, RawFrame};
+use super::{Config, DecodeError, FieldErrorKind, RawDecoder, RawDecoderStreaming, RawFrame};
 use crate::dict::{FixDatatype, IsFieldDefinition};
 use crate::{
     Buffer, Dictionary, FieldMap, FieldType, FieldValueError, GetConfig, RepeatingGroup,
@@ -40,14 +40,61 @@ enum FieldLocatorContext {
 //   8=FIX.4.2|...
 const BEGIN_STRING_OFFSET: usize = 2;
 
+const MSG_TYPE: u32 = 35;
+const APPL_VER_ID: u32 = 1128;
+
+/// The `MsgType <35>` values of FIX session layer messages, which are always
+/// decoded with the transport [`Dictionary`].
+const ADMIN_MSG_TYPES: &[&[u8]] = &[b"0", b"1", b"2", b"3", b"4", b"5", b"A"];
+
+/// Returns the `ApplVerID <1128>` value of the FIX application version
+/// `version`, either as returned by [`Dictionary::version`] (e.g.
+/// `FIX.5.0-SP2`) or in QuickFIX style (e.g. `FIX.5.0SP2`).
+///
+/// ```
+/// use fefix::tagvalue::appl_ver_id;
+///
+/// assert_eq!(appl_ver_id("FIX.4.4"), Some("6"));
+/// assert_eq!(appl_ver_id("FIX.5.0-SP2"), Some("9"));
+/// assert_eq!(appl_ver_id("FIX.5.0SP2"), Some("9"));
+/// assert_eq!(appl_ver_id("FIXT.1.1"), None);
+/// ```
+pub fn appl_ver_id(version: &str) -> Option<&'static str> {
+    match version.replace("-SP", "SP").as_str() {
+        "FIX.2.7" => Some("0"),
+        "FIX.3.0" => Some("1"),
+        "FIX.4.0" => Some("2"),
+        "FIX.4.1" => Some("3"),
+        "FIX.4.2" => Some("4"),
+        "FIX.4.3" => Some("5"),
+        "FIX.4.4" => Some("6"),
+        "FIX.5.0" => Some("7"),
+        "FIX.5.0SP1" => Some("8"),
+        "FIX.5.0SP2" => Some("9"),
+        _ => None,
+    }
+}
+
 /// FIX message decoder.
 ///
 /// One should create a [`Decoder`] per stream of FIX messages.
+///
+/// FIXT.1.1 streams carry messages of several FIX application versions. The
+/// [`Dictionary`] of [`Decoder::new`] is then the transport dictionary, used
+/// for session messages, and application messages are parsed with the
+/// dictionary of their `ApplVerID <1128>` (or of the default `ApplVerID`)
+/// among those added with [`Decoder::add_app_dictionary`].
 #[derive(Debug)]
 pub struct Decoder {
     builder: MessageBuilder<'static>,
     raw_decoder: RawDecoder,
     tag_lookup: IntMap<u32, FixDatatype>,
+    app_tag_lookups: Vec<(Vec<u8>, IntMap<u32, FixDatatype>)>,
+    default_appl_ver_id: Option<Vec<u8>>,
+    // Whether the current message is an application message and, if so, the
+    // index of its dictionary within `app_tag_lookups`.
+    is_app_message: bool,
+    app_dict_index: Option<usize>,
 }
 
 impl Decoder {
@@ -57,25 +104,43 @@ impl Decoder {
         Self {
             builder: MessageBuilder::default(),
             raw_decoder: RawDecoder::default(),
-            tag_lookup: dict
-                .fields()
-                .iter()
-                .filter_map(|field| {
-                    let mut fix_type = field.data_type().basetype();
-                    if field.is_num_in_group() {
-                        fix_type = FixDatatype::NumInGroup;
-                    }
+            tag_lookup: tag_lookup(&dict),
+            app_tag_lookups: vec![],
+            default_appl_ver_id: None,
+            is_app_message: false,
+            app_dict_index: None,
+        }
+    }
 
-                    if fix_type == FixDatatype::Length || fix_type == FixDatatype::NumInGroup {
-                        Some((field.tag().get(), fix_type))
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+    /// Parses application messages with `ApplVerID <1128>` equal to
+    /// `appl_ver_id` using `dict`, rather than the transport dictionary.
+    /// See also [`appl_ver_id`].
+    pub fn add_app_dictionary(&mut self, appl_ver_id: &[u8], dict: Dictionary) {
+        let lookup = tag_lookup(&dict);
+        match self.app_dict_position(Some(appl_ver_id)) {
+            Some(i) => self.app_tag_lookups[i].1 = lookup,
+            None => self.app_tag_lookups.push((appl_ver_id.to_vec(), lookup)),
         }
     }
 
+    /// Returns the `ApplVerID <1128>` of application messages that don't
+    /// specify one, i.e. the `DefaultApplVerID <1137>` of the FIX session.
+    pub fn default_appl_ver_id(&self) -> Option<&[u8]> {
+        self.default_appl_ver_id.as_deref()
+    }
+
+    /// Sets [`Decoder::default_appl_ver_id`] to `appl_ver_id`.
+    pub fn set_default_appl_ver_id(&mut self, appl_ver_id: Option<&[u8]>) {
+        self.default_appl_ver_id = appl_ver_id.map(<[u8]>::to_vec);
+    }
+
+    fn app_dict_position(&self, appl_ver_id: Option<&[u8]>) -> Option<usize> {
+        let appl_ver_id = appl_ver_id?;
+        self.app_tag_lookups
+            .iter()
+            .position(|(id, _)| id.as_slice() == appl_ver_id)
+    }
+
     /// Adds a [`Buffer`] to `self`, turning it into a [`StreamingDecoder`].
     pub fn streaming<B>(self, buffer: B) -> DecoderStreaming<B>
     where
@@ -124,58 +189,76 @@ impl Decoder {
         T: AsRef<[u8]>,
     {
         self.builder.clear();
+        self.is_app_message = false;
+        self.app_dict_index = None;
         self.message_builder_mut().bytes = frame.as_bytes();
         let separator = self.config().separator;
         let payload = frame.payload();
+        let payload_offset = frame.payload.start;
         self.store_field(
             TagU32::new(8).unwrap(),
             frame.as_bytes(),
             BEGIN_STRING_OFFSET,
             frame.begin_string().len(),
-        );
+        )
+        .map_err(|kind| DecodeError::Field {
+            offset: 0,
+            tag: Some(8),
+            kind,
+        })?;
         let mut i = 0;
         while i < payload.len() {
-            let index_of_next_equal_sign = {
-                let i_eq = (&payload[i..])
-                    .iter()
-                    .copied()
-                    .position(|byte| byte == b'=')
-                    .map(|pos| pos + i);
-                if i_eq.is_none() {
-                    break;
+            let field_error = |tag: Option<TagU32>, kind| DecodeError::Field {
+                offset: payload_offset + i,
+                tag: tag.map(TagU32::get),
+                kind,
+            };
+            let index_of_next_equal_sign = (&payload[i..])
+                .iter()
+                .copied()
+                .position(|byte| byte == b'=')
+                .map(|pos| pos + i)
+                .ok_or_else(|| field_error(None, FieldErrorKind::InvalidTagNumber))?;
+            let tag_num = {
+                let digits = &payload[i..index_of_next_equal_sign];
+                let mut tag = 0u32;
+                for byte in digits.iter().copied() {
+                    if !byte.is_ascii_digit() {
+                        tag = 0;
+                        break;
+                    }
+                    tag = tag
+                        .saturating_mul(10)
+                        .saturating_add(u32::from(byte - b'0'));
                 }
-                i_eq.unwrap()
+                TagU32::new(tag)
+                    .filter(|_| !digits.is_empty() && tag < u32::MAX)
+                    .ok_or_else(|| field_error(None, FieldErrorKind::InvalidTagNumber))?
             };
+            let remaining = &payload[index_of_next_equal_sign + 1..];
             let field_value_len = if let Some(len) = self.builder.state.data_field_length {
                 self.builder.state.data_field_length = None;
+                if remaining.get(len) != Some(&separator) {
+                    return Err(field_error(Some(tag_num), FieldErrorKind::ValueIsIncorrect));
+                }
                 len
             } else {
-                let len = (&payload[index_of_next_equal_sign + 1..])
-                    .iter()
-                    .copied()
-                    .position(|byte| byte == separator);
+                let len = remaining.iter().copied().position(|byte| byte == separator);
                 if len.is_none() {
                     break;
                 }
                 len.unwrap()
             };
-            let tag_num = {
-                let mut tag = 0u32;
-                for byte in (&payload[i..index_of_next_equal_sign]).iter().copied() {
-                    tag = tag * 10 + (byte as u32 - b'0' as u32);
-                }
-                if let Some(tag) = TagU32::new(tag) {
-                    tag
-                } else {
-                    break;
-                }
-            };
+            if field_value_len == 0 {
+                return Err(field_error(Some(tag_num), FieldErrorKind::TagWithoutValue));
+            }
             self.store_field(
                 tag_num,
                 frame.payload(),
                 index_of_next_equal_sign + 1,
                 field_value_len,
-            );
+            )
+            .map_err(|kind| field_error(Some(tag_num), kind))?;
             // Equal sign                ~~~
             // Separator                                       ~~~
             i = index_of_next_equal_sign + 1 + field_value_len + 1;
@@ -193,8 +276,9 @@ impl Decoder {
         raw_message: &'a [u8],
         field_value_start: usize,
         field_value_len: usize,
-    ) {
+    ) -> Result<(), FieldErrorKind> {
         let config_assoc = self.config().should_decode_associative;
+        let reject_duplicates = self.config().reject_duplicate_tags;
         let field_value = &raw_message[field_value_start..][..field_value_len];
         if self.builder.state.new_group.is_some() {
             // We are entering a new group, but we still don't know which tag
@@ -205,32 +289,78 @@ impl Decoder {
                 self.builder.state.group_information.pop();
             } else if tag == group_info.first_tag_of_every_group_entry {
                 group_info.current_entry_i += 1;
+                if group_info.current_entry_i >= group_info.num_entries {
+                    return Err(FieldErrorKind::IncorrectNumInGroupCount);
+                }
             }
         }
-        self.message_builder_mut()
-            .add_field(
-                tag,
-                &raw_message[field_value_start..][..field_value_len],
-                config_assoc,
-            )
-            .unwrap();
-        let fix_type = self.tag_lookup.get(&tag.get());
+        self.message_builder_mut().add_field(
+            tag,
+            &raw_message[field_value_start..][..field_value_len],
+            config_assoc,
+            reject_duplicates,
+        )?;
+        // Header fields have the same types in all dictionaries, so we can
+        // switch to the application dictionary as soon as we know it.
+        match tag.get() {
+            MSG_TYPE if !ADMIN_MSG_TYPES.contains(&field_value) => {
+                self.is_app_message = true;
+                self.app_dict_index = self.app_dict_position(self.default_appl_ver_id());
+            }
+            APPL_VER_ID if self.is_app_message => {
+                if let Some(i) = self.app_dict_position(Some(field_value)) {
+                    self.app_dict_index = Some(i);
+                }
+            }
+            _ => {}
+        }
+        let tag_lookup = match self.app_dict_index {
+            Some(i) => &self.app_tag_lookups[i].1,
+            None => &self.tag_lookup,
+        };
+        let fix_type = tag_lookup.get(&tag.get());
         if fix_type == Some(&FixDatatype::NumInGroup) {
-            self.builder
-                .state
-                .add_group(tag, self.builder.field_locators.len() - 1, field_value);
+            self.builder.state.add_group(
+                tag,
+                self.builder.field_locators.len() - 1,
+                field_value,
+            )?;
         } else if fix_type == Some(&FixDatatype::Length) {
-            // FIXME
-            let last_field_locator = self.builder.field_locators.last().unwrap();
-            let last_field = self.builder.fields.get(last_field_locator).unwrap();
-            let last_field_value = last_field.1;
-            let s = std::str::from_utf8(last_field_value).unwrap();
-            let data_field_length = str::parse(s).unwrap();
+            let data_field_length = parse_usize(field_value)?;
             self.builder.state.data_field_length = Some(data_field_length);
         }
+        Ok(())
     }
 }
 
+/// Parses the value of a `NumInGroup` or `Length` field.
+fn parse_usize(field_value: &[u8]) -> Result<usize, FieldErrorKind> {
+    std::str::from_utf8(field_value)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(FieldErrorKind::ValueIsIncorrect)
+}
+
+/// Collects the tags of `dict` that affect parsing, i.e. lengths of data
+/// fields and numbers of group entries.
+fn tag_lookup(dict: &Dictionary) -> IntMap<u32, FixDatatype> {
+    dict.fields()
+        .iter()
+        .filter_map(|field| {
+            let mut fix_type = field.data_type().basetype();
+            if field.is_num_in_group() {
+                fix_type = FixDatatype::NumInGroup;
+            }
+
+            if fix_type == FixDatatype::Length || fix_type == FixDatatype::NumInGroup {
+                Some((field.tag().get(), fix_type))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
 impl GetConfig for Decoder {
     type Config = Config;
 
@@ -292,6 +422,23 @@ where
     }
 }
 
+impl<B> DecoderStreaming<B> {
+    /// See [`Decoder::add_app_dictionary`].
+    pub fn add_app_dictionary(&mut self, appl_ver_id: &[u8], dict: Dictionary) {
+        self.decoder.add_app_dictionary(appl_ver_id, dict);
+    }
+
+    /// See [`Decoder::default_appl_ver_id`].
+    pub fn default_appl_ver_id(&self) -> Option<&[u8]> {
+        self.decoder.default_appl_ver_id()
+    }
+
+    /// See [`Decoder::set_default_appl_ver_id`].
+    pub fn set_default_appl_ver_id(&mut self, appl_ver_id: Option<&[u8]>) {
+        self.decoder.set_default_appl_ver_id(appl_ver_id);
+    }
+}
+
 impl<B> DecoderStreaming<B>
 where
     B: Buffer,
@@ -494,9 +641,13 @@ impl DecoderState {
         });
     }
 
-    fn add_group(&mut self, tag: TagU32, index_of_group_tag: usize, field_value: &[u8]) {
-        let field_value_str = std::str::from_utf8(field_value).unwrap();
-        let num_entries = str::parse(field_value_str).unwrap();
+    fn add_group(
+        &mut self,
+        tag: TagU32,
+        index_of_group_tag: usize,
+        field_value: &[u8],
+    ) -> Result<(), FieldErrorKind> {
+        let num_entries = parse_usize(field_value)?;
         if num_entries > 0 {
             self.new_group = Some(DecoderStateNewGroup {
                 tag,
@@ -504,6 +655,7 @@ impl DecoderState {
                 num_entries,
             });
         }
+        Ok(())
     }
 }
 
@@ -553,10 +705,19 @@ impl<'a> MessageBuilder<'a> {
         tag: TagU32,
         field_value: &'a [u8],
         associative: bool,
-    ) -> Result<(), DecodeError> {
+        reject_duplicates: bool,
+    ) -> Result<(), FieldErrorKind> {
         let field_locator = self.state.current_field_locator(tag);
         let i = self.field_locators.len();
         if associative {
+            if reject_duplicates && self.fields.contains_key(&field_locator) {
+                return Err(match field_locator.context {
+                    FieldLocatorContext::TopLevel => FieldErrorKind::TagAppearsMoreThanOnce,
+                    FieldLocatorContext::WithinGroup { .. } => {
+                        FieldErrorKind::GroupFieldsOutOfOrder
+                    }
+                });
+            }
             self.fields.insert(field_locator, (tag, field_value, i));
         }
         self.field_locators.push(field_locator);
@@ -758,6 +919,40 @@ mod test {
         assert_eq!(message.get_raw(346), Some("1".as_bytes()));
     }
 
+    #[test]
+    fn app_messages_are_parsed_with_their_appl_ver_id_dictionary() {
+        // Unlike FIX 4.4, this transport dictionary doesn't know about
+        // `NoMDEntries <268>`.
+        let transport = Dictionary::from_quickfix_spec(
+            "<fix type='FIXT' major='1' minor='1' servicepack='0'>
+                <header/><trailer/><messages/><components/>
+                <fields><field number='1128' name='ApplVerID' type='STRING'/></fields>
+            </fix>",
+        )
+        .unwrap();
+        let mut decoder = Decoder::new(transport);
+        decoder.config_mut().separator = b'|';
+        decoder.add_app_dictionary(b"6", Dictionary::fix44());
+        let mut md_entry_id = |bytes: &[u8]| {
+            let message = decoder.decode(bytes).unwrap();
+            let entry = message.group(268).unwrap().get(0).unwrap();
+            entry.get_raw(278).map(<[u8]>::to_vec)
+        };
+        let bytes = b"8=FIXT.1.1|9=38|35=X|1128=6|268=1|279=0|269=0|278=BID|10=000|";
+        assert_eq!(md_entry_id(bytes), Some(b"BID".to_vec()));
+        let bytes = b"8=FIXT.1.1|9=31|35=X|268=1|279=0|269=0|278=BID|10=000|";
+        assert_eq!(md_entry_id(bytes), None);
+        // Session messages stick to the transport dictionary.
+        let bytes = b"8=FIXT.1.1|9=38|35=0|1128=6|268=1|279=0|269=0|278=BID|10=000|";
+        assert_eq!(md_entry_id(bytes), None);
+
+        decoder.set_default_appl_ver_id(Some(b"6"));
+        let bytes = b"8=FIXT.1.1|9=31|35=X|268=1|279=0|269=0|278=BID|10=000|";
+        let message = decoder.decode(&bytes[..]).unwrap();
+        let entry = message.group(268).unwrap().get(0).unwrap();
+        assert_eq!(entry.get_raw(278), Some(&b"BID"[..]));
+    }
+
     #[test]
     fn assortment_of_random_messages_is_ok() {
         for msg_with_vertical_bar in RANDOM_MESSAGES {
@@ -826,7 +1021,83 @@ mod test {
         let msg = "8=FIX.4.2|9=43|35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|10=146|";
         let mut codec = decoder();
         let result = codec.decode(msg.as_bytes());
-        assert!(matches!(result, Err(DecodeError::Invalid)));
+        assert!(matches!(
+            result,
+            Err(DecodeError::BodyLength {
+                declared: 43,
+                actual: 40
+            })
+        ));
+    }
+
+    #[test]
+    fn body_length_mismatch_reports_both_lengths() {
+        let msg = "8=FIX.4.4|9=20|35=D|11=A|10=000|";
+        let mut codec = decoder();
+        let result = codec.decode(msg.as_bytes());
+        assert!(matches!(
+            result,
+            Err(DecodeError::BodyLength {
+                declared: 20,
+                actual: 10
+            })
+        ));
+    }
+
+    #[test]
+    fn field_errors_report_offset_tag_and_kind() {
+        let cases = [
+            (
+                "8=FIX.4.4|9=14|35=D|11=|59=0|10=000|",
+                20,
+                Some(11),
+                FieldErrorKind::TagWithoutValue,
+            ),
+            (
+                "8=FIX.4.4|9=15|35=D|11=A|11=B|10=000|",
+                25,
+                Some(11),
+                FieldErrorKind::TagAppearsMoreThanOnce,
+            ),
+            (
+                "8=FIX.4.4|9=10|35=D|1x=A|10=000|",
+                20,
+                None,
+                FieldErrorKind::InvalidTagNumber,
+            ),
+            (
+                "8=FIX.4.4|9=11|35=D|453=x|10=000|",
+                20,
+                Some(453),
+                FieldErrorKind::ValueIsIncorrect,
+            ),
+            (
+                "8=FIX.4.4|9=35|35=D|453=1|448=A|447=D|448=B|447=D|10=000|",
+                38,
+                Some(448),
+                FieldErrorKind::IncorrectNumInGroupCount,
+            ),
+        ];
+        for (msg, expected_offset, expected_tag, expected_kind) in cases {
+            let mut codec = decoder();
+            codec.config_mut().reject_duplicate_tags = true;
+            match codec.decode(msg.as_bytes()) {
+                Err(DecodeError::Field { offset, tag, kind }) => {
+                    assert_eq!(offset, expected_offset, "{}", msg);
+                    assert_eq!(tag, expected_tag, "{}", msg);
+                    assert_eq!(kind, expected_kind, "{}", msg);
+                }
+                other => panic!("{}: unexpected result {:?}", msg, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn last_duplicate_tag_wins_by_default() {
+        let msg = "8=FIX.4.4|9=15|35=D|11=A|11=B|10=000|";
+        let mut codec = decoder();
+        let message = codec.decode(msg.as_bytes()).unwrap();
+        assert_eq!(message.get::<&str>(11), Ok("B"));
     }
 
     #[test]
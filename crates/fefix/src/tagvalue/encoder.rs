@@ -111,6 +111,13 @@ where
         (self.buffer.as_slice(), self.initial_buffer_len)
     }
 
+    /// Appends `fields` as they are, without any validation. `fields` must
+    /// contain zero or more already serialized fields, each one followed by
+    /// the separator.
+    pub fn set_raw(&mut self, fields: &[u8]) {
+        self.buffer.extend_from_slice(fields);
+    }
+
     fn body_length_writable_range(&self) -> Range<usize> {
         self.body_start_i - 9..self.body_start_i - 1
     }
@@ -129,7 +136,7 @@ where
     }
 
     fn write_checksum(&mut self) {
-        let checksum = CheckSum::compute(self.buffer.as_slice());
+        let checksum = CheckSum::compute(&self.buffer.as_slice()[self.initial_buffer_len..]);
         self.set(10, checksum);
     }
 }
@@ -40,7 +40,7 @@
 //! |[`Decoder`]           |`&[u8]`                  |[`Message`] |
 //! |[`DecoderStreaming`]   |data streams             |[`Message`] |
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 
 mod config;
 mod decoder;
@@ -49,7 +49,7 @@ mod raw_decoder;
 mod utils;
 
 pub use config::Config;
-pub use decoder::{Decoder, DecoderStreaming, Fields, Message, MessageGroup};
+pub use decoder::{appl_ver_id, Decoder, DecoderStreaming, Fields, Message, MessageGroup};
 pub use encoder::{Encoder, EncoderHandle};
 pub use raw_decoder::{RawDecoder, RawDecoderStreaming, RawFrame};
 
@@ -64,13 +64,79 @@ pub enum DecodeError {
     /// Mandatory field not found.
     #[error("Field not found.")]
     FieldPresence,
-    /// Invalid FIX message syntax, `BodyLength <9>` value mismatch, or similar errors.
+    /// Invalid FIX message syntax, e.g. a malformed `BeginString <8>` or
+    /// `BodyLength <9>`, or a message that is too short.
     #[error("Invalid FIX message syntax.")]
     Invalid,
-    /// Invalid `CheckSum <10>` FIX field value.
-    #[error("Invalid `CheckSum <10>` FIX field value.")]
-    CheckSum,
+    /// The value of `BodyLength <9>` doesn't match the actual length of the
+    /// message body.
+    #[error("`BodyLength <9>` is {declared}, but the body is {actual} bytes long.")]
+    BodyLength {
+        /// The value of `BodyLength <9>`.
+        declared: usize,
+        /// The actual length of the message body.
+        actual: usize,
+    },
+    /// The value of `CheckSum <10>` doesn't match the actual checksum of the
+    /// message.
+    #[error("`CheckSum <10>` is {declared:03}, but the message adds up to {actual:03}.")]
+    CheckSum {
+        /// The value of `CheckSum <10>`.
+        declared: u8,
+        /// The actual checksum of the message.
+        actual: u8,
+    },
+    /// A field of an otherwise well-framed message is invalid.
+    #[error(
+        "{kind} at byte {offset}{}.",
+        .tag.map(|tag| format!(" (tag {})", tag)).unwrap_or_default()
+    )]
+    Field {
+        /// The position of the field within the message, starting from
+        /// `BeginString <8>`.
+        offset: usize,
+        /// The tag of the field, if valid.
+        tag: Option<u32>,
+        /// What's wrong with the field.
+        kind: FieldErrorKind,
+    },
     /// I/O error.
     #[error("I/O error: {0}")]
     IO(#[from] std::io::Error),
 }
+
+/// What's wrong with a field, as reported by [`DecodeError::Field`].
+///
+/// Each kind has a matching `SessionRejectReason <373>`, see
+/// [`SessionRejectReason::for_decode_error`](crate::session::SessionRejectReason::for_decode_error).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FieldErrorKind {
+    /// The tag isn't a positive integer.
+    InvalidTagNumber,
+    /// The field has an empty value.
+    TagWithoutValue,
+    /// The value doesn't make sense for the tag, e.g. a `NumInGroup` or
+    /// `Length` field that isn't a number.
+    ValueIsIncorrect,
+    /// The tag appears more than once outside of repeating groups.
+    TagAppearsMoreThanOnce,
+    /// The tag appears more than once within the same repeating group entry,
+    /// i.e. the fields of the group are out of order.
+    GroupFieldsOutOfOrder,
+    /// A repeating group has more entries than its `NumInGroup` field says.
+    IncorrectNumInGroupCount,
+}
+
+impl fmt::Display for FieldErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidTagNumber => "Invalid tag number",
+            Self::TagWithoutValue => "Tag specified without a value",
+            Self::ValueIsIncorrect => "Incorrect value",
+            Self::TagAppearsMoreThanOnce => "Tag appears more than once",
+            Self::GroupFieldsOutOfOrder => "Repeating group fields out of order",
+            Self::IncorrectNumInGroupCount => "Incorrect NumInGroup count for repeating group",
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use crate::tagvalue::{utils, Config, DecodeError};
+use crate::tagvalue::{utils, Config, DecodeError, FieldErrorKind};
 use crate::{Buffer, GetConfig, StreamingDecoder};
 use std::ops::Range;
 
@@ -127,6 +127,9 @@ impl RawDecoder {
 
         let header_info =
             HeaderInfo::parse(data, self.config().separator).ok_or(DecodeError::Invalid)?;
+        if !utils::has_trailer(data, self.config().separator) {
+            return Err(DecodeError::Invalid);
+        }
 
         utils::verify_body_length(
             data,
@@ -161,6 +164,7 @@ impl<C> GetConfig for RawDecoder<C> {
 #[derive(Debug)]
 enum ParserState {
     Empty,
+    PartialHeader(usize),
     Header(HeaderInfo, usize),
     Failed,
 }
@@ -192,6 +196,7 @@ where
     fn num_bytes_required(&self) -> usize {
         match self.state {
             ParserState::Empty => utils::MIN_FIX_MESSAGE_LEN_IN_BYTES,
+            ParserState::PartialHeader(expected_len) => expected_len,
             ParserState::Header(_, expected_len) => expected_len,
             ParserState::Failed => 0,
         }
@@ -199,22 +204,43 @@ where
 
     fn try_parse(&mut self) -> Result<Option<()>, Self::Error> {
         match self.state {
-            ParserState::Empty => {
+            ParserState::Empty | ParserState::PartialHeader(_) => {
                 let header_info =
                     HeaderInfo::parse(self.buffer.as_slice(), self.config().separator);
                 if let Some(header_info) = header_info {
-                    let expected_len_of_frame = header_info.field_1.end
-                        + 1
-                        + header_info.nominal_body_len
-                        + utils::FIELD_CHECKSUM_LEN_IN_BYTES;
+                    let expected_len_of_frame = (header_info.field_1.end + 1)
+                        .checked_add(header_info.nominal_body_len)
+                        .and_then(|len| len.checked_add(utils::FIELD_CHECKSUM_LEN_IN_BYTES))
+                        .ok_or(DecodeError::Field {
+                            offset: header_info.field_0.end + 1,
+                            tag: Some(9),
+                            kind: FieldErrorKind::ValueIsIncorrect,
+                        })?;
 
                     self.state = ParserState::Header(header_info, expected_len_of_frame);
                     Ok(None)
+                } else if self.buffer.len() < utils::MAX_HEADER_LEN_IN_BYTES
+                    && self.buffer.as_slice().starts_with(b"8=")
+                {
+                    // `BeginString <8>` and `BodyLength <9>` can be longer
+                    // than the minimum message length; ask for more bytes.
+                    self.state = ParserState::PartialHeader(self.buffer.len() + 1);
+                    Ok(None)
                 } else {
                     Err(DecodeError::Invalid)
                 }
             }
-            ParserState::Header(_, _) => Ok(Some(())),
+            ParserState::Header(ref header_info, _) => {
+                let data = self.buffer.as_slice();
+                let separator = self.config.separator;
+                if !utils::has_trailer(data, separator) {
+                    return Err(body_length_error(data, header_info, separator));
+                }
+                if self.config.verify_checksum && separator == b'\x01' {
+                    utils::verify_checksum(data)?;
+                }
+                Ok(Some(()))
+            }
             ParserState::Failed => panic!("Failed state"),
         }
     }
@@ -255,6 +281,22 @@ impl<B, C> GetConfig for RawDecoderStreaming<B, C> {
     }
 }
 
+/// Returns the error of a frame whose `CheckSum <10>` isn't where its
+/// `BodyLength <9>` says. The actual body length can only be found if the
+/// `CheckSum <10>` field starts within the frame; otherwise the frame is
+/// simply [`DecodeError::Invalid`].
+fn body_length_error(data: &[u8], header_info: &HeaderInfo, separator: u8) -> DecodeError {
+    let start_of_body = header_info.field_1.end + 1;
+    let trailer = [separator, b'1', b'0', b'='];
+    data[start_of_body.saturating_sub(1)..]
+        .windows(trailer.len())
+        .position(|window| window == trailer)
+        .map_or(DecodeError::Invalid, |i| DecodeError::BodyLength {
+            declared: header_info.nominal_body_len,
+            actual: i,
+        })
+}
+
 #[derive(Debug, Clone)]
 struct HeaderInfo {
     field_0: Range<usize>,
@@ -270,6 +312,9 @@ impl HeaderInfo {
             nominal_body_len: 0,
         };
 
+        if !data.starts_with(b"8=") {
+            return None;
+        }
         let mut iterator = data.iter();
         let mut find_byte = |byte| iterator.position(|b| *b == byte);
         let mut i = 0;
@@ -285,11 +330,18 @@ impl HeaderInfo {
         i += find_byte(separator)?;
         info.field_1.end = i;
 
-        for byte in &data[info.field_1.clone()] {
+        let body_length = &data[info.field_1.clone()];
+        if &data[info.field_0.end + 1..info.field_1.start] != b"9="
+            || body_length.is_empty()
+            || !body_length.iter().all(u8::is_ascii_digit)
+        {
+            return None;
+        }
+        for byte in body_length {
             info.nominal_body_len = info
                 .nominal_body_len
-                .wrapping_mul(10)
-                .wrapping_add(byte.wrapping_sub(b'0') as usize);
+                .saturating_mul(10)
+                .saturating_add((byte - b'0') as usize);
         }
 
         Some(info)
@@ -350,7 +402,13 @@ mod test {
         decoder.config_mut().verify_checksum = true;
         let msg =
             "8=FIX.4.2|9=40|35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|10=000|".replace('|', "\u{01}");
-        assert!(matches!(decoder.decode(&msg), Err(DecodeError::CheckSum)));
+        assert!(matches!(
+            decoder.decode(&msg),
+            Err(DecodeError::CheckSum {
+                declared: 0,
+                actual: 254
+            })
+        ));
     }
 
     #[test]
@@ -400,4 +458,97 @@ mod test {
             b"35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|"
         );
     }
+
+    fn streaming_decode(stream: &[u8]) -> Result<Option<()>, DecodeError> {
+        let mut decoder = new_decoder().streaming(vec![]);
+        decoder.config_mut().separator = 0x01;
+        decoder.config_mut().verify_checksum = true;
+        let mut i = 0;
+        loop {
+            let buf = decoder.fillable();
+            buf.clone_from_slice(&stream[i..i + buf.len()]);
+            i += buf.len();
+            match decoder.try_parse() {
+                Ok(None) => {}
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_decoder_verifies_checksum() {
+        let msg =
+            "8=FIX.4.2|9=40|35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|10=000|".replace('|', "\u{01}");
+        assert!(matches!(
+            streaming_decode(msg.as_bytes()),
+            Err(DecodeError::CheckSum {
+                declared: 0,
+                actual: 254
+            })
+        ));
+    }
+
+    #[test]
+    fn streaming_decoder_verifies_body_length() {
+        let stream = "8=FIX.4.2|9=42|35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|10=254|8=FIX.4.2|"
+            .replace('|', "\u{01}");
+        assert!(matches!(
+            streaming_decode(stream.as_bytes()),
+            Err(DecodeError::BodyLength {
+                declared: 42,
+                actual: 40
+            })
+        ));
+        let stream =
+            "8=FIX.4.2|9=30|35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|10=254|".replace('|', "\u{01}");
+        assert!(matches!(
+            streaming_decode(stream.as_bytes()),
+            Err(DecodeError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn streaming_decoder_with_overflowing_body_length() {
+        let stream = b"8=FIX.4.4|9=18446744073709551615|35=D|10=000|";
+        let mut decoder = new_decoder().streaming(vec![]);
+        let mut i = 0;
+        let result = loop {
+            let buf = decoder.fillable();
+            buf.clone_from_slice(&stream[i..i + buf.len()]);
+            i += buf.len();
+            match decoder.try_parse() {
+                Ok(None) => {}
+                result => break result,
+            }
+        };
+        assert!(matches!(
+            result,
+            Err(DecodeError::Field {
+                offset: 10,
+                tag: Some(9),
+                kind: FieldErrorKind::ValueIsIncorrect,
+            })
+        ));
+    }
+
+    #[test]
+    fn streaming_decoder_with_zero_padded_body_length() {
+        let stream = b"8=FIX.4.4|9=00000040|35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|10=091|";
+        let mut i = 0;
+        let mut decoder = new_decoder().streaming(vec![]);
+        loop {
+            let buf = decoder.fillable();
+            buf.clone_from_slice(&stream[i..i + buf.len()]);
+            i += buf.len();
+            if decoder.try_parse().unwrap().is_some() {
+                break;
+            }
+        }
+        assert_eq!(i, stream.len());
+        assert_eq!(decoder.raw_frame().begin_string(), b"FIX.4.4");
+        assert_eq!(
+            decoder.raw_frame().payload(),
+            b"35=D|49=AFUNDMGR|56=ABROKER|15=USD|59=0|"
+        );
+    }
 }
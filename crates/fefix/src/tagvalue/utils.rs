@@ -1,5 +1,5 @@
 use crate::field_types::CheckSum;
-use crate::tagvalue::DecodeError;
+use crate::tagvalue::{DecodeError, FieldErrorKind};
 use crate::FieldType;
 use std::convert::TryInto;
 
@@ -8,6 +8,10 @@ use std::convert::TryInto;
 //   8=?|9=?|35=?|10=???|
 pub const MIN_FIX_MESSAGE_LEN_IN_BYTES: usize = 20;
 
+// Upper bound on the length of `BeginString <8>` and `BodyLength <9>`, taken
+// together. Streaming decoders give up on headers longer than this.
+pub const MAX_HEADER_LEN_IN_BYTES: usize = 64;
+
 /// The checksum field is composed of:
 ///  - `10=`       (3 characters)
 ///  - `XYZ`       (checksum value, always 3 characters)
@@ -24,17 +28,35 @@ pub fn checksum_digits(message: &[u8]) -> [u8; 3] {
 }
 
 pub fn verify_checksum(headerless_msg: &[u8]) -> Result<(), DecodeError> {
-    let msg_contents = &headerless_msg[..headerless_msg.len() - FIELD_CHECKSUM_LEN_IN_BYTES];
+    let len_before_checksum = headerless_msg.len() - FIELD_CHECKSUM_LEN_IN_BYTES;
+    let msg_contents = &headerless_msg[..len_before_checksum];
     let nominal_checksum = CheckSum::deserialize_lossy(&checksum_digits(headerless_msg)[..])
-        .map_err(|_| DecodeError::CheckSum)?;
+        .map_err(|_| DecodeError::Field {
+            offset: len_before_checksum,
+            tag: Some(10),
+            kind: FieldErrorKind::ValueIsIncorrect,
+        })?;
     let actual_checksum = CheckSum::compute(msg_contents);
     if nominal_checksum == actual_checksum {
         Ok(())
     } else {
-        Err(DecodeError::CheckSum)
+        Err(DecodeError::CheckSum {
+            declared: nominal_checksum.0,
+            actual: actual_checksum.0,
+        })
     }
 }
 
+/// Returns `true` if `data` ends with a `CheckSum(10)` field and its
+/// separator.
+pub fn has_trailer(data: &[u8], separator: u8) -> bool {
+    let len = data.len();
+    len > FIELD_CHECKSUM_LEN_IN_BYTES
+        && data[len - FIELD_CHECKSUM_LEN_IN_BYTES - 1] == separator
+        && &data[len - FIELD_CHECKSUM_LEN_IN_BYTES..][..3] == b"10="
+        && data[len - 1] == separator
+}
+
 /// Verifies the `BodyLength(9)` field of the FIX message in `data`.
 pub fn verify_body_length(
     data: &[u8],
@@ -46,8 +68,13 @@ pub fn verify_body_length(
         .wrapping_sub(FIELD_CHECKSUM_LEN_IN_BYTES)
         .wrapping_sub(start_of_body);
     let end_of_body = data.len() - FIELD_CHECKSUM_LEN_IN_BYTES;
-    if start_of_body > end_of_body || nominal_body_length != body_length {
+    if start_of_body > end_of_body {
         Err(DecodeError::Invalid)
+    } else if nominal_body_length != body_length {
+        Err(DecodeError::BodyLength {
+            declared: nominal_body_length,
+            actual: body_length,
+        })
     } else {
         debug_assert!(body_length < data.len());
         Ok(())
@@ -69,4 +96,13 @@ mod test {
             b"ABC"
         );
     }
+
+    #[test]
+    fn malformed_checksum_is_a_mismatch() {
+        let msg = b"8=FIX.4.4\x019=5\x0135=0\x0110=1x3\x01";
+        assert!(matches!(
+            verify_checksum(msg),
+            Err(DecodeError::CheckSum { .. })
+        ));
+    }
 }
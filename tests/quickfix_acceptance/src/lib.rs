@@ -0,0 +1,475 @@
+//! Runs QuickFIX acceptance-test definitions (`.def` scripts) against the
+//! fefix session acceptor, over an in-memory transport.
+//!
+//! Each line of a definition is a step:
+//!
+//! - `iCONNECT` opens a new connection, `iDISCONNECT` closes it and
+//!   `eDISCONNECT` expects fefix to close it.
+//! - `I...` sends a message. `<TIME>`, `<TIME+n>` and `<TIME-n>` are replaced
+//!   with the current UTC time, give or take `n` seconds. `BodyLength <9>` and
+//!   `CheckSum <10>` are filled in, unless `CheckSum <10>` is there already.
+//! - `E...` expects a message with the same fields, ignoring
+//!   `BodyLength <9>`, `CheckSum <10>`, `SendingTime <52>`,
+//!   `OrigSendingTime <122>` and any `<...>` placeholder values.
+//! - Empty lines and lines starting with `#` are ignored.
+//!
+//! Steps may be prefixed with the number of their connection, e.g.
+//! `I1,8=FIX.4.4...`. Only connection `1` is supported: scripts with more
+//! than one concurrent connection are reported as unsupported and skipped.
+//!
+//! Fields are separated by SOH, like in QuickFIX's own suite. The scripted
+//! counterparty is `TW`, fefix is `ISLD`.
+//!
+//! The bundled `definitions/fix44` are a handful of hand-written scripts in
+//! QuickFIX's format, covering logon, seq. number checks and `TestRequest
+//! <1>`; they are not QuickFIX's acceptance suite, which isn't vendored. Set
+//! `QUICKFIX_DEFINITIONS` to a directory of `.def` files, e.g. QuickFIX's
+//! `test/definitions/server/fix44`, and run the ignored tests with `cargo
+//! test -- --ignored` to run against it as well: every supported script must
+//! then pass, except for [`KNOWN_FAILURES`].
+
+use chrono::{Duration, Utc};
+use fefix::session::testing::{duplex, Script, ScriptError};
+use fefix::session::{Backend, Config, FixConnection};
+use fefix::tagvalue::{Decoder, Encoder, Message};
+use fefix::{Dictionary, SetField};
+use futures::future::join;
+use futures::AsyncReadExt;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+const SOH: char = '\x01';
+const SENDER_COMP_ID: &str = "ISLD";
+const TARGET_COMP_ID: &str = "TW";
+const IGNORED_TAGS: &[u32] = &[9, 10, 52, 122];
+
+/// The names of the QuickFIX definitions that are known to fail.
+pub const KNOWN_FAILURES: &[&str] = &[];
+
+/// A step of a [`Definition`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// `iCONNECT`.
+    Connect,
+    /// `iDISCONNECT`.
+    Disconnect,
+    /// `eDISCONNECT`.
+    ExpectDisconnect,
+    /// `I...`, with placeholders still in place.
+    Send(Vec<(u32, String)>),
+    /// `E...`.
+    Expect(Vec<(u32, String)>),
+}
+
+/// A parsed `.def` script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    /// The name of the script, e.g. its file name.
+    pub name: String,
+    /// The steps of the script, in order.
+    pub steps: Vec<Step>,
+}
+
+/// The type returned in the event of an invalid or unsupported [`Definition`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DefinitionError {
+    /// The line isn't a step that this runner knows of.
+    #[error("Line {line}: unsupported step {step:?}.")]
+    Unsupported {
+        /// The line number, starting from one.
+        line: usize,
+        /// The offending step.
+        step: String,
+    },
+    /// The line has a malformed field.
+    #[error("Line {line}: invalid field {field:?}.")]
+    InvalidField {
+        /// The line number, starting from one.
+        line: usize,
+        /// The offending field.
+        field: String,
+    },
+}
+
+impl Definition {
+    /// Parses the `.def` script `text`.
+    pub fn parse(name: &str, text: &str) -> Result<Self, DefinitionError> {
+        let mut steps = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // Steps can be prefixed with a connection number, e.g.
+            // `I1,8=FIX.4.4...`. Only the first connection is supported.
+            let (kind, rest) = line.split_at(1);
+            let unnumbered = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+            let connection = &rest[..rest.len() - unnumbered.len()];
+            let rest = match unnumbered.strip_prefix(',') {
+                Some(_) if connection != "1" => {
+                    return Err(DefinitionError::Unsupported {
+                        line: i + 1,
+                        step: line.to_string(),
+                    })
+                }
+                Some(rest) => rest,
+                None if kind == "i" || kind == "e" => unnumbered,
+                None => rest,
+            };
+            let step = match (kind, rest) {
+                ("i", "CONNECT") => Step::Connect,
+                ("i", "DISCONNECT") => Step::Disconnect,
+                ("e", "DISCONNECT") => Step::ExpectDisconnect,
+                ("I", fields) => Step::Send(parse_fields(i + 1, fields)?),
+                ("E", fields) => Step::Expect(parse_fields(i + 1, fields)?),
+                _ => {
+                    return Err(DefinitionError::Unsupported {
+                        line: i + 1,
+                        step: line.to_string(),
+                    })
+                }
+            };
+            steps.push(step);
+        }
+        Ok(Self {
+            name: name.to_string(),
+            steps,
+        })
+    }
+
+    /// Runs `self` against a fresh fefix acceptor. Every `iCONNECT` starts a
+    /// new connection to the same FIX session.
+    pub fn run(&self) -> Result<(), ScriptError> {
+        let mut conn = FixConnection::new(self.config(), NullBackend);
+        for connection in self.steps.split(|step| *step == Step::Connect) {
+            if connection.is_empty() {
+                continue;
+            }
+            let script = script(conn.config(), connection);
+            let (stream, counterparty) = duplex(1 << 16);
+            let (input, output) = stream.split();
+            let decoder = Decoder::new(Dictionary::fix44()).streaming(vec![]);
+            // Failed connections are fine, as long as the script expected it.
+            let (_, result) = futures::executor::block_on(join(
+                conn.accept(input, output, decoder),
+                script.run(counterparty),
+            ));
+            result?;
+        }
+        Ok(())
+    }
+
+    /// The acceptor's configuration, after the first inbound `Logon <A>`.
+    fn config(&self) -> Config {
+        let mut config = Config::default();
+        config.sender_comp_id = SENDER_COMP_ID.to_string();
+        config.target_comp_id = TARGET_COMP_ID.to_string();
+        let logon = self.steps.iter().find_map(|step| match step {
+            Step::Send(fields) if value(fields, 35) == Some("A") => Some(fields),
+            _ => None,
+        });
+        if let Some(fields) = logon {
+            if let Some(begin_string) = value(fields, 8) {
+                config.begin_string = begin_string.to_string();
+            }
+            if let Some(heartbeat) = value(fields, 108).and_then(|s| s.parse().ok()) {
+                config.heartbeat = std::time::Duration::from_secs(heartbeat);
+            }
+        }
+        config
+    }
+}
+
+fn parse_fields(line: usize, fields: &str) -> Result<Vec<(u32, String)>, DefinitionError> {
+    fields
+        .split(SOH)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            field
+                .split_once('=')
+                .and_then(|(tag, value)| Some((tag.parse().ok()?, value.to_string())))
+                .ok_or_else(|| DefinitionError::InvalidField {
+                    line,
+                    field: field.to_string(),
+                })
+        })
+        .collect()
+}
+
+fn value(fields: &[(u32, String)], tag: u32) -> Option<&str> {
+    fields
+        .iter()
+        .find(|(t, _)| *t == tag)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Translates the steps of a single connection into a [`Script`].
+fn script(config: &Config, steps: &[Step]) -> Script {
+    let mut script = Script::new(config);
+    for step in steps {
+        script = match step {
+            Step::Connect => script,
+            Step::Disconnect => script.disconnect(),
+            Step::ExpectDisconnect => script.expect_disconnect(),
+            Step::Send(fields) => script.send_raw(&encode(fields)),
+            Step::Expect(fields) => {
+                let msg_type = value(fields, 35).unwrap_or_default();
+                let fields = fields
+                    .iter()
+                    .filter(|(tag, value)| {
+                        *tag != 35 && !IGNORED_TAGS.contains(tag) && !value.starts_with('<')
+                    })
+                    .map(|(tag, value)| (*tag, value.as_str()))
+                    .collect::<Vec<_>>();
+                script.expect_fields(msg_type, &fields)
+            }
+        };
+    }
+    script
+}
+
+/// Replaces `<TIME>` placeholders in `value`.
+fn substitute(value: &str) -> String {
+    let offset = match value
+        .strip_prefix("<TIME")
+        .and_then(|s| s.strip_suffix('>'))
+    {
+        Some("") => 0,
+        Some(offset) => match offset.trim_start_matches('+').parse() {
+            Ok(offset) => offset,
+            Err(_) => return value.to_string(),
+        },
+        None => return value.to_string(),
+    };
+    (Utc::now() + Duration::seconds(offset))
+        .format("%Y%m%d-%H:%M:%S")
+        .to_string()
+}
+
+/// Serializes an `I...` step, filling in `BodyLength <9>` and `CheckSum <10>`
+/// unless `CheckSum <10>` is given.
+fn encode(fields: &[(u32, String)]) -> Vec<u8> {
+    let fields = fields
+        .iter()
+        .map(|(tag, value)| (*tag, substitute(value)))
+        .collect::<Vec<_>>();
+    if value(&fields, 10).is_some() {
+        return fields
+            .iter()
+            .flat_map(|(tag, value)| format!("{}={}{}", tag, value, SOH).into_bytes())
+            .collect();
+    }
+    let mut buffer = Vec::new();
+    let mut encoder = Encoder::new();
+    let begin_string = value(&fields, 8).unwrap_or_default();
+    let msg_type = value(&fields, 35).unwrap_or_default();
+    let mut msg = encoder.start_message(begin_string.as_bytes(), &mut buffer, msg_type.as_bytes());
+    for (tag, value) in &fields {
+        if ![8, 9, 35].contains(tag) {
+            msg.set(*tag, value.as_str());
+        }
+    }
+    msg.done();
+    buffer
+}
+
+/// The outcome of [`run_dir`].
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    /// The names of the passed definitions.
+    pub passed: Vec<String>,
+    /// The names of the failed definitions, with the reason why.
+    pub failed: Vec<(String, String)>,
+    /// The names of the unsupported definitions, with the reason why.
+    pub skipped: Vec<(String, String)>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.passed.len() + self.failed.len() + self.skipped.len();
+        writeln!(
+            f,
+            "{}/{} passed, {} failed, {} skipped",
+            self.passed.len(),
+            total,
+            self.failed.len(),
+            self.skipped.len()
+        )?;
+        for (name, reason) in &self.failed {
+            writeln!(f, "FAILED  {}: {}", name, reason)?;
+        }
+        for (name, reason) in &self.skipped {
+            writeln!(f, "SKIPPED {}: {}", name, reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs all `.def` scripts in `dir`, in alphabetical order.
+pub fn run_dir(dir: impl AsRef<Path>) -> io::Result<Summary> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.extension().map_or(false, |ext| ext == "def"));
+    paths.sort();
+    let mut summary = Summary::default();
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let text = String::from_utf8_lossy(&fs::read(&path)?).into_owned();
+        match Definition::parse(&name, &text) {
+            Ok(definition) => match definition.run() {
+                Ok(()) => summary.passed.push(name),
+                Err(err) => summary.failed.push((name, err.to_string())),
+            },
+            Err(err) => summary.skipped.push((name, err.to_string())),
+        }
+    }
+    Ok(summary)
+}
+
+/// The acceptor's [`Backend`]. Application messages are dropped.
+#[derive(Debug, Clone, Default)]
+struct NullBackend;
+
+impl Backend for NullBackend {
+    type Error = u32;
+
+    fn sender_comp_id(&self) -> &[u8] {
+        SENDER_COMP_ID.as_bytes()
+    }
+
+    fn target_comp_id(&self) -> &[u8] {
+        TARGET_COMP_ID.as_bytes()
+    }
+
+    fn on_inbound_app_message(&mut self, _message: Message<&[u8]>) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn on_outbound_message(&mut self, _message: &[u8]) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn on_resend_request(&mut self, _range: Range<u64>) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn on_successful_handshake(&mut self) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn fetch_messages(&mut self) -> Result<&[&[u8]], u32> {
+        Ok(&[])
+    }
+
+    fn pending_message(&mut self) -> Option<&[u8]> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn definitions_are_parsed() {
+        let text = "# Logon\niCONNECT\nI8=FIX.4.4\x0135=A\x0134=1\x0152=<TIME>\x01\n\
+            E1,8=FIX.4.4\x019=57\x0135=A\x0110=0\x01\neDISCONNECT\n";
+        let definition = Definition::parse("1a.def", text).unwrap();
+        assert_eq!(
+            definition.steps,
+            vec![
+                Step::Connect,
+                Step::Send(vec![
+                    (8, "FIX.4.4".to_string()),
+                    (35, "A".to_string()),
+                    (34, "1".to_string()),
+                    (52, "<TIME>".to_string()),
+                ]),
+                Step::Expect(vec![
+                    (8, "FIX.4.4".to_string()),
+                    (9, "57".to_string()),
+                    (35, "A".to_string()),
+                    (10, "0".to_string()),
+                ]),
+                Step::ExpectDisconnect,
+            ]
+        );
+        assert_eq!(
+            Definition::parse("x.def", "iSETSESSIONTIME\n"),
+            Err(DefinitionError::Unsupported {
+                line: 1,
+                step: "iSETSESSIONTIME".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn time_placeholders_are_substituted() {
+        let fields = vec![
+            (8, "FIX.4.4".to_string()),
+            (35, "0".to_string()),
+            (52, "<TIME-5>".to_string()),
+        ];
+        let msg = String::from_utf8(encode(&fields)).unwrap();
+        assert!(msg.starts_with("8=FIX.4.4\x019="));
+        assert!(!msg.contains('<'));
+        assert!(msg.contains("\x0110="));
+    }
+
+    #[test]
+    fn mismatches_fail_the_definition() {
+        let text = "iCONNECT\n\
+            I8=FIX.4.4\x0135=A\x0134=1\x0149=TW\x0152=<TIME>\x0156=ISLD\x0198=0\x01108=30\x01\n\
+            E8=FIX.4.4\x0135=A\x0134=1\x0149=ISLD\x0156=TW\x0198=0\x01108=60\x01\n";
+        let definition = Definition::parse("mismatch.def", text).unwrap();
+        assert!(matches!(
+            definition.run(),
+            Err(ScriptError::UnexpectedMessage { step: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn bundled_definitions_pass() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("definitions/fix44");
+        let summary = run_dir(dir).unwrap();
+        assert!(
+            summary.failed.is_empty() && summary.skipped.is_empty(),
+            "{}",
+            summary
+        );
+        assert!(!summary.passed.is_empty());
+    }
+
+    #[test]
+    fn multiple_connections_are_unsupported() {
+        let text = "i1,CONNECT\ni2,CONNECT\nI2,8=FIX.4.4\x0135=A\x01\n";
+        assert_eq!(
+            Definition::parse("multi.def", text),
+            Err(DefinitionError::Unsupported {
+                line: 2,
+                step: "i2,CONNECT".to_string()
+            })
+        );
+    }
+
+    #[test]
+    #[ignore = "needs QUICKFIX_DEFINITIONS"]
+    fn quickfix_definitions() {
+        let dir = std::env::var_os("QUICKFIX_DEFINITIONS")
+            .expect("QUICKFIX_DEFINITIONS must point to a directory of .def files");
+        let summary = run_dir(dir).unwrap();
+        let unexpected: Vec<_> = summary
+            .failed
+            .iter()
+            .filter(|(name, _)| !KNOWN_FAILURES.contains(&name.as_str()))
+            .collect();
+        assert!(unexpected.is_empty(), "{}", summary);
+    }
+}